@@ -0,0 +1,42 @@
+//! Compares parsing a large calendar with the minimal field mask against parsing everything.
+//!
+//! Run with `cargo bench --bench parse_mask`.
+
+use std::time::{Duration, Instant};
+
+use ur_connect::parsing::ics::{FieldMask, ParseOptions, parse_ics_with};
+
+fn sample_calendar(events: usize, description_len: usize) -> String {
+    let description =
+        "Dozent: Prof. Dr. Beispiel; Hinweise zur Veranstaltung. ".repeat(description_len / 56 + 1);
+    let mut ics = String::from("BEGIN:VCALENDAR\nVERSION:2.0\n");
+    for i in 0..events {
+        ics.push_str(&format!(
+            "BEGIN:VEVENT\nUID:event-{i}@ur.de\nSUMMARY:Vorlesung {i}\nDESCRIPTION:{description}\nLOCATION:H {room}\nDTSTART:20241001T{hour:02}0000Z\nDTEND:20241001T{end:02}0000Z\nRRULE:FREQ=WEEKLY\nX-FOO:bar\nEND:VEVENT\n",
+            room = i % 20,
+            hour = 8 + i % 8,
+            end = 9 + i % 8,
+        ));
+    }
+    ics.push_str("END:VCALENDAR\n");
+    ics
+}
+
+fn best_of(runs: usize, ics: &str, options: &ParseOptions) -> Duration {
+    (0..runs)
+        .map(|_| {
+            let started = Instant::now();
+            std::hint::black_box(parse_ics_with(ics, options));
+            started.elapsed()
+        })
+        .min()
+        .unwrap()
+}
+
+fn main() {
+    let ics = sample_calendar(1_000, 2_000);
+    let minimal = ParseOptions::default().fields(FieldMask::STABLE_ID);
+    let everything = ParseOptions::default().fields(FieldMask::all());
+    println!("minimal mask: {:?}", best_of(10, &ics, &minimal));
+    println!("all fields:   {:?}", best_of(10, &ics, &everything));
+}
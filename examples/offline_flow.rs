@@ -0,0 +1,41 @@
+//! Runs the full login and timetable flow against the in-process fixture portal.
+//!
+//! ```sh
+//! cargo run --example offline_flow --features test-util
+//! ```
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use ur_connect::{
+    TimetableEntry, UrConnect,
+    testing::{FIXTURE_PASSWORD, FIXTURE_USERNAME, FixturePortal},
+};
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let portal = Arc::new(FixturePortal::new("https://portal.invalid"));
+    let client = UrConnect::with_transport(portal.config(), portal.clone())?;
+
+    client.login(FIXTURE_USERNAME, FIXTURE_PASSWORD).await?;
+    let entries = client.get_timetable().await?;
+
+    let mut days: Vec<(&str, Vec<TimetableEntry>)> = Vec::new();
+    for entry in &entries {
+        match days.iter_mut().find(|(date, _)| *date == entry.date) {
+            Some((_, day)) => day.push(entry.clone()),
+            None => days.push((entry.date.as_str(), vec![entry.clone()])),
+        }
+    }
+    for (date, day) in days {
+        println!("{date}");
+        println!("{}", UrConnect::format_entries(&day));
+        println!();
+    }
+
+    println!(
+        "{} requests served by the fixture portal",
+        portal.requests().len()
+    );
+    Ok(())
+}
@@ -0,0 +1,148 @@
+//! A synchronous client for programs without an async runtime (`blocking` feature).
+//!
+//! [`UrConnect`] wraps the async [`crate::UrConnect`] and drives it on a current-thread
+//! Tokio runtime of its own, so navigation and parsing are the same code on both paths.
+//! Its methods block the calling thread and must not be called from within an async runtime.
+
+use reqwest::Url;
+use tokio::runtime::{Builder, Runtime};
+
+use crate::{
+    client::{FlowPage, TimetableResult},
+    config::PortalConfig,
+    error::UrError,
+    model::{SemesterId, TimetableEntry},
+    session::{Credentials, SessionState},
+};
+
+/// The blocking counterpart of [`crate::UrConnect`].
+pub struct UrConnect {
+    inner: crate::UrConnect,
+    runtime: Runtime,
+}
+
+impl UrConnect {
+    pub fn new() -> Result<Self, UrError> {
+        Self::from_async(crate::UrConnect::new()?)
+    }
+
+    pub fn with_config(config: PortalConfig) -> Result<Self, UrError> {
+        Self::from_async(crate::UrConnect::with_config(config)?)
+    }
+
+    /// Wraps an async client, e.g. one set up with [`crate::UrConnect::builder`].
+    pub fn from_async(inner: crate::UrConnect) -> Result<Self, UrError> {
+        let runtime = Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(|err| UrError::Transport {
+                context: "failed to start the runtime of the blocking client".to_string(),
+                source: err.into(),
+            })?;
+        Ok(Self { inner, runtime })
+    }
+
+    /// The wrapped async client.
+    pub fn as_async(&self) -> &crate::UrConnect {
+        &self.inner
+    }
+
+    /// See [`crate::UrConnect::login`].
+    pub fn login(&self, username: &str, password: &str) -> Result<(), UrError> {
+        self.runtime.block_on(self.inner.login(username, password))
+    }
+
+    /// See [`crate::UrConnect::login_with_credentials`].
+    pub fn login_with_credentials(&self, credentials: &Credentials) -> Result<(), UrError> {
+        self.runtime
+            .block_on(self.inner.login_with_credentials(credentials))
+    }
+
+    /// See [`crate::UrConnect::login_with_otp`].
+    pub fn login_with_otp(&self, username: &str, password: &str, otp: &str) -> Result<(), UrError> {
+        self.runtime
+            .block_on(self.inner.login_with_otp(username, password, otp))
+    }
+
+    /// See [`crate::UrConnect::logout`].
+    pub fn logout(&self) -> Result<(), UrError> {
+        self.runtime.block_on(self.inner.logout())
+    }
+
+    /// See [`crate::UrConnect::is_session_valid`].
+    pub fn is_session_valid(&self) -> Result<bool, UrError> {
+        self.runtime.block_on(self.inner.is_session_valid())
+    }
+
+    /// See [`crate::UrConnect::is_logged_in`].
+    pub fn is_logged_in(&self) -> Result<bool, UrError> {
+        self.runtime.block_on(self.inner.is_logged_in())
+    }
+
+    /// See [`crate::UrConnect::refresh_session`].
+    pub fn refresh_session(&self) -> Result<(), UrError> {
+        self.runtime.block_on(self.inner.refresh_session())
+    }
+
+    /// See [`crate::UrConnect::get_timetable`].
+    pub fn get_timetable(&self) -> Result<Vec<TimetableEntry>, UrError> {
+        self.runtime.block_on(self.inner.get_timetable())
+    }
+
+    /// See [`crate::UrConnect::get_timetable_detailed`].
+    pub fn get_timetable_detailed(&self) -> Result<TimetableResult, UrError> {
+        self.runtime.block_on(self.inner.get_timetable_detailed())
+    }
+
+    /// See [`crate::UrConnect::get_timetable_for_semester`].
+    pub fn get_timetable_for_semester(
+        &self,
+        semester: SemesterId,
+    ) -> Result<Vec<TimetableEntry>, UrError> {
+        self.runtime
+            .block_on(self.inner.get_timetable_for_semester(semester))
+    }
+
+    /// See [`crate::UrConnect::list_semesters`].
+    pub fn list_semesters(&self) -> Result<Vec<SemesterId>, UrError> {
+        self.runtime.block_on(self.inner.list_semesters())
+    }
+
+    /// See [`crate::UrConnect::navigate_flow`].
+    pub fn navigate_flow(&self, flow_id: &str) -> Result<FlowPage, UrError> {
+        self.runtime.block_on(self.inner.navigate_flow(flow_id))
+    }
+
+    /// See [`crate::UrConnect::invalidate_cache`].
+    pub fn invalidate_cache(&self) {
+        self.inner.invalidate_cache();
+    }
+
+    /// See [`crate::UrConnect::get_timetable_cached`].
+    pub fn get_timetable_cached(&self) -> Result<Vec<TimetableEntry>, UrError> {
+        self.runtime.block_on(self.inner.get_timetable_cached())
+    }
+
+    /// See [`crate::UrConnect::get_ics_url`].
+    pub fn get_ics_url(&self) -> Result<Url, UrError> {
+        self.runtime.block_on(self.inner.get_ics_url())
+    }
+
+    /// See [`crate::UrConnect::get_raw_ics`].
+    pub fn get_raw_ics(&self) -> Result<String, UrError> {
+        self.runtime.block_on(self.inner.get_raw_ics())
+    }
+
+    /// See [`crate::UrConnect::download_ics`].
+    pub fn download_ics(&self) -> Result<String, UrError> {
+        self.runtime.block_on(self.inner.download_ics())
+    }
+
+    pub fn export_session(&self) -> SessionState {
+        self.inner.export_session()
+    }
+
+    pub fn format_entries(entries: &[TimetableEntry]) -> String {
+        crate::UrConnect::format_entries(entries)
+    }
+}
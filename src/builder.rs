@@ -0,0 +1,473 @@
+use std::{path::PathBuf, sync::Arc, time::Duration};
+
+use crate::{
+    client::{RequestInfo, RequestObserver, UrConnect},
+    config::{PortalConfig, ProxyConfig, RetryPolicy, UrConnectConfig},
+    error::UrError,
+    session::{Credentials, SessionState},
+    transport::HttpTransport,
+};
+
+/// Step-by-step construction of a [`UrConnect`]; anything left unset keeps the UR defaults.
+///
+/// URLs are validated in [`build`](Self::build), so a typo in a path fails there instead of on
+/// the first request.
+#[derive(Default)]
+pub struct UrConnectBuilder {
+    config: UrConnectConfig,
+    transport: Option<Arc<dyn HttpTransport>>,
+    debug_dump_dir: Option<PathBuf>,
+    capture_debug: bool,
+    request_observer: Option<RequestObserver>,
+    session: Option<SessionState>,
+    credentials: Option<Credentials>,
+    deadline: Option<Duration>,
+    cache_ttl: Option<Duration>,
+}
+
+impl UrConnectBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts from a complete configuration, e.g. one read with
+    /// [`UrConnectConfig::from_toml`](crate::config::UrConnectConfig).
+    pub fn from_config(config: UrConnectConfig) -> Self {
+        Self {
+            config,
+            ..Self::default()
+        }
+    }
+
+    /// The portal origin, e.g. `https://campusportal.ur.de`.
+    pub fn base_url(mut self, base_url: &str) -> Self {
+        self.config.portal.base_url = base_url.to_string();
+        self
+    }
+
+    pub fn start_page_path(mut self, path: &str) -> Self {
+        self.config.portal.start_page_path = path.to_string();
+        self
+    }
+
+    /// Where credentials are posted when the start page has no login form action.
+    pub fn login_path(mut self, path: &str) -> Self {
+        self.config.portal.login_path = path.to_string();
+        self
+    }
+
+    pub fn logout_path(mut self, path: &str) -> Self {
+        self.config.portal.logout_path = path.to_string();
+        self
+    }
+
+    /// The timetable flow page the `_flowId` and `_flowExecutionKey` are appended to.
+    pub fn timetable_path(mut self, path: &str) -> Self {
+        self.config.portal.timetable_path = path.to_string();
+        self
+    }
+
+    /// The timetable flow ids to try in order until the portal accepts one. Defaults to
+    /// [`DEFAULT_FLOW_IDS`](crate::config::DEFAULT_FLOW_IDS).
+    pub fn flow_ids(mut self, ids: &[&str]) -> Self {
+        self.config.portal.flow_ids = ids.iter().map(|id| id.to_string()).collect();
+        self
+    }
+
+    /// Replaces all portal locations at once.
+    pub fn portal(mut self, portal: PortalConfig) -> Self {
+        self.config.portal = portal;
+        self
+    }
+
+    /// The per-request timeout, rounded up to whole seconds. Defaults to 60 seconds.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.config.timeout_secs = whole_secs(timeout);
+        self
+    }
+
+    /// The timeout for establishing a connection, rounded up to whole seconds. Defaults to
+    /// 10 seconds.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.config.connect_timeout_secs = whole_secs(timeout);
+        self
+    }
+
+    /// The time a whole [`UrConnect::get_timetable`] (or
+    /// [`download_ics`](UrConnect::download_ics)) may take across all its requests; past it
+    /// the fetch fails with [`UrError::Timeout`] naming the step it was in. Unlimited by
+    /// default.
+    ///
+    /// Unlike the HTTP timeouts this also applies to a custom [`transport`](Self::transport).
+    /// It needs a Tokio runtime with the time driver enabled.
+    pub fn deadline(mut self, deadline: Duration) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Keeps the entries of a fetch for `ttl`, during which [`UrConnect::get_timetable`]
+    /// returns them without contacting the portal. Off by default; see also
+    /// [`UrConnect::invalidate_cache`].
+    pub fn cache_ttl(mut self, ttl: Duration) -> Self {
+        self.cache_ttl = Some(ttl);
+        self
+    }
+
+    /// How requests failing with a server error or a connection problem are repeated;
+    /// [`RetryPolicy::never`] turns retrying off. Also applies to a custom
+    /// [`transport`](Self::transport).
+    pub fn retry(mut self, policy: RetryPolicy) -> Self {
+        self.config.retry = policy;
+        self
+    }
+
+    /// Waits at least `interval` between two requests, e.g. when fetching the timetables of
+    /// several accounts in a row. The pause is shared by all tasks using the client and also
+    /// applies to a custom [`transport`](Self::transport). Off by default.
+    pub fn min_request_interval(mut self, interval: Duration) -> Self {
+        self.config.min_request_interval_ms =
+            u64::try_from(interval.as_millis()).unwrap_or(u64::MAX);
+        self
+    }
+
+    /// Sends every request, the calendar download included, through the proxy at `url`. A
+    /// proxy that rejects the request fails with [`UrError::ProxyAuthRequired`].
+    ///
+    /// HTTP(S) proxies always work; SOCKS5 ones (`socks5://`, or `socks5h://` to resolve host
+    /// names on the proxy) need the `socks` feature. A malformed URL fails in
+    /// [`build`](Self::build). The proxy replaces any named by `HTTPS_PROXY`, `HTTP_PROXY` or
+    /// `ALL_PROXY`, while hosts listed in `NO_PROXY` still bypass it unless
+    /// [`env_proxy`](Self::env_proxy) is off.
+    pub fn proxy(mut self, url: &str) -> Self {
+        self.config.proxy = Some(ProxyConfig::new(url));
+        self
+    }
+
+    /// Basic authentication for the [`proxy`](Self::proxy); ignored without one.
+    pub fn proxy_auth(mut self, username: &str, password: &str) -> Self {
+        if let Some(proxy) = &mut self.config.proxy {
+            proxy.username = Some(username.to_string());
+            proxy.password = Some(password.to_string());
+        }
+        self
+    }
+
+    /// Whether to honour `HTTPS_PROXY`/`HTTP_PROXY`/`NO_PROXY` when no explicit
+    /// [`proxy`](Self::proxy) is set. On by default.
+    pub fn env_proxy(mut self, enabled: bool) -> Self {
+        self.config.use_env_proxy = enabled;
+        self
+    }
+
+    /// Trusts the PEM certificates in `pem` as roots in addition to the built-in ones, e.g.
+    /// the private root CA of a TLS-intercepting network. Unreadable certificates fail in
+    /// [`build`](Self::build).
+    pub fn add_root_certificate(mut self, pem: &[u8]) -> Self {
+        self.config
+            .root_certificates
+            .push(String::from_utf8_lossy(pem).into_owned());
+        self
+    }
+
+    /// Accepts any server certificate, expired, self-signed or forged ones included.
+    ///
+    /// This removes all protection against a man in the middle, so only use it in lab
+    /// environments; prefer [`add_root_certificate`](Self::add_root_certificate).
+    pub fn danger_accept_invalid_certs(mut self) -> Self {
+        self.config.danger_accept_invalid_certs = true;
+        self
+    }
+
+    /// The `Accept-Language` the portal renders its pages in, e.g. `en-US,en;q=0.5`. Defaults
+    /// to German, which the university portal serves most consistently.
+    pub fn accept_language(self, languages: &str) -> Self {
+        self.extra_header("Accept-Language", languages)
+    }
+
+    /// Replaces the default browser `User-Agent`, e.g. with
+    /// `format!("{DEFAULT_USER_AGENT} timetable-sync/1.0")`; see
+    /// [`DEFAULT_USER_AGENT`](crate::DEFAULT_USER_AGENT).
+    pub fn user_agent(self, user_agent: &str) -> Self {
+        self.extra_header("User-Agent", user_agent)
+    }
+
+    /// Sends `name: value` with every request, replacing a default header of the same name
+    /// (compared case-insensitively) and keeping all others. An invalid name or value makes
+    /// [`build`](Self::build) fail with [`UrError::Config`].
+    pub fn extra_header(mut self, name: &str, value: &str) -> Self {
+        let headers = &mut self.config.headers;
+        headers.retain(|existing, _| !existing.eq_ignore_ascii_case(name));
+        headers.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    /// Sends requests through `transport`; the HTTP settings of the builder then do not apply.
+    pub fn transport(mut self, transport: Arc<dyn HttpTransport>) -> Self {
+        self.transport = Some(transport);
+        self
+    }
+
+    /// Writes the timetable pages into `dir` when no calendar can be found on them, for
+    /// reporting portal changes. Off by default.
+    pub fn debug_dump_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.debug_dump_dir = Some(dir.into());
+        self
+    }
+
+    /// Keeps the timetable pages in memory when no calendar can be found on them, for
+    /// [`UrConnect::last_debug_capture`] to hand out. Off by default.
+    pub fn capture_debug(mut self, capture: bool) -> Self {
+        self.capture_debug = capture;
+        self
+    }
+
+    /// Calls `observer` after every request; see [`UrConnect::with_request_observer`].
+    pub fn request_observer(
+        mut self,
+        observer: impl Fn(&RequestInfo) + Send + Sync + 'static,
+    ) -> Self {
+        self.request_observer = Some(Arc::new(observer));
+        self
+    }
+
+    /// Continues a session saved with [`UrConnect::export_session`]; see
+    /// [`UrConnect::from_session`].
+    pub fn session(mut self, session: SessionState) -> Self {
+        self.session = Some(session);
+        self
+    }
+
+    /// Logs in again with these credentials when the session expires; see
+    /// [`UrConnect::with_credentials`].
+    pub fn credentials(mut self, username: &str, password: &str) -> Self {
+        self.credentials = Some(Credentials::new(username, password));
+        self
+    }
+
+    pub fn build(self) -> Result<UrConnect, UrError> {
+        let mut client = match self.transport {
+            Some(transport) => UrConnect::with_transport(self.config.portal, transport)?,
+            None => UrConnect::from_config(&self.config)?,
+        };
+        client.set_debug_dump_dir(self.debug_dump_dir);
+        client.set_capture_debug(self.capture_debug);
+        client.set_cache_ttl(self.cache_ttl);
+        if let Some(observer) = self.request_observer {
+            client = client.with_request_observer(observer);
+        }
+        client.set_retry(self.config.retry);
+        client.set_min_request_interval(Duration::from_millis(self.config.min_request_interval_ms));
+        client.set_deadline(
+            self.deadline
+                .or(self.config.deadline_secs.map(Duration::from_secs)),
+        );
+        if let Some(session) = &self.session {
+            client.restore_session(session);
+        }
+        if let Some(credentials) = self.credentials {
+            client = client.with_credentials(credentials.username(), credentials.password());
+        }
+        Ok(client)
+    }
+}
+
+fn whole_secs(duration: Duration) -> u64 {
+    duration.as_secs() + u64::from(duration.subsec_nanos() > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::mock::MockTransport;
+
+    #[tokio::test]
+    async fn builds_client_for_another_portal() {
+        let transport =
+            Arc::new(MockTransport::default().route("/hisinone/start.faces", "<html></html>"));
+        let client = UrConnect::builder()
+            .base_url("https://his.example.org")
+            .start_page_path("/hisinone/start.faces")
+            .login_path("/hisinone/login")
+            .timetable_path("/hisinone/plan.xhtml")
+            .transport(transport.clone())
+            .build()
+            .unwrap();
+
+        assert!(client.get_timetable().await.is_err());
+        let requests = transport.requests();
+        assert_eq!(
+            requests[0],
+            "GET https://his.example.org/hisinone/start.faces"
+        );
+        assert!(
+            requests[1].starts_with("GET https://his.example.org/hisinone/plan.xhtml?_flowId=")
+        );
+    }
+
+    #[tokio::test]
+    async fn tries_the_configured_flow_ids_in_order() {
+        let transport = Arc::new(
+            MockTransport::default()
+                .route("hisinoneStartPage", "<html></html>")
+                .route(
+                    "_flowId=",
+                    "<html><body><!-- NoSuchFlowDefinitionException --></body></html>",
+                ),
+        );
+        let client = UrConnect::builder()
+            .base_url("https://his.example.org")
+            .flow_ids(&["timetable-flow", "scheduler-flow"])
+            .transport(transport.clone())
+            .build()
+            .unwrap();
+
+        let err = client.get_timetable().await.unwrap_err();
+        assert!(
+            matches!(&err, UrError::FlowNotRecognised { attempted }
+                if attempted == &["timetable-flow", "scheduler-flow"]),
+            "{err}"
+        );
+        assert_eq!(
+            err.to_string(),
+            "portal did not recognise any timetable flow id (tried timetable-flow, scheduler-flow)"
+        );
+        let requests = transport.requests();
+        assert!(requests[1].ends_with("_flowId=timetable-flow"));
+        assert!(requests[2].ends_with("_flowId=scheduler-flow"));
+    }
+
+    #[tokio::test]
+    async fn dumps_pages_only_when_asked() {
+        let dir = std::env::temp_dir().join(format!("ur-connect-dump-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let transport = || {
+            Arc::new(
+                MockTransport::default()
+                    .route("hisinoneStartPage", "<html></html>")
+                    .route("_flowExecutionKey=e1s1", "<html>no export here</html>")
+                    .route(
+                        "_flowId=",
+                        r#"<input type="hidden" name="_flowExecutionKey" value="e1s1"/>"#,
+                    ),
+            )
+        };
+
+        let client = UrConnect::builder()
+            .transport(transport())
+            .debug_dump_dir(&dir)
+            .build()
+            .unwrap();
+        assert!(matches!(
+            client.get_timetable().await,
+            Err(UrError::IcsUrlNotFound)
+        ));
+        let dumped = std::fs::read_to_string(dir.join("debug_timetable_full.html")).unwrap();
+        assert_eq!(dumped, "<html>no export here</html>");
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let client = UrConnect::builder().transport(transport()).build().unwrap();
+        assert!(client.get_timetable().await.is_err());
+        assert!(!std::path::Path::new("debug_timetable_full.html").exists());
+        assert_eq!(client.last_debug_capture(), None);
+
+        let client = UrConnect::builder()
+            .transport(transport())
+            .capture_debug(true)
+            .build()
+            .unwrap();
+        assert_eq!(client.last_debug_capture(), None);
+        assert!(client.get_timetable().await.is_err());
+        let capture = client.last_debug_capture().unwrap();
+        assert_eq!(capture.full_page, "<html>no export here</html>");
+        assert!(
+            capture
+                .full_page_url
+                .as_str()
+                .contains("_flowExecutionKey=e1s1")
+        );
+        assert!(capture.entry_page.contains("_flowExecutionKey"));
+    }
+
+    #[test]
+    fn merges_http_settings_into_the_defaults() {
+        let builder = UrConnect::builder()
+            .timeout(Duration::from_millis(14_500))
+            .connect_timeout(Duration::from_millis(2_500))
+            .user_agent("timetable-sync/1.0")
+            .accept_language("en-US")
+            .extra_header("accept-language", "de-DE")
+            .extra_header("X-Requested-By", "cron");
+        let headers = &builder.config.headers;
+        assert_eq!(builder.config.timeout_secs, 15);
+        assert_eq!(builder.config.connect_timeout_secs, 3);
+        assert_eq!(headers["User-Agent"], "timetable-sync/1.0");
+        assert_eq!(headers["accept-language"], "de-DE");
+        assert!(!headers.contains_key("Accept-Language"));
+        assert_eq!(headers["X-Requested-By"], "cron");
+        assert!(headers.contains_key("Accept"));
+        assert!(builder.build().is_ok());
+
+        let invalid = UrConnect::builder().extra_header("Bad Header", "x").build();
+        assert!(matches!(invalid, Err(UrError::Config(_))));
+        let invalid = UrConnect::builder()
+            .user_agent("bot\r\nX-Injected: 1")
+            .build();
+        assert!(matches!(invalid, Err(UrError::Config(_))));
+
+        let appended = UrConnect::builder()
+            .user_agent(&format!("{} timetable-sync/1.0", crate::DEFAULT_USER_AGENT));
+        assert!(appended.config.headers["User-Agent"].starts_with("Mozilla/5.0"));
+    }
+
+    #[test]
+    fn rejects_invalid_urls_up_front() {
+        assert!(matches!(
+            UrConnect::builder().base_url("not a url").build(),
+            Err(UrError::Config(_))
+        ));
+        assert!(
+            UrConnect::builder()
+                .base_url("ftp://portal.example.org")
+                .build()
+                .is_err()
+        );
+        for attached in [
+            "https://portal.example.org/qisserver",
+            "https://portal.example.org/?lang=de",
+            "https://portal.example.org#top",
+        ] {
+            assert!(UrConnect::with_base_url(attached).is_err(), "{attached}");
+        }
+        assert!(UrConnect::with_base_url("https://portal.example.org/").is_ok());
+        assert!(UrConnect::builder().build().is_ok());
+    }
+
+    #[test]
+    fn checks_proxy_urls_when_building() {
+        let err = UrConnect::builder()
+            .proxy("http://[proxy")
+            .build()
+            .err()
+            .unwrap();
+        assert!(
+            matches!(&err, UrError::Config(message) if message.contains("http://[proxy")),
+            "{err}"
+        );
+        assert!(
+            UrConnect::builder()
+                .proxy("http://proxy.example.org:3128")
+                .build()
+                .is_ok()
+        );
+        let socks = UrConnect::builder()
+            .proxy("socks5h://127.0.0.1:1080")
+            .build();
+        if cfg!(feature = "socks") {
+            assert!(socks.is_ok());
+        } else {
+            assert!(
+                matches!(&socks, Err(UrError::Config(message)) if message.contains("`socks` feature"))
+            );
+        }
+    }
+}
@@ -0,0 +1,129 @@
+//! Pluggable storage for downloaded calendars.
+//!
+//! [`UrConnect`](crate::UrConnect) consults a [`ResponseCache`] before downloading the ICS
+//! export: a fresh entry is used without contacting the portal, a stale one is revalidated
+//! with `If-None-Match` / `If-Modified-Since`.
+
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, SystemTime},
+};
+
+use reqwest::Url;
+
+/// A stored response body together with its HTTP validators.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CachedResponse {
+    pub body: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    /// When the response was last fetched or revalidated.
+    pub stored_at: SystemTime,
+}
+
+impl CachedResponse {
+    /// Whether the response is younger than `max_age`.
+    pub fn is_fresh(&self, max_age: Duration) -> bool {
+        self.stored_at.elapsed().is_ok_and(|age| age < max_age)
+    }
+}
+
+/// A store for responses keyed by URL, e.g. on disk or in Redis.
+pub trait ResponseCache: Send + Sync {
+    fn get(&self, url: &Url) -> Option<CachedResponse>;
+
+    fn put(&self, url: &Url, response: CachedResponse);
+
+    /// How long a stored response is used without revalidating it. Zero, the default, means
+    /// every use revalidates.
+    fn max_age(&self) -> Duration {
+        Duration::ZERO
+    }
+}
+
+/// A process-local [`ResponseCache`].
+#[derive(Default)]
+pub struct MemoryCache {
+    max_age: Duration,
+    entries: Mutex<HashMap<Url, CachedResponse>>,
+}
+
+impl MemoryCache {
+    pub fn new(max_age: Duration) -> Self {
+        Self {
+            max_age,
+            entries: Mutex::default(),
+        }
+    }
+}
+
+impl ResponseCache for MemoryCache {
+    fn get(&self, url: &Url) -> Option<CachedResponse> {
+        self.entries.lock().unwrap().get(url).cloned()
+    }
+
+    fn put(&self, url: &Url, response: CachedResponse) {
+        self.entries.lock().unwrap().insert(url.clone(), response);
+    }
+
+    fn max_age(&self) -> Duration {
+        self.max_age
+    }
+}
+
+/// The cache key for `url`: the per-session `_flowExecutionKey` is dropped so that the same
+/// export is found again after logging in anew.
+pub(crate) fn cache_key(url: &Url) -> Url {
+    let mut key = url.clone();
+    let pairs: Vec<(String, String)> = url
+        .query_pairs()
+        .filter(|(name, _)| name != "_flowExecutionKey")
+        .map(|(name, value)| (name.into_owned(), value.into_owned()))
+        .collect();
+    if pairs.is_empty() {
+        key.set_query(None);
+    } else {
+        key.query_pairs_mut().clear().extend_pairs(pairs);
+    }
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn memory_cache_reports_freshness_by_max_age() {
+        let cache = MemoryCache::new(Duration::from_secs(60));
+        let url = Url::parse("https://portal.test/export.ics?user=7").unwrap();
+        assert!(cache.get(&url).is_none());
+
+        let mut response = CachedResponse {
+            body: "BEGIN:VCALENDAR".to_string(),
+            etag: Some("\"v1\"".to_string()),
+            last_modified: None,
+            stored_at: SystemTime::now(),
+        };
+        cache.put(&url, response.clone());
+        assert!(cache.get(&url).unwrap().is_fresh(cache.max_age()));
+
+        response.stored_at -= Duration::from_secs(120);
+        cache.put(&url, response);
+        assert!(!cache.get(&url).unwrap().is_fresh(cache.max_age()));
+    }
+
+    #[test]
+    fn cache_key_ignores_flow_execution_key() {
+        let keyed =
+            Url::parse("https://portal.test/export.ics?user=7&_flowExecutionKey=e3s1").unwrap();
+        let plain = Url::parse("https://portal.test/export.ics?user=7").unwrap();
+        assert_eq!(cache_key(&keyed), plain);
+
+        let only_key = Url::parse("https://portal.test/export.ics?_flowExecutionKey=e3s1").unwrap();
+        assert_eq!(
+            cache_key(&only_key).as_str(),
+            "https://portal.test/export.ics"
+        );
+    }
+}
@@ -1,315 +1,2229 @@
-use std::{sync::Arc, time::Duration};
+use std::{
+    cell::RefCell,
+    fs,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime},
+};
 
-use anyhow::{Context, Result, bail};
-use chrono::Utc;
+use chrono::{DateTime, Local, Utc};
+use futures_util::{Stream, stream};
 use reqwest::{
-    Client, StatusCode, Url,
-    cookie::Jar,
+    Certificate, Client, NoProxy, Proxy, StatusCode, Url,
     header::{
-        self, ACCEPT, ACCEPT_LANGUAGE, CACHE_CONTROL, HeaderMap, HeaderValue, ORIGIN, PRAGMA,
-        REFERER, USER_AGENT,
+        CONTENT_TYPE, ETAG, HeaderMap, HeaderName, HeaderValue, IF_MODIFIED_SINCE, IF_NONE_MATCH,
+        LAST_MODIFIED, RETRY_AFTER,
     },
 };
+use tracing::{debug, instrument, trace, warn};
+use zeroize::Zeroizing;
 
 use crate::{
-    model::TimetableEntry,
+    builder::UrConnectBuilder,
+    cache::{CachedResponse, ResponseCache, cache_key},
+    config::{DEFAULT_BASE_URL, PortalConfig, RetryPolicy, UrConnectConfig},
+    error::{FetchStep, GaveUp, ProxyRejected, UrError},
+    export,
+    model::{SemesterId, TimetableEntry},
+    monitor::{MonitorEvent, TimetableMonitor},
     parsing::{
         dom::{
-            extract_flow_key_from_html, find_credential_fields, find_ics_url, find_input_value,
-            find_timetable_menu_link, parse_document,
+            LoginError, detect_login_error, extract_flow_key_from_html, find_credential_fields,
+            find_flow_link, find_ics_url, find_input_value, find_login_error_message,
+            find_login_form, find_login_form_action, find_maintenance_end, find_meta_refresh,
+            find_otp_form, find_saml_response_form, find_semesters, find_study_submenu_link,
+            find_timetable_menu_link, find_timetable_selection_form, has_strong_timetable_link,
+            is_challenge_page, is_flow_execution_expired_page, is_flow_not_found_page,
+            is_logged_in_page, is_login_page, is_maintenance_page, is_timetable_selection_page,
+            parse_document,
         },
-        ics::parse_ics,
+        ics::{ParseOptions, ParseReport, parse_ics, parse_ics_events, parse_ics_report},
+        jsonld::parse_jsonld_events,
     },
+    schedule::{RefreshOutcome, find_current, refresh_outcome},
+    session::{Credentials, SessionState},
+    transport::{CookieJar, FetchResult, HttpTransport, ReqwestTransport},
 };
 
+/// A post-processing step applied to every entry returned by [`UrConnect::get_timetable`].
+pub type EntryMapper = Box<dyn Fn(TimetableEntry) -> TimetableEntry + Send + Sync>;
+
+/// A callback told about every request the client sends; see
+/// [`UrConnect::with_request_observer`].
+pub type RequestObserver = Arc<dyn Fn(&RequestInfo) + Send + Sync>;
+
+/// One request as reported to a [`RequestObserver`]. Bodies and headers are left out, so no
+/// credentials or cookies reach the observer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestInfo {
+    /// `GET` or `POST`.
+    pub method: &'static str,
+    pub url: Url,
+    /// The response status; `None` when the request failed without a response.
+    pub status: Option<StatusCode>,
+    pub elapsed: Duration,
+    /// Counting from 1; higher for retries.
+    pub attempt: u32,
+}
+
 pub struct UrConnect {
-    client: Client,
-    jar: Arc<Jar>,
+    transport: Arc<dyn HttpTransport>,
     base_uri: Url,
     start_page: Url,
     login_post: Url,
+    logout: Url,
     timetable_base: Url,
-    flow_id: String,
+    flow_ids: Vec<String>,
+    follow_submenu: bool,
+    semester_param: String,
+    flow_cache: Mutex<FlowCache>,
+    entry_cache: Option<EntryCache>,
+    entry_mappers: Vec<EntryMapper>,
+    response_cache: Option<Arc<dyn ResponseCache>>,
+    request_observer: Option<RequestObserver>,
+    debug_dump_dir: Option<PathBuf>,
+    /// The last capture, when capturing is on; see [`UrConnect::last_debug_capture`].
+    debug_capture: Option<Mutex<Option<DebugCapture>>>,
+    /// Dropped by [`UrConnect::logout`]; the password is wiped when they are.
+    credentials: Mutex<Option<Credentials>>,
+    deadline: Option<Duration>,
+    retry: RetryPolicy,
+    throttle: Throttle,
+}
+
+/// Navigation state remembered between timetable fetches.
+#[derive(Default)]
+struct FlowCache {
+    /// The flow id the portal last accepted.
+    flow_id: Option<String>,
+    /// The calendar export last downloaded and the page linking it; see
+    /// [`UrConnect::get_timetable_cached`].
+    export: Option<(Url, Url)>,
+}
+
+/// How long each phase of a timetable fetch took; see [`UrConnect::get_timetable_timed`].
+///
+/// Phases that were retried (for example while trying several flow ids) add up.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FetchTimings {
+    /// Loading the landing page (and the study submenu, when followed).
+    pub landing: Duration,
+    /// Opening the timetable flow up to the `_flowExecutionKey`.
+    pub flow_resolution: Duration,
+    /// Loading the full timetable page.
+    pub timetable_page: Duration,
+    pub ics_download: Duration,
+    pub parse: Duration,
+}
+
+impl FetchTimings {
+    pub fn total(&self) -> Duration {
+        self.landing + self.flow_resolution + self.timetable_page + self.ics_download + self.parse
+    }
+}
+
+/// The entries of a fetch together with where they came from; see
+/// [`UrConnect::get_timetable_detailed`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimetableResult {
+    pub entries: Vec<TimetableEntry>,
+    /// The calendar export the entries were read from; `None` when they came from a schedule
+    /// embedded in the timetable page.
+    pub ics_url: Option<Url>,
+    /// When the fetch started.
+    pub fetched_at: DateTime<Local>,
+    /// The calendar's `X-WR-CALNAME`.
+    pub calendar_name: Option<String>,
+    /// The calendar's `PRODID`, naming the software that wrote it.
+    pub product_id: Option<String>,
+    /// Events of the export left out because they had neither a start nor a summary.
+    pub skipped_events: usize,
+}
+
+/// The background refresh started by [`UrConnect::spawn_keepalive`]; dropping it stops the
+/// refreshes.
+#[derive(Debug)]
+pub struct KeepAlive {
+    task: tokio::task::JoinHandle<()>,
+}
+
+impl KeepAlive {
+    /// Whether the refreshes stopped because the session ended.
+    pub fn is_finished(&self) -> bool {
+        self.task.is_finished()
+    }
+}
+
+impl Drop for KeepAlive {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// A page of a portal flow opened by [`UrConnect::navigate_flow`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlowPage {
+    pub flow_id: String,
+    /// The `_flowExecutionKey` the portal handed out for this visit; pages of the same flow
+    /// need it in their requests.
+    pub flow_key: String,
+    /// The entry page the key was taken from.
+    pub entry_url: Url,
+    /// The HTML of the page reached with the key.
+    pub html: String,
+    /// Where the request for that page finally ended up.
+    pub final_url: Url,
+}
+
+/// What a single timetable fetch did; see [`UrConnect::get_timetable_with_metrics`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FetchMetrics {
+    pub timings: FetchTimings,
+    /// Every request sent, in order, retries included.
+    pub requests: Vec<RequestInfo>,
+    /// The size of all response bodies together.
+    pub bytes_downloaded: u64,
+    /// How many of the requests repeated a failed attempt.
+    pub retries: u32,
+    /// Where the calendar was found.
+    pub source: Option<CalendarSource>,
+}
+
+/// Which of the places the client looks for a calendar had one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum CalendarSource {
+    /// The export link on the full timetable page.
+    FullTimetablePage,
+    /// The export link on the flow entry page.
+    EntryPage,
+    /// A schedule embedded as JSON-LD, for pages without an export.
+    EmbeddedSchedule,
+}
+
+tokio::task_local! {
+    /// The metrics of the [`UrConnect::get_timetable_with_metrics`] call being polled, if any.
+    static METRICS: RefCell<FetchMetrics>;
+}
+
+/// Updates the metrics of the current call, if it collects any.
+fn record_metrics(update: impl FnOnce(&mut FetchMetrics)) {
+    let _ = METRICS.try_with(|metrics| update(&mut metrics.borrow_mut()));
+}
+
+/// The timetable pages of a fetch that found no calendar on them; see
+/// [`UrConnect::last_debug_capture`].
+///
+/// The pages belong to the logged-in session, so treat them as confidential.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DebugCapture {
+    /// The flow entry page; empty when the fetch resumed with a known flow key.
+    pub entry_page: String,
+    pub full_page_url: Url,
+    pub full_page: String,
+}
+
+/// When a whole timetable fetch has to be done by; see
+/// [`UrConnectBuilder::deadline`](crate::UrConnectBuilder::deadline).
+#[derive(Clone, Copy)]
+struct Deadline(Option<tokio::time::Instant>);
+
+impl Deadline {
+    fn after(limit: Option<Duration>) -> Self {
+        Self(limit.map(|limit| tokio::time::Instant::now() + limit))
+    }
+
+    /// Runs `work` as `step`, failing with [`UrError::Timeout`] when the deadline passes
+    /// first or one of its requests ran into the request timeout.
+    async fn run<T>(
+        self,
+        step: FetchStep,
+        work: impl Future<Output = Result<T, UrError>>,
+    ) -> Result<T, UrError> {
+        let result = match self.0 {
+            None => work.await,
+            Some(at) if tokio::time::Instant::now() >= at => return Err(UrError::Timeout { step }),
+            Some(at) => tokio::time::timeout_at(at, work)
+                .await
+                .map_err(|_| UrError::Timeout { step })?,
+        };
+        match result {
+            Err(UrError::Network { source, .. }) if source.is_timeout() => {
+                Err(UrError::Timeout { step })
+            }
+            result => result,
+        }
+    }
+}
+
+/// Spaces requests at least `interval` apart; see
+/// [`UrConnectBuilder::min_request_interval`](crate::UrConnectBuilder::min_request_interval).
+#[derive(Default)]
+struct Throttle {
+    interval: Duration,
+    /// When the next request may be sent.
+    next_slot: Mutex<Option<Instant>>,
+}
+
+impl Throttle {
+    /// Waits for the caller's turn. The slot is reserved before sleeping, so tasks sharing
+    /// the client queue up one interval apart instead of all firing when the wait is over.
+    async fn wait(&self) {
+        if self.interval.is_zero() {
+            return;
+        }
+        let slot = {
+            let mut next_slot = self.next_slot.lock().unwrap();
+            let now = Instant::now();
+            let slot = next_slot.map_or(now, |next| next.max(now));
+            *next_slot = Some(slot + self.interval);
+            slot
+        };
+        tokio::time::sleep_until(slot.into()).await;
+    }
+}
+
+/// Entries parsed as they are taken, as [`UrConnect::stream_timetable`] hands them out.
+type LazyEntries<'a> = Box<dyn Iterator<Item = TimetableEntry> + Send + 'a>;
+
+/// The progress of [`UrConnect::stream_timetable`].
+enum EntryStream<'a> {
+    Start,
+    Entries(LazyEntries<'a>),
+    Done,
+}
+
+/// The entries of the last fetch, handed out again while younger than `ttl`; see
+/// [`UrConnectBuilder::cache_ttl`](crate::UrConnectBuilder::cache_ttl).
+struct EntryCache {
+    ttl: Duration,
+    stored: Mutex<Option<(Instant, Vec<TimetableEntry>)>>,
+}
+
+impl EntryCache {
+    fn fresh(&self) -> Option<Vec<TimetableEntry>> {
+        match &*self.stored.lock().unwrap() {
+            Some((stored_at, entries)) if stored_at.elapsed() < self.ttl => Some(entries.clone()),
+            _ => None,
+        }
+    }
+
+    fn store(&self, entries: &[TimetableEntry]) {
+        *self.stored.lock().unwrap() = Some((Instant::now(), entries.to_vec()));
+    }
+
+    fn clear(&self) {
+        *self.stored.lock().unwrap() = None;
+    }
+}
+
+/// The longest `Retry-After` pause honoured before retrying a `429` response.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+/// How many `<meta http-equiv="refresh">` pages in a row a GET follows.
+const MAX_META_REFRESHES: usize = 3;
+
+/// The timetable flow pages reached after the landing page.
+struct TimetablePages {
+    /// The entry page; absent when resuming with a known flow key.
+    first: Option<FetchResult>,
+    flow_key: String,
+    full_url: Url,
+    full_page: FetchResult,
+    /// The semester asked for; `None` for the one the portal shows by default.
+    semester: Option<SemesterId>,
 }
 
-struct FetchResult {
-    body: String,
-    final_url: Url,
-    status: StatusCode,
+/// What the timetable pages offer as the calendar: the export [`UrConnect::get_raw_ics`]
+/// returns, or, without one, the pages themselves for reading an embedded schedule.
+enum RawCalendar {
+    Export { url: Url, ics: String },
+    NotOffered(Box<TimetablePages>),
+}
+
+/// Where [`UrConnect::open_flow`] finds a flow.
+struct FlowRequest<'a> {
+    entry_url: &'a Url,
+    flow_id: &'a str,
+    /// The page the flow is continued on with its `_flowExecutionKey`.
+    flow_base: &'a Url,
+    semester: Option<SemesterId>,
+}
+
+impl TimetablePages {
+    fn first_body(&self) -> &str {
+        self.first.as_ref().map_or("", |page| page.body.as_str())
+    }
 }
 
 impl UrConnect {
-    pub fn new() -> Result<Self> {
-        let base_uri = Url::parse("https://campusportal.ur.de")?;
-        let start_page = base_uri.join("/qisserver/pages/cs/sys/portal/hisinoneStartPage.faces")?;
-        let login_post = base_uri.join("/qisserver/rds?state=user&type=1&category=auth.login")?;
-        let timetable_base = base_uri.join("/qisserver/pages/plan/individualTimetable.xhtml")?;
-        let jar = Arc::new(Jar::default());
+    pub fn new() -> Result<Self, UrError> {
+        Self::with_base_url(DEFAULT_BASE_URL)
+    }
+
+    /// Creates a client for another HISinOne portal that uses the UR page layout, e.g.
+    /// `https://his.example.org`.
+    ///
+    /// `base` must be a bare http(s) origin without path or query.
+    pub fn with_base_url(base: &str) -> Result<Self, UrError> {
+        Self::builder().base_url(base).build()
+    }
+
+    pub fn builder() -> UrConnectBuilder {
+        UrConnectBuilder::new()
+    }
+
+    /// Creates a client for the portal described by `config`.
+    pub fn with_config(config: PortalConfig) -> Result<Self, UrError> {
+        Self::from_config(&UrConnectConfig {
+            portal: config,
+            ..UrConnectConfig::default()
+        })
+    }
+
+    /// Creates a client with the portal, timeout and headers described by `config`.
+    pub fn from_config(config: &UrConnectConfig) -> Result<Self, UrError> {
+        let jar = Arc::new(CookieJar::default());
 
         let mut headers = HeaderMap::new();
-        headers.insert(
-            ACCEPT,
-            HeaderValue::from_static(
-                "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8",
-            ),
-        );
-        headers.insert(ACCEPT_LANGUAGE, HeaderValue::from_static("en-US,en;q=0.5"));
-        headers.insert(header::CONNECTION, HeaderValue::from_static("keep-alive"));
-        headers.insert(
-            USER_AGENT,
-            HeaderValue::from_static(
-                "Mozilla/5.0 (Macintosh; Intel Mac OS X 10.15; rv:128.0) Gecko/20100101 Firefox/128.0",
-            ),
-        );
+        for (name, value) in &config.headers {
+            let name = HeaderName::from_bytes(name.as_bytes())
+                .map_err(|_| UrError::Config(format!("invalid header name {name:?}")))?;
+            let value = HeaderValue::from_str(value)
+                .map_err(|_| UrError::Config(format!("invalid value for header {name}")))?;
+            headers.insert(name, value);
+        }
 
-        let client = Client::builder()
+        let mut builder = Client::builder()
             .default_headers(headers)
             .cookie_provider(jar.clone())
-            .timeout(Duration::from_secs(60))
-            .build()?;
+            .connect_timeout(Duration::from_secs(config.connect_timeout_secs))
+            .timeout(Duration::from_secs(config.timeout_secs));
+        if let Some(proxy) = &config.proxy {
+            let scheme = proxy.url.trim().split_once("://").map(|(scheme, _)| scheme);
+            if !cfg!(feature = "socks")
+                && scheme.is_some_and(|scheme| scheme.to_ascii_lowercase().starts_with("socks"))
+            {
+                return Err(UrError::Config(format!(
+                    "SOCKS proxy {} needs the `socks` feature of ur-connect",
+                    proxy.url
+                )));
+            }
+            let mut explicit = Proxy::all(&proxy.url).map_err(|err| {
+                UrError::Config(format!("invalid proxy URL {}: {err}", proxy.url))
+            })?;
+            if let Some(username) = &proxy.username {
+                explicit = explicit.basic_auth(username, proxy.password.as_deref().unwrap_or(""));
+            }
+            if config.use_env_proxy {
+                explicit = explicit.no_proxy(NoProxy::from_env());
+            }
+            builder = builder.proxy(explicit);
+        } else if !config.use_env_proxy {
+            builder = builder.no_proxy();
+        }
+        for pem in &config.root_certificates {
+            let certificates = Certificate::from_pem_bundle(pem.as_bytes())
+                .ok()
+                .filter(|certificates| !certificates.is_empty())
+                .ok_or_else(|| {
+                    UrError::Config("root certificate is not a PEM certificate".to_string())
+                })?;
+            for certificate in certificates {
+                builder = builder.add_root_certificate(certificate);
+            }
+        }
+        if config.danger_accept_invalid_certs {
+            warn!("TLS certificate validation is disabled");
+            builder = builder.danger_accept_invalid_certs(true);
+        }
+        let client = builder.build().map_err(|source| UrError::Network {
+            context: "failed to build HTTP client".to_string(),
+            source,
+        })?;
+
+        let mut client = Self::with_transport(
+            config.portal.clone(),
+            Arc::new(ReqwestTransport::new(client, jar)),
+        )?;
+        client.set_deadline(config.deadline_secs.map(Duration::from_secs));
+        client.set_retry(config.retry.clone());
+        client.set_min_request_interval(Duration::from_millis(config.min_request_interval_ms));
+        Ok(client)
+    }
+
+    /// Creates a client that sends all requests through `transport` instead of `reqwest`.
+    pub fn with_transport(
+        config: PortalConfig,
+        transport: Arc<dyn HttpTransport>,
+    ) -> Result<Self, UrError> {
+        let base_uri = Url::parse(&config.base_url).map_err(|err| {
+            UrError::Config(format!(
+                "invalid portal base URL {}: {err}",
+                config.base_url
+            ))
+        })?;
+        if !matches!(base_uri.scheme(), "http" | "https") {
+            return Err(UrError::Config(format!(
+                "portal base URL {base_uri} is not an http(s) URL"
+            )));
+        }
+        if base_uri.path() != "/" || base_uri.query().is_some() || base_uri.fragment().is_some() {
+            return Err(UrError::Config(format!(
+                "portal base URL {base_uri} must be an origin without path or query"
+            )));
+        }
+        let join = |path: &str| {
+            base_uri
+                .join(path)
+                .map_err(|err| UrError::Config(format!("invalid portal path {path:?}: {err}")))
+        };
+        let start_page = join(&config.start_page_path)?;
+        let login_post = join(&config.login_path)?;
+        let logout = join(&config.logout_path)?;
+        let timetable_base = join(&config.timetable_path)?;
+        if config.flow_ids.is_empty() {
+            return Err(UrError::Config(
+                "at least one timetable flow id is required".to_string(),
+            ));
+        }
 
         Ok(Self {
-            client,
-            jar,
+            transport,
             base_uri,
             start_page,
             login_post,
+            logout,
             timetable_base,
-            flow_id: "individualTimetableSchedule-flow".to_string(),
+            flow_ids: config.flow_ids,
+            follow_submenu: config.follow_submenu,
+            semester_param: config.semester_param,
+            flow_cache: Mutex::new(FlowCache::default()),
+            entry_cache: None,
+            entry_mappers: Vec::new(),
+            response_cache: None,
+            request_observer: None,
+            debug_dump_dir: None,
+            debug_capture: None,
+            credentials: Mutex::new(None),
+            deadline: None,
+            retry: RetryPolicy::default(),
+            throttle: Throttle::default(),
         })
     }
 
-    pub async fn login(&self, username: &str, password: &str) -> Result<()> {
-        let start = self
-            .get_with_headers(&self.start_page, Some(&self.start_page))
+    /// Creates a client for `state.base_url` that continues the saved session instead of
+    /// logging in.
+    ///
+    /// Whether the session is still alive only shows on the first request; an expired one
+    /// fails with [`UrError::SessionExpired`].
+    pub fn from_session(state: SessionState) -> Result<Self, UrError> {
+        Self::builder()
+            .base_url(&state.base_url)
+            .session(state)
+            .build()
+    }
+
+    /// Saves the cookies of the current session, e.g. after [`login`](Self::login).
+    pub fn export_session(&self) -> SessionState {
+        let mut cookies: Vec<String> = Vec::new();
+        for url in [&self.start_page, &self.timetable_base, &self.base_uri] {
+            for cookie in self.transport.cookies(url) {
+                if !cookies.contains(&cookie) {
+                    cookies.push(cookie);
+                }
+            }
+        }
+        SessionState {
+            base_url: self.base_uri.as_str().trim_end_matches('/').to_string(),
+            cookies,
+        }
+    }
+
+    pub(crate) fn restore_session(&self, state: &SessionState) {
+        for cookie in &state.cookies {
+            self.transport
+                .add_cookie(&format!("{cookie}; Path=/"), &self.base_uri);
+        }
+    }
+
+    /// Registers a mapper applied to each fetched entry, e.g. to rename courses or fix rooms.
+    ///
+    /// Mappers run in registration order.
+    pub fn with_entry_mapper(mut self, mapper: EntryMapper) -> Self {
+        self.entry_mappers.push(mapper);
+        self
+    }
+
+    /// Remembers the credentials so that a fetch that finds the session expired logs in
+    /// again and retries once.
+    ///
+    /// A second expired session in the same fetch, or rejected credentials, is returned as
+    /// the error, so a changed password cannot cause a login loop.
+    pub fn with_credentials(mut self, username: &str, password: &str) -> Self {
+        *self.credentials.get_mut().unwrap() = Some(Credentials::new(username, password));
+        self
+    }
+
+    /// Keeps downloaded calendars in `cache` and revalidates them with the portal instead of
+    /// downloading them anew.
+    pub fn with_response_cache(mut self, cache: Arc<dyn ResponseCache>) -> Self {
+        self.response_cache = Some(cache);
+        self
+    }
+
+    /// Calls `observer` after every request, failed and retried ones included, e.g. to record
+    /// metrics. Requests cut off by a [deadline](UrConnectBuilder::deadline) are not reported.
+    pub fn with_request_observer(mut self, observer: RequestObserver) -> Self {
+        self.request_observer = Some(observer);
+        self
+    }
+
+    /// Logs in with username and password.
+    ///
+    /// Accounts with two-factor authentication fail with [`UrError::SecondFactorRequired`];
+    /// use [`login_with_otp`](Self::login_with_otp) for them. When the portal serves a
+    /// challenge page in place of its login form, no credentials are posted and the login
+    /// fails with [`UrError::RateLimited`].
+    pub async fn login(&self, username: &str, password: &str) -> Result<(), UrError> {
+        self.login_with_credentials(&Credentials::new(username, password))
             .await
-            .context("failed to load start page")?;
+    }
 
-        let start_doc = parse_document(&start.body);
-        let ajax_token = find_input_value(&start_doc, "input[name='ajax-token']", "value")
-            .filter(|v| !v.is_empty())
-            .ok_or_else(|| anyhow::anyhow!("ajax-token not found on login form"))?;
+    /// Like [`login`](Self::login), taking the password from `credentials`.
+    ///
+    /// The login forms holding the password are wiped once they are sent, and no error or
+    /// debug dump contains them.
+    pub async fn login_with_credentials(&self, credentials: &Credentials) -> Result<(), UrError> {
+        self.login_inner(credentials, None).await
+    }
 
-        let (user_field, pass_field) = find_credential_fields(&start_doc);
+    /// Logs in and returns the client with the credentials remembered as by
+    /// [`with_credentials`](Self::with_credentials), so an expired session is renewed
+    /// transparently during later fetches.
+    pub async fn login_and_remember(self, username: &str, password: &str) -> Result<Self, UrError> {
+        self.login_remembering(Credentials::new(username, password))
+            .await
+    }
 
-        let cookie_domain = self.base_uri.domain().unwrap_or("");
-        self.jar.add_cookie_str(
-            &format!("_clickedButtonId=undefined; Domain={cookie_domain}; Path=/"),
-            &self.base_uri,
-        );
+    /// Reads the credentials from the environment variables `user_var` and `password_var`
+    /// (see [`Credentials::from_env`]), logs in and returns the client with the credentials
+    /// remembered as by [`with_credentials`](Self::with_credentials).
+    ///
+    /// ```no_run
+    /// # async fn run() -> Result<(), ur_connect::UrError> {
+    /// use ur_connect::{PASSWORD_ENV_VAR, USER_ENV_VAR, UrConnect};
+    ///
+    /// let client = UrConnect::new()?
+    ///     .login_from_env(USER_ENV_VAR, PASSWORD_ENV_VAR)
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn login_from_env(self, user_var: &str, password_var: &str) -> Result<Self, UrError> {
+        self.login_remembering(Credentials::from_env(user_var, password_var)?)
+            .await
+    }
+
+    async fn login_remembering(mut self, credentials: Credentials) -> Result<Self, UrError> {
+        self.login_with_credentials(&credentials).await?;
+        *self.credentials.get_mut().unwrap() = Some(credentials);
+        Ok(self)
+    }
 
-        let mut form = Vec::with_capacity(5);
-        form.push(("userInfo".to_string(), String::new()));
-        form.push(("ajax-token".to_string(), ajax_token));
-        form.push((user_field, username.to_string()));
-        form.push((pass_field, password.to_string()));
-        form.push(("submit".to_string(), String::new()));
+    /// Logs in with username, password and the current one-time code of the second factor.
+    ///
+    /// A wrong or expired code fails with [`UrError::InvalidOtp`]. When the portal does not
+    /// ask for a code, `otp` is ignored.
+    pub async fn login_with_otp(
+        &self,
+        username: &str,
+        password: &str,
+        otp: &str,
+    ) -> Result<(), UrError> {
+        self.login_inner(&Credentials::new(username, password), Some(otp))
+            .await
+    }
 
-        let login_res = self
-            .post_form_with_headers(&self.login_post, Some(&self.start_page), &form)
+    // Only whether a one-time code was given is recorded; the credentials never are.
+    #[instrument(name = "login", skip_all, fields(second_factor = otp.is_some()))]
+    async fn login_inner(
+        &self,
+        credentials: &Credentials,
+        otp: Option<&str>,
+    ) -> Result<(), UrError> {
+        let start = self
+            .get_with_headers(&self.start_page, Some(&self.start_page))
             .await
-            .context("login request failed")?;
+            .map_err(|err| UrError::request("failed to load start page", err))?;
+        let cookie_domain = self.base_uri.domain().unwrap_or("");
 
-        // inside span with id=contextInformation
-        if !login_res.body.contains("data-user-logged-in=\"true\"") {
-            bail!("invalid login credentials");
+        ensure_no_maintenance(&start)?;
+        if is_challenge_page(&start.body) {
+            return Err(UrError::RateLimited);
         }
 
+        // Deployments with an external identity provider may send the browser there at once.
+        let login_res = if self.is_external(&start.final_url) {
+            self.login_with_idp(start, credentials).await?
+        } else {
+            // Parsed documents are not `Send`, so this one must not live across an await.
+            let (ajax_token, user_field, pass_field, login_url) = {
+                let start_doc = parse_document(&start.body);
+                let (user_field, pass_field) =
+                    find_credential_fields(&start_doc).ok_or_else(|| {
+                        UrError::LoginFormNotFound {
+                            url: start.final_url.clone(),
+                        }
+                    })?;
+                let ajax_token = find_input_value(&start_doc, "input[name='ajax-token']", "value")
+                    .filter(|v| !v.is_empty())
+                    .ok_or_else(|| {
+                        UrError::Parse("ajax-token not found on login form".to_string())
+                    })?;
+                let login_url = find_login_form_action(&start_doc, &start.final_url)
+                    .unwrap_or_else(|| self.login_post.clone());
+                (ajax_token, user_field, pass_field, login_url)
+            };
+
+            self.transport.add_cookie(
+                &format!("_clickedButtonId=undefined; Domain={cookie_domain}; Path=/"),
+                &self.base_uri,
+            );
+
+            let form = Zeroizing::new(vec![
+                ("userInfo".to_string(), String::new()),
+                ("ajax-token".to_string(), ajax_token),
+                (user_field, credentials.username().to_string()),
+                (pass_field, credentials.password().to_string()),
+                ("submit".to_string(), String::new()),
+            ]);
+
+            let login_res = self
+                .post_form_once(&login_url, Some(&self.start_page), &form)
+                .await
+                .map_err(|err| UrError::request("login request failed", err))?;
+            if self.is_external(&login_res.final_url) {
+                self.login_with_idp(login_res, credentials).await?
+            } else {
+                login_res
+            }
+        };
+
         if !login_res.status.is_success() {
-            bail!("login failed with status {}", login_res.status);
+            return Err(UrError::LoginFailed {
+                status: login_res.status,
+            });
+        }
+        let login_res = self.pass_second_factor(login_res, otp).await?;
+        if is_challenge_page(&login_res.body) {
+            return Err(UrError::RateLimited);
+        }
+
+        // The portal answers rejected credentials with 200 and the login form again.
+        let login_doc = parse_document(&login_res.body);
+        if let Some(error) = detect_login_error(&login_doc) {
+            let message = find_login_error_message(&login_doc);
+            return Err(match error {
+                LoginError::InvalidCredentials => UrError::InvalidCredentials { message },
+                LoginError::AccountLocked => UrError::AccountLocked { message },
+                LoginError::CaptchaRequired => UrError::CaptchaRequired,
+            });
         }
 
+        self.set_refresh_cookies();
+        Ok(())
+    }
+
+    /// Sets the `lastRefresh`/`sessionRefresh` cookies the portal's session countdown keeps.
+    fn set_refresh_cookies(&self) {
+        let cookie_domain = self.base_uri.domain().unwrap_or("");
         let millis = Utc::now().timestamp_millis();
-        self.jar.add_cookie_str(
+        self.transport.add_cookie(
             &format!("lastRefresh={millis}; Domain={cookie_domain}; Path=/"),
             &self.base_uri,
         );
-        self.jar.add_cookie_str(
+        self.transport.add_cookie(
             &format!("sessionRefresh=0; Domain={cookie_domain}; Path=/"),
             &self.base_uri,
         );
+    }
+
+    /// Submits `otp` when `page` is the second-factor prompt; other pages are returned as is.
+    async fn pass_second_factor(
+        &self,
+        page: FetchResult,
+        otp: Option<&str>,
+    ) -> Result<FetchResult, UrError> {
+        let Some(prompt) = find_otp_form(&parse_document(&page.body), &page.final_url) else {
+            return Ok(page);
+        };
+        let otp = otp.ok_or(UrError::SecondFactorRequired)?;
+        let mut form = Zeroizing::new(prompt.fields);
+        form.push((prompt.code_field, otp.trim().to_string()));
 
+        let answer = self
+            .post_form_once(&prompt.action, Some(&page.final_url), &form)
+            .await
+            .map_err(|err| UrError::request("one-time code request failed", err))?;
+        let answer_doc = parse_document(&answer.body);
+        if find_otp_form(&answer_doc, &answer.final_url).is_some() {
+            return Err(UrError::InvalidOtp {
+                message: find_login_error_message(&answer_doc),
+            });
+        }
+        Ok(answer)
+    }
+
+    /// Completes a login on an external SAML identity provider, starting from its login
+    /// `page`, and returns the portal page the SAML response is posted to.
+    async fn login_with_idp(
+        &self,
+        page: FetchResult,
+        credentials: &Credentials,
+    ) -> Result<FetchResult, UrError> {
+        debug!(idp = %page.final_url, "logging in through external identity provider");
+        // Parsed documents are not `Send`, so none may live across an await.
+        let login_form = {
+            let document = parse_document(&page.body);
+            // An IdP that still has a session of its own answers with the SAML response
+            // directly.
+            if find_saml_response_form(&document, &page.final_url).is_some() {
+                None
+            } else {
+                let (action, form) =
+                    find_login_form(&document, &page.final_url).ok_or_else(|| {
+                        UrError::Parse("identity provider page has no login form".to_string())
+                    })?;
+                let fields = find_credential_fields(&document).ok_or_else(|| {
+                    UrError::LoginFormNotFound {
+                        url: page.final_url.clone(),
+                    }
+                })?;
+                Some((action, form, fields))
+            }
+        };
+        let answer = match login_form {
+            None => page,
+            Some((action, form, (user_field, pass_field))) => {
+                let mut form = Zeroizing::new(form);
+                form.push((user_field, credentials.username().to_string()));
+                form.push((pass_field, credentials.password().to_string()));
+                self.post_form_once(&action, Some(&page.final_url), &form)
+                    .await
+                    .map_err(|err| {
+                        UrError::request("identity provider login request failed", err)
+                    })?
+            }
+        };
+
+        let (acs_url, fields) = {
+            let answer_doc = parse_document(&answer.body);
+            find_saml_response_form(&answer_doc, &answer.final_url).ok_or_else(|| {
+                UrError::IdpRejected {
+                    message: find_login_error_message(&answer_doc),
+                }
+            })?
+        };
+        self.post_form_once(&acs_url, Some(&answer.final_url), &fields)
+            .await
+            .map_err(|err| UrError::request("failed to post SAML response to the portal", err))
+    }
+
+    /// Whether `url` lies outside the portal, e.g. on an identity provider.
+    fn is_external(&self, url: &Url) -> bool {
+        url.host_str() != self.base_uri.host_str()
+    }
+
+    /// Fails with [`UrError::SessionExpired`] when a navigation request ended on the login
+    /// form: the portal redirects requests of an ended session to it, or to the identity
+    /// provider on deployments with external logins.
+    fn ensure_logged_in(&self, page: &FetchResult) -> Result<(), UrError> {
+        let bounced = self.is_external(&page.final_url)
+            || page
+                .final_url
+                .query_pairs()
+                .any(|(key, value)| key == "category" && value == "auth.login")
+            || is_login_page(&page.body);
+        if bounced {
+            debug!(final_url = %page.final_url, "portal sent the request to the login");
+            return Err(UrError::SessionExpired);
+        }
         Ok(())
     }
 
-    pub async fn get_timetable(&self) -> Result<Vec<TimetableEntry>> {
-        let landing = self
+    /// Checks with one GET of the start page whether the portal still considers the session
+    /// logged in.
+    ///
+    /// Nothing is posted and no cookies are added besides those the portal sets. Only a failed
+    /// request is an error; any page without a logged-in marker (the context information
+    /// flag or a logout link) counts as logged out, the login form included.
+    pub async fn is_session_valid(&self) -> Result<bool, UrError> {
+        let start = self
             .get_with_headers(&self.start_page, Some(&self.start_page))
             .await
-            .context("failed to load landing page after login")?;
+            .map_err(|err| UrError::request("failed to load start page", err))?;
+        Ok(is_logged_in_page(&parse_document(&start.body)))
+    }
 
-        let entry_url = find_timetable_menu_link(&landing.body, &self.base_uri, &self.flow_id)
-            .unwrap_or_else(|| build_timetable_uri(&self.timetable_base, &self.flow_id, None));
+    /// Whether the portal still considers the session logged in; the same check as
+    /// [`is_session_valid`](Self::is_session_valid).
+    pub async fn is_logged_in(&self) -> Result<bool, UrError> {
+        self.is_session_valid().await
+    }
 
-        let first = self
-            .get_with_headers(&entry_url, Some(&self.start_page))
+    /// Keeps the session alive the way an open browser tab does: renews the `lastRefresh`
+    /// cookie and loads the start page.
+    ///
+    /// HISinOne ends sessions idle for about 30 minutes by default, so a long-running client
+    /// that polls less often should call this every 10 to 15 minutes. Fails with
+    /// [`UrError::SessionExpired`] when the session has already ended.
+    pub async fn refresh_session(&self) -> Result<(), UrError> {
+        self.set_refresh_cookies();
+        let start = self
+            .get_with_headers(&self.start_page, Some(&self.start_page))
             .await
-            .with_context(|| format!("failed to load timetable entry page at {entry_url}"))?;
-
-        let flow_key = extract_flow_key_from_html(&first.body)
-            .or_else(|| extract_flow_key_from_url(&first.final_url))
-            .or_else(|| extract_flow_key_from_url(&entry_url))
-            .ok_or_else(|| {
-                anyhow::anyhow!("could not determine _flowExecutionKey for timetable")
-            })?;
+            .map_err(|err| UrError::request("failed to refresh the session", err))?;
+        if !is_logged_in_page(&parse_document(&start.body)) {
+            return Err(UrError::SessionExpired);
+        }
+        Ok(())
+    }
 
-        let full_timetable_url =
-            build_timetable_uri(&self.timetable_base, &self.flow_id, Some(&flow_key));
+    /// Calls [`refresh_session`](Self::refresh_session) every `interval` on a Tokio task
+    /// until the returned handle is dropped or the session ends.
+    ///
+    /// A failed refresh is logged and retried at the next tick. Must be called from within a
+    /// Tokio runtime.
+    pub fn spawn_keepalive(self: &Arc<Self>, interval: Duration) -> KeepAlive {
+        let client = Arc::clone(self);
+        let task = tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                match client.refresh_session().await {
+                    Ok(()) => trace!("refreshed session"),
+                    Err(UrError::SessionExpired) => {
+                        warn!("session ended, stopping keep-alive");
+                        break;
+                    }
+                    Err(err) => warn!(%err, "failed to refresh session"),
+                }
+            }
+        });
+        KeepAlive { task }
+    }
 
-        let full_page = self
-            .get_with_headers(&full_timetable_url, Some(&self.start_page))
+    /// Ends the portal session and forgets its cookies and any credentials remembered with
+    /// [`with_credentials`](Self::with_credentials).
+    ///
+    /// Both are dropped even when the logout request fails, so later fetches fail with
+    /// [`UrError::SessionExpired`] until [`login`](Self::login) is called again. Calling it
+    /// without being logged in, or twice, is harmless.
+    ///
+    /// The logout URL is loaded with a GET rather than posted: HISinOne's own "Abmelden"
+    /// control is a plain link to it, so the GET is what a browser sends and what the portal
+    /// ends the session for.
+    pub async fn logout(&self) -> Result<(), UrError> {
+        let result = self
+            .get_with_headers(&self.logout, Some(&self.start_page))
             .await
-            .with_context(|| {
-                format!("failed to load full timetable page at {full_timetable_url}")
-            })?;
+            .map_err(|err| UrError::request("logout request failed", err));
+        self.transport.clear_cookies();
+        drop(self.credentials.lock().unwrap().take());
+        *self.flow_cache.lock().unwrap() = FlowCache::default();
+        self.invalidate_cache();
 
-        let ics_url = find_ics_url(&full_page.body, &self.base_uri)
-            .or_else(|| find_ics_url(&first.body, &self.base_uri))
-            .or_else(|| {
-                let _ = std::fs::write("debug_timetable_full.html", &full_page.body);
-                let _ = std::fs::write("debug_timetable_initial.html", &first.body);
-                None
-            })
-            .ok_or_else(|| anyhow::anyhow!("could not locate ICS URL in timetable pages"))?;
+        let status = result?.status;
+        if !status.is_success() {
+            return Err(UrError::LogoutFailed { status });
+        }
+        Ok(())
+    }
+
+    /// Fetches and parses the timetable of the logged-in user.
+    ///
+    /// With a [cache TTL](UrConnectBuilder::cache_ttl) set, the entries of the last fetch are
+    /// returned without contacting the portal while they are younger than the TTL. Tasks
+    /// missing the cache at the same time each fetch.
+    ///
+    /// The calendar is the one [`get_raw_ics`](Self::get_raw_ics) downloads, parsed. Where
+    /// that fails with [`UrError::IcsUrlNotFound`] because the pages offer no export, the
+    /// schedule embedded in them as JSON-LD is read instead.
+    pub async fn get_timetable(&self) -> Result<Vec<TimetableEntry>, UrError> {
+        if let Some(entries) = self.entry_cache.as_ref().and_then(EntryCache::fresh) {
+            debug!(count = entries.len(), "serving cached entries");
+            return Ok(entries);
+        }
+        self.get_timetable_timed().await.map(|(entries, _)| entries)
+    }
 
-        println!("ics URL: {}", &ics_url);
+    /// Like [`get_timetable`](Self::get_timetable), also reporting how long each phase took.
+    #[instrument(name = "get_timetable", skip_all)]
+    pub async fn get_timetable_timed(
+        &self,
+    ) -> Result<(Vec<TimetableEntry>, FetchTimings), UrError> {
+        let mut timings = FetchTimings::default();
+        let result = self
+            .fetch_timetable(&mut timings, Deadline::after(self.deadline))
+            .await?;
+        Ok((result.entries, timings))
+    }
 
-        let ics = self
-            .get_with_headers(&ics_url, Some(&full_timetable_url))
+    /// Like [`get_timetable`](Self::get_timetable), also telling where the entries came from
+    /// and what the parser left out.
+    pub async fn get_timetable_detailed(&self) -> Result<TimetableResult, UrError> {
+        self.fetch_timetable(&mut FetchTimings::default(), Deadline::after(self.deadline))
             .await
-            .with_context(|| format!("failed to download ICS from {ics_url}"))?;
+    }
 
-        let entries = parse_ics(&ics.body);
-        if entries.is_empty() {
-            bail!("no events were parsed from the ICS response");
+    /// Like [`get_timetable`](Self::get_timetable), yielding the entries one at a time while
+    /// the calendar is parsed, e.g. to render a long schedule progressively.
+    ///
+    /// The navigation and the download happen before the first item, and a failure there is
+    /// the only item. Fresh entries of the [entry cache](UrConnectBuilder::cache_ttl) are
+    /// yielded as they are; streamed entries are not stored in it.
+    pub fn stream_timetable(&self) -> impl Stream<Item = Result<TimetableEntry, UrError>> + Send {
+        stream::unfold(EntryStream::Start, move |state| async move {
+            let (mut entries, yielded) = match state {
+                EntryStream::Start => match self.timetable_entries().await {
+                    Ok(entries) => (entries, false),
+                    Err(err) => return Some((Err(err), EntryStream::Done)),
+                },
+                EntryStream::Entries(entries) => (entries, true),
+                EntryStream::Done => return None,
+            };
+            match entries.next() {
+                Some(entry) => Some((Ok(entry), EntryStream::Entries(entries))),
+                None if !yielded => Some((Err(UrError::EmptyTimetable), EntryStream::Done)),
+                None => None,
+            }
+        })
+    }
+
+    /// Fetches the calendar for [`stream_timetable`](Self::stream_timetable) and returns its
+    /// entries, parsed as they are taken.
+    async fn timetable_entries(&self) -> Result<LazyEntries<'_>, UrError> {
+        if let Some(entries) = self.entry_cache.as_ref().and_then(EntryCache::fresh) {
+            debug!(count = entries.len(), "serving cached entries");
+            return Ok(Box::new(entries.into_iter()));
         }
+        let mut timings = FetchTimings::default();
+        let deadline = Deadline::after(self.deadline);
+        let calendar = self.download_calendar(&mut timings, deadline, None).await?;
+        let entries: LazyEntries<'_> = match calendar {
+            RawCalendar::Export { ics, .. } => {
+                Box::new(parse_ics_events(&ics, &ParseOptions::default()))
+            }
+            RawCalendar::NotOffered(pages) => {
+                let mut entries = parse_jsonld_events(&pages.full_page.body);
+                if entries.is_empty() {
+                    entries = parse_jsonld_events(pages.first_body());
+                }
+                if entries.is_empty() {
+                    self.dump_pages(&pages);
+                    return Err(UrError::IcsUrlNotFound);
+                }
+                record_metrics(|metrics| metrics.source = Some(CalendarSource::EmbeddedSchedule));
+                Box::new(entries.into_iter())
+            }
+        };
+        Ok(Box::new(entries.map(|entry| self.map_entry(entry))))
+    }
 
-        Ok(entries)
+    async fn fetch_timetable(
+        &self,
+        timings: &mut FetchTimings,
+        deadline: Deadline,
+    ) -> Result<TimetableResult, UrError> {
+        let calendar = self.download_calendar(timings, deadline, None).await?;
+        let result = self.timetable_from_calendar(calendar, timings)?;
+        if let Some(cache) = &self.entry_cache {
+            cache.store(&result.entries);
+        }
+        Ok(result)
     }
 
-    pub fn format_entries(entries: &[TimetableEntry]) -> String {
-        if entries.is_empty() {
-            return "No timetable entries found.".to_string();
+    /// Like [`get_timetable`](Self::get_timetable), for `semester` instead of the one the
+    /// portal shows by default.
+    ///
+    /// The semester is passed to the full timetable page as the
+    /// [`semester_param`](PortalConfig::semester_param) query parameter;
+    /// [`list_semesters`](Self::list_semesters) tells which semesters the portal offers.
+    /// Entries fetched this way bypass the [entry cache](UrConnectBuilder::cache_ttl).
+    #[instrument(skip_all, fields(%semester))]
+    pub async fn get_timetable_for_semester(
+        &self,
+        semester: SemesterId,
+    ) -> Result<Vec<TimetableEntry>, UrError> {
+        let mut timings = FetchTimings::default();
+        let deadline = Deadline::after(self.deadline);
+        let calendar = self
+            .download_calendar(&mut timings, deadline, Some(semester))
+            .await?;
+        self.timetable_from_calendar(calendar, &mut timings)
+            .map(|result| result.entries)
+    }
+
+    /// The semesters the timetable pages offer to choose from, oldest first; empty when the
+    /// portal shows no semester choice.
+    #[instrument(skip_all)]
+    pub async fn list_semesters(&self) -> Result<Vec<SemesterId>, UrError> {
+        let mut timings = FetchTimings::default();
+        let deadline = Deadline::after(self.deadline);
+        let pages = self.timetable_pages(&mut timings, deadline, None).await?;
+        let semesters = find_semesters(&pages.full_page.body);
+        if semesters.is_empty() {
+            return Ok(find_semesters(pages.first_body()));
         }
-        entries
-            .iter()
-            .map(|entry| entry.to_string())
-            .collect::<Vec<_>>()
-            .join("\n")
+        Ok(semesters)
     }
 
-    async fn get_with_headers(&self, url: &Url, referer: Option<&Url>) -> Result<FetchResult> {
-        let mut request = self.client.get(url.clone());
-        if let Some(r) = referer {
-            request = request.header(REFERER, r.as_str());
+    /// Like [`get_timetable`](Self::get_timetable), but downloads straight from the export
+    /// link an earlier fetch of this client found, which saves the landing, flow and timetable
+    /// pages.
+    ///
+    /// Without such a link, or when downloading from it fails or yields no events (e.g. the
+    /// portal answers with its login page once the flow expired), the full navigation runs
+    /// instead and its link is remembered for the next call. The
+    /// [deadline](UrConnectBuilder::deadline) covers both the download and the navigation.
+    pub async fn get_timetable_cached(&self) -> Result<Vec<TimetableEntry>, UrError> {
+        let deadline = Deadline::after(self.deadline);
+        let cached = self.flow_cache.lock().unwrap().export.clone();
+        if let Some((ics_url, referer)) = cached {
+            let download = deadline.run(FetchStep::IcsDownload, self.fetch_ics(&ics_url, &referer));
+            match download.await {
+                Err(err @ UrError::Timeout { .. }) => return Err(err),
+                Ok(ics) => {
+                    let entries = parse_ics(&ics);
+                    if !entries.is_empty() {
+                        return Ok(self.map_entries(entries));
+                    }
+                }
+                Err(err) => debug!(error = %err, "cached calendar export failed"),
+            }
+            debug!(%ics_url, "cached calendar export unusable, navigating again");
+            self.flow_cache.lock().unwrap().export = None;
+        }
+        if let Some(entries) = self.entry_cache.as_ref().and_then(EntryCache::fresh) {
+            debug!(count = entries.len(), "serving cached entries");
+            return Ok(entries);
         }
-        request = request
-            .header("Upgrade-Insecure-Requests", "1")
-            .header("Sec-Fetch-Dest", "document")
-            .header("Sec-Fetch-Mode", "navigate")
-            .header("Sec-Fetch-Site", "same-origin")
-            .header(header::CONNECTION, "keep-alive");
+        self.fetch_timetable(&mut FetchTimings::default(), deadline)
+            .await
+            .map(|result| result.entries)
+    }
 
-        let response = request.send().await.context("HTTP GET request failed")?;
-        let status = response.status();
-        let final_url = response.url().clone();
-        let body = response
-            .text()
+    /// Like [`get_timetable_timed`](Self::get_timetable_timed), also reporting the requests
+    /// sent, the bytes downloaded, the retries and where the calendar was found.
+    ///
+    /// The metrics cover this call only, even while other calls share the client.
+    pub async fn get_timetable_with_metrics(
+        &self,
+    ) -> Result<(Vec<TimetableEntry>, FetchMetrics), UrError> {
+        METRICS
+            .scope(RefCell::default(), async {
+                let (entries, timings) = self.get_timetable_timed().await?;
+                let metrics = FetchMetrics {
+                    timings,
+                    ..METRICS.with(RefCell::take)
+                };
+                Ok((entries, metrics))
+            })
             .await
-            .context("failed to read GET response body")?;
+    }
 
-        Ok(FetchResult {
-            body,
-            final_url,
-            status,
-        })
+    /// Downloads the calendar export verbatim, without parsing it.
+    ///
+    /// The navigation is the same as for [`get_timetable`](Self::get_timetable), but the
+    /// embedded-schedule fallback does not apply: pages without an export fail with
+    /// [`UrError::IcsUrlNotFound`], and an export without events with
+    /// [`UrError::EmptyTimetable`]. The text is returned untouched, with every `UID`, `TZID`
+    /// and property the entry model drops.
+    #[instrument(skip_all)]
+    pub async fn get_raw_ics(&self) -> Result<String, UrError> {
+        let mut timings = FetchTimings::default();
+        let deadline = Deadline::after(self.deadline);
+        let ics = match self.download_calendar(&mut timings, deadline, None).await? {
+            RawCalendar::Export { ics, .. } => ics,
+            RawCalendar::NotOffered(pages) => {
+                self.dump_pages(&pages);
+                return Err(UrError::IcsUrlNotFound);
+            }
+        };
+        if !ics.contains("BEGIN:VEVENT") {
+            return Err(UrError::EmptyTimetable);
+        }
+        Ok(ics)
     }
 
-    async fn post_form_with_headers(
+    /// The same as [`get_raw_ics`](Self::get_raw_ics).
+    pub async fn download_ics(&self) -> Result<String, UrError> {
+        self.get_raw_ics().await
+    }
+
+    /// Navigates to the timetable and downloads the calendar export it links, the path shared
+    /// by [`get_raw_ics`](Self::get_raw_ics) and the timetable fetches.
+    async fn download_calendar(
         &self,
-        url: &Url,
-        referer: Option<&Url>,
-        form: &[(String, String)],
-    ) -> Result<FetchResult> {
-        let mut pairs: Vec<(&str, &str)> = Vec::with_capacity(form.len());
-        for (k, v) in form {
-            pairs.push((k.as_str(), v.as_str()));
-        }
+        timings: &mut FetchTimings,
+        deadline: Deadline,
+        semester: Option<SemesterId>,
+    ) -> Result<RawCalendar, UrError> {
+        let pages = self.timetable_pages(timings, deadline, semester).await?;
+        self.calendar_from_pages(pages, timings, deadline).await
+    }
 
-        let mut request = self.client.post(url.clone()).form(&pairs);
-        if let Some(r) = referer {
-            request = request.header(REFERER, r.as_str());
-        }
+    /// A copy of the credentials for logging in again, if any are remembered.
+    fn remembered_credentials(&self) -> Option<Credentials> {
+        self.credentials.lock().unwrap().clone()
+    }
 
-        if let Some(authority) = self.base_uri.domain() {
-            request = request.header(ORIGIN, format!("https://{authority}"));
+    /// Walks from the landing page to the full timetable page, logging in again once when the
+    /// session expired and credentials are known.
+    ///
+    /// A rejected `_flowExecutionKey` opens the flow once more for a fresh key.
+    async fn timetable_pages(
+        &self,
+        timings: &mut FetchTimings,
+        deadline: Deadline,
+        semester: Option<SemesterId>,
+    ) -> Result<TimetablePages, UrError> {
+        let mut result = self
+            .navigate_to_timetable(timings, deadline, semester)
+            .await;
+        if let Err(UrError::FlowExecutionExpired) = result {
+            debug!("portal rejected the flow execution key, opening the flow again");
+            result = self
+                .navigate_to_timetable(timings, deadline, semester)
+                .await;
         }
+        let (Err(UrError::SessionExpired), Some(credentials)) =
+            (&result, self.remembered_credentials())
+        else {
+            return result;
+        };
+        debug!("session expired, logging in again");
+        deadline
+            .run(FetchStep::Login, self.login_with_credentials(&credentials))
+            .await?;
+        self.navigate_to_timetable(timings, deadline, semester)
+            .await
+    }
 
-        request = request
-            .header("Upgrade-Insecure-Requests", "1")
-            .header("Sec-Fetch-Dest", "document")
-            .header("Sec-Fetch-Mode", "navigate")
-            .header("Sec-Fetch-Site", "same-origin")
-            .header("Sec-Fetch-User", "?1")
-            .header(PRAGMA, "no-cache")
-            .header(CACHE_CONTROL, "no-cache");
+    async fn navigate_to_timetable(
+        &self,
+        timings: &mut FetchTimings,
+        deadline: Deadline,
+        semester: Option<SemesterId>,
+    ) -> Result<TimetablePages, UrError> {
+        let started = Instant::now();
+        let menu = deadline
+            .run(FetchStep::Landing, async {
+                let landing = self.landing_page().await?;
+                self.timetable_menu(landing).await
+            })
+            .await?;
+        timings.landing = started.elapsed();
 
-        let response = request.send().await.context("HTTP POST request failed")?;
-        let status = response.status();
-        let final_url = response.url().clone();
-        let body = response
-            .text()
+        self.open_timetable_flow(&menu, timings, deadline, semester)
             .await
-            .context("failed to read POST response body")?;
+    }
 
-        Ok(FetchResult {
-            body,
-            final_url,
-            status,
-        })
+    /// The start page of the logged-in session.
+    async fn landing_page(&self) -> Result<FetchResult, UrError> {
+        let landing = self
+            .get_with_headers(&self.start_page, Some(&self.start_page))
+            .await
+            .map_err(|err| UrError::request("failed to load landing page after login", err))?;
+        ensure_no_maintenance(&landing)?;
+        self.ensure_logged_in(&landing)?;
+        Ok(landing)
     }
-}
 
-fn build_timetable_uri(base: &Url, flow_id: &str, flow_key: Option<&str>) -> Url {
-    let mut result = base.clone();
-    {
-        let mut qp = result.query_pairs_mut();
-        qp.clear();
-        qp.append_pair("_flowId", flow_id);
-        if let Some(key) = flow_key {
-            qp.append_pair("_flowExecutionKey", key);
-        }
+    /// Opens the HISinOne flow `flow_id`, e.g. exam registration or the course catalogue, the
+    /// way [`get_timetable`](Self::get_timetable) opens the timetable flow, and returns the
+    /// page reached with its `_flowExecutionKey`.
+    ///
+    /// The entry page is the landing page's link to the flow or, without one, `flow_id` on
+    /// the [timetable page](PortalConfig::timetable_path). The key is then sent to the page of
+    /// the entry URL. An expired session is logged in again once when credentials are known.
+    #[instrument(skip(self))]
+    pub async fn navigate_flow(&self, flow_id: &str) -> Result<FlowPage, UrError> {
+        let deadline = Deadline::after(self.deadline);
+        let result = self.open_named_flow(flow_id, deadline).await;
+        let (Err(UrError::SessionExpired), Some(credentials)) =
+            (&result, self.remembered_credentials())
+        else {
+            return result;
+        };
+        debug!("session expired, logging in again");
+        deadline
+            .run(FetchStep::Login, self.login_with_credentials(&credentials))
+            .await?;
+        self.open_named_flow(flow_id, deadline).await
     }
-    result
-}
 
-fn extract_flow_key_from_url(url: &Url) -> Option<String> {
-    for (key, value) in url.query_pairs() {
-        if key == "_flowExecutionKey" {
-            return Some(value.into_owned());
-        }
+    async fn open_named_flow(
+        &self,
+        flow_id: &str,
+        deadline: Deadline,
+    ) -> Result<FlowPage, UrError> {
+        let landing = deadline
+            .run(FetchStep::Landing, self.landing_page())
+            .await?;
+        let entry_url = find_flow_link(&landing.body, &landing.final_url, flow_id)
+            .unwrap_or_else(|| build_timetable_uri(&self.timetable_base, flow_id, None));
+        debug!(%entry_url, "opening flow");
+        let mut flow_base = entry_url.clone();
+        flow_base.set_fragment(None);
+        let request = FlowRequest {
+            entry_url: &entry_url,
+            flow_id,
+            flow_base: &flow_base,
+            semester: None,
+        };
+        let pages = self
+            .open_flow(request, &mut FetchTimings::default(), deadline)
+            .await?
+            .ok_or_else(|| UrError::FlowNotRecognised {
+                attempted: vec![flow_id.to_string()],
+            })?;
+        Ok(FlowPage {
+            flow_id: flow_id.to_string(),
+            flow_key: pages.flow_key,
+            entry_url,
+            html: pages.full_page.body,
+            final_url: pages.full_page.final_url,
+        })
     }
-    None
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::parsing::dom::contains_calendar_hint;
+    /// Fetches the timetable of an already opened flow, skipping the landing and entry pages.
+    ///
+    /// Useful for resuming with a `_flowExecutionKey` taken from a previous session or a
+    /// browser. The flow id that worked last (or the first configured one) is used.
+    pub async fn get_timetable_with_flow_key(
+        &self,
+        flow_key: &str,
+    ) -> Result<Vec<TimetableEntry>, UrError> {
+        let flow_id = self
+            .flow_candidates()
+            .into_iter()
+            .next()
+            .ok_or_else(|| UrError::Config("no timetable flow id configured".to_string()))?;
+        let full_url = build_timetable_uri(&self.timetable_base, &flow_id, Some(flow_key));
 
-    #[test]
+        let mut timings = FetchTimings::default();
+        let deadline = Deadline::after(self.deadline);
+        let full_page = deadline
+            .run(FetchStep::TimetablePage, async {
+                let full_page = self
+                    .get_with_headers(&full_url, Some(&self.start_page))
+                    .await
+                    .map_err(|err| {
+                        UrError::request(
+                            format!("failed to load full timetable page at {full_url}"),
+                            err,
+                        )
+                    })?;
+                self.ensure_logged_in(&full_page)?;
+                if is_flow_execution_expired_page(&full_page.body) {
+                    return Err(UrError::FlowExecutionExpired);
+                }
+                if is_flow_not_found_page(&full_page.body) {
+                    return Err(UrError::FlowNotRecognised {
+                        attempted: vec![flow_id.clone()],
+                    });
+                }
+                self.pass_timetable_selection(full_page).await
+            })
+            .await?;
+
+        let pages = TimetablePages {
+            first: None,
+            flow_key: flow_key.to_string(),
+            full_url,
+            full_page,
+            semester: None,
+        };
+        let calendar = self
+            .calendar_from_pages(pages, &mut timings, deadline)
+            .await?;
+        self.timetable_from_calendar(calendar, &mut timings)
+            .map(|result| result.entries)
+    }
+
+    /// Runs the navigation of [`get_timetable`](Self::get_timetable) up to the calendar export
+    /// link and returns it without downloading the calendar, e.g. for subscribing to it from a
+    /// calendar app.
+    ///
+    /// The link carries the `_flowExecutionKey` of this session, so it may stop working once
+    /// the portal expires the session or the flow; fetch a fresh one then.
+    #[instrument(skip_all)]
+    pub async fn get_ics_url(&self) -> Result<Url, UrError> {
+        let mut timings = FetchTimings::default();
+        let deadline = Deadline::after(self.deadline);
+        let pages = self.timetable_pages(&mut timings, deadline, None).await?;
+        self.ics_url_from_pages(&pages).ok_or_else(|| {
+            self.dump_pages(&pages);
+            UrError::IcsUrlNotFound
+        })
+    }
+
+    /// The calendar export linked from the timetable pages, with the page's flow key.
+    fn ics_url_from_pages(&self, pages: &TimetablePages) -> Option<Url> {
+        let found = find_ics_url(&pages.full_page.body, &self.base_uri)
+            .map(|url| (url, CalendarSource::FullTimetablePage))
+            .or_else(|| {
+                find_ics_url(pages.first_body(), &self.base_uri)
+                    .map(|url| (url, CalendarSource::EntryPage))
+            });
+        let Some((ics_url, source)) = found else {
+            debug!("no ICS export link on the timetable pages");
+            return None;
+        };
+        record_metrics(|metrics| metrics.source = Some(source));
+        let found_on = match source {
+            CalendarSource::EntryPage => "entry page",
+            _ => "full timetable page",
+        };
+        let page_flow_key = extract_flow_key_from_html(&pages.full_page.body)
+            .unwrap_or_else(|| pages.flow_key.clone());
+        let ics_url = with_flow_key(ics_url, &self.base_uri, &page_flow_key);
+
+        debug!(%ics_url, found_on, "found ICS export");
+        Some(ics_url)
+    }
+
+    /// Downloads the calendar export linked from the timetable pages, or hands the pages back
+    /// when they link none.
+    async fn calendar_from_pages(
+        &self,
+        pages: TimetablePages,
+        timings: &mut FetchTimings,
+        deadline: Deadline,
+    ) -> Result<RawCalendar, UrError> {
+        let Some(ics_url) = self.ics_url_from_pages(&pages) else {
+            return Ok(RawCalendar::NotOffered(Box::new(pages)));
+        };
+        let started = Instant::now();
+        let ics = deadline
+            .run(
+                FetchStep::IcsDownload,
+                self.fetch_ics(&ics_url, &pages.full_url),
+            )
+            .await?;
+        timings.ics_download = started.elapsed();
+        // The export of another semester must not stand in for the current timetable.
+        if pages.semester.is_none() {
+            self.flow_cache.lock().unwrap().export =
+                Some((ics_url.clone(), pages.full_url.clone()));
+        }
+        Ok(RawCalendar::Export { url: ics_url, ics })
+    }
+
+    /// Parses the downloaded calendar, or the schedule embedded in pages without one, then
+    /// applies the entry mappers.
+    fn timetable_from_calendar(
+        &self,
+        calendar: RawCalendar,
+        timings: &mut FetchTimings,
+    ) -> Result<TimetableResult, UrError> {
+        let fetched_at = Local::now();
+        let report = match calendar {
+            RawCalendar::Export { url: ics_url, ics } => {
+                let started = Instant::now();
+                let report = parse_ics_report(&ics, &ParseOptions::default());
+                timings.parse = started.elapsed();
+                if report.entries.is_empty() {
+                    return Err(UrError::EmptyTimetable);
+                }
+                if report.skipped_events > 0 {
+                    debug!(skipped = report.skipped_events, "left out unusable events");
+                }
+                (Some(ics_url), report)
+            }
+            RawCalendar::NotOffered(pages) => {
+                // Some portals embed the schedule as JSON-LD instead of offering an export.
+                let started = Instant::now();
+                let mut entries = parse_jsonld_events(&pages.full_page.body);
+                if entries.is_empty() {
+                    entries = parse_jsonld_events(pages.first_body());
+                }
+                timings.parse = started.elapsed();
+                if entries.is_empty() {
+                    self.dump_pages(&pages);
+                    return Err(UrError::IcsUrlNotFound);
+                }
+                record_metrics(|metrics| metrics.source = Some(CalendarSource::EmbeddedSchedule));
+                debug!(count = entries.len(), "read entries from embedded JSON-LD");
+                (
+                    None,
+                    ParseReport {
+                        entries,
+                        ..ParseReport::default()
+                    },
+                )
+            }
+        };
+
+        let (ics_url, report) = report;
+        Ok(TimetableResult {
+            entries: self.map_entries(report.entries),
+            ics_url,
+            fetched_at,
+            calendar_name: report.calendar_name,
+            product_id: report.product_id,
+            skipped_events: report.skipped_events,
+        })
+    }
+
+    fn map_entries(&self, entries: Vec<TimetableEntry>) -> Vec<TimetableEntry> {
+        entries
+            .into_iter()
+            .map(|entry| self.map_entry(entry))
+            .collect()
+    }
+
+    fn map_entry(&self, entry: TimetableEntry) -> TimetableEntry {
+        self.entry_mappers
+            .iter()
+            .fold(entry, |entry, mapper| mapper(entry))
+    }
+
+    /// Re-fetches the timetable and returns the current version of `entry`, or `None` if it
+    /// no longer exists.
+    pub async fn refresh_entry(
+        &self,
+        entry: &TimetableEntry,
+    ) -> Result<Option<TimetableEntry>, UrError> {
+        let current = self.get_timetable().await?;
+        Ok(find_current(entry, &current).cloned())
+    }
+
+    /// Re-fetches the timetable once and classifies each of `entries` against it.
+    pub async fn refresh_entries(
+        &self,
+        entries: &[TimetableEntry],
+    ) -> Result<Vec<RefreshOutcome>, UrError> {
+        let current = self.get_timetable().await?;
+        Ok(entries
+            .iter()
+            .map(|entry| refresh_outcome(entry, &current))
+            .collect())
+    }
+
+    /// Fetches the timetable once and records the outcome in `monitor`.
+    ///
+    /// Call this in a loop, sleeping for [`TimetableMonitor::next_interval`] in between.
+    pub async fn poll(&self, monitor: &mut TimetableMonitor) -> Vec<MonitorEvent> {
+        monitor.record(self.get_timetable().await)
+    }
+
+    pub fn format_entries(entries: &[TimetableEntry]) -> String {
+        if entries.is_empty() {
+            return "No timetable entries found.".to_string();
+        }
+        entries
+            .iter()
+            .map(|entry| entry.to_string())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Downloads the calendar at `url`, going through the response cache when one is set.
+    ///
+    /// Error statuses and pages served in place of the calendar fail and are not cached.
+    async fn fetch_ics(&self, url: &Url, referer: &Url) -> Result<String, UrError> {
+        let failed = |err| UrError::request(format!("failed to download ICS from {url}"), err);
+        let Some(cache) = &self.response_cache else {
+            let response = self
+                .get_with_headers(url, Some(referer))
+                .await
+                .map_err(failed)?;
+            ensure_download_succeeded(&response)?;
+            ensure_calendar(&response)?;
+            return Ok(response.body);
+        };
+        let key = cache_key(url);
+        let cached = cache.get(&key);
+        if let Some(cached) = &cached
+            && cached.is_fresh(cache.max_age())
+        {
+            return Ok(cached.body.clone());
+        }
+
+        let mut validators = HeaderMap::new();
+        if let Some(cached) = &cached {
+            let etag = cached.etag.as_deref().map(HeaderValue::from_str);
+            if let Some(Ok(etag)) = etag {
+                validators.insert(IF_NONE_MATCH, etag);
+            }
+            let modified = cached.last_modified.as_deref().map(HeaderValue::from_str);
+            if let Some(Ok(modified)) = modified {
+                validators.insert(IF_MODIFIED_SINCE, modified);
+            }
+        }
+        let response = self
+            .get_with_request_headers(url, Some(referer), &validators)
+            .await
+            .map_err(failed)?;
+        let stored = match cached {
+            Some(cached) if response.status == StatusCode::NOT_MODIFIED => CachedResponse {
+                stored_at: SystemTime::now(),
+                ..cached
+            },
+            _ => {
+                ensure_download_succeeded(&response)?;
+                ensure_calendar(&response)?;
+                let header = |name| {
+                    response
+                        .headers
+                        .get(name)
+                        .and_then(|value: &HeaderValue| value.to_str().ok())
+                        .map(str::to_string)
+                };
+                CachedResponse {
+                    etag: header(ETAG),
+                    last_modified: header(LAST_MODIFIED),
+                    body: response.body,
+                    stored_at: SystemTime::now(),
+                }
+            }
+        };
+        let body = stored.body.clone();
+        cache.put(&key, stored);
+        Ok(body)
+    }
+
+    /// The pages of the last fetch that found no calendar, when built with
+    /// [`UrConnectBuilder::capture_debug`]; always `None` otherwise.
+    pub fn last_debug_capture(&self) -> Option<DebugCapture> {
+        self.debug_capture.as_ref()?.lock().unwrap().clone()
+    }
+
+    /// Keeps the timetable pages in memory and writes them into the debug dump directory, as
+    /// far as either is configured.
+    fn dump_pages(&self, pages: &TimetablePages) {
+        if let Some(capture) = &self.debug_capture {
+            *capture.lock().unwrap() = Some(DebugCapture {
+                entry_page: pages.first_body().to_string(),
+                full_page_url: pages.full_url.clone(),
+                full_page: pages.full_page.body.clone(),
+            });
+        }
+        let Some(dir) = &self.debug_dump_dir else {
+            return;
+        };
+        for (name, body) in [
+            ("debug_timetable_full.html", pages.full_page.body.as_str()),
+            ("debug_timetable_initial.html", pages.first_body()),
+        ] {
+            let path = dir.join(name);
+            match fs::write(&path, body) {
+                Ok(()) => debug!(path = %path.display(), "dumped timetable page"),
+                Err(err) => warn!(path = %path.display(), %err, "could not dump timetable page"),
+            }
+        }
+    }
+
+    pub(crate) fn set_retry(&mut self, retry: RetryPolicy) {
+        self.retry = retry;
+    }
+
+    pub(crate) fn set_min_request_interval(&mut self, interval: Duration) {
+        self.throttle.interval = interval;
+    }
+
+    pub(crate) fn set_deadline(&mut self, deadline: Option<Duration>) {
+        self.deadline = deadline;
+    }
+
+    pub(crate) fn set_debug_dump_dir(&mut self, dir: Option<PathBuf>) {
+        self.debug_dump_dir = dir;
+    }
+
+    pub(crate) fn set_cache_ttl(&mut self, ttl: Option<Duration>) {
+        self.entry_cache = ttl.map(|ttl| EntryCache {
+            ttl,
+            stored: Mutex::new(None),
+        });
+    }
+
+    /// Makes the next [`get_timetable`](Self::get_timetable) fetch from the portal even when
+    /// a [cache TTL](UrConnectBuilder::cache_ttl) is set and the cached entries are fresh.
+    pub fn invalidate_cache(&self) {
+        if let Some(cache) = &self.entry_cache {
+            cache.clear();
+        }
+    }
+
+    pub(crate) fn set_capture_debug(&mut self, capture: bool) {
+        self.debug_capture = capture.then(|| Mutex::new(None));
+    }
+
+    /// Picks the page to look for timetable links on: the landing page, or its study submenu
+    /// when `follow_submenu` is set and the landing page has no strong candidate.
+    async fn timetable_menu(&self, landing: FetchResult) -> Result<FetchResult, UrError> {
+        if !self.follow_submenu
+            || self
+                .flow_ids
+                .iter()
+                .any(|id| has_strong_timetable_link(&landing.body, &landing.final_url, id))
+        {
+            return Ok(landing);
+        }
+        let Some(submenu_url) = find_study_submenu_link(&landing.body, &landing.final_url) else {
+            return Ok(landing);
+        };
+        let submenu = self
+            .get_with_headers(&submenu_url, Some(&landing.final_url))
+            .await
+            .map_err(|err| {
+                UrError::request(
+                    format!("failed to load study submenu at {submenu_url}"),
+                    err,
+                )
+            })?;
+        self.ensure_logged_in(&submenu)?;
+        Ok(submenu)
+    }
+
+    /// Renders `entries` as an importable iCalendar document; see [`export::to_ics`].
+    pub fn format_entries_ics(entries: &[TimetableEntry]) -> String {
+        export::to_ics(entries)
+    }
+
+    /// Walks the flow id candidates until the portal accepts one and returns its pages.
+    ///
+    /// The id that worked last is tried first; a "flow not found" page moves on to the next
+    /// candidate.
+    async fn open_timetable_flow(
+        &self,
+        menu: &FetchResult,
+        timings: &mut FetchTimings,
+        deadline: Deadline,
+        semester: Option<SemesterId>,
+    ) -> Result<TimetablePages, UrError> {
+        let mut attempted = Vec::new();
+        let mut visited: Vec<Url> = Vec::new();
+
+        for candidate in self.flow_candidates() {
+            let menu_link = find_timetable_menu_link(&menu.body, &menu.final_url, &candidate)
+                .filter(|url| !visited.contains(url));
+            let strong_link = menu_link.is_some()
+                && has_strong_timetable_link(&menu.body, &menu.final_url, &candidate);
+            let link_source = match (&menu_link, strong_link) {
+                (Some(_), true) => "menu link",
+                (Some(_), false) => "menu link text",
+                (None, _) => "flow id",
+            };
+            let entry_url = menu_link
+                .unwrap_or_else(|| build_timetable_uri(&self.timetable_base, &candidate, None));
+            let flow_id = extract_flow_id_from_url(&entry_url).unwrap_or(candidate);
+            debug!(%flow_id, %entry_url, link_source, "opening timetable flow");
+            visited.push(entry_url.clone());
+
+            let request = FlowRequest {
+                entry_url: &entry_url,
+                flow_id: &flow_id,
+                flow_base: &self.timetable_base,
+                semester,
+            };
+            let Some(pages) = self.open_flow(request, timings, deadline).await? else {
+                debug!(%flow_id, "portal does not know timetable flow");
+                attempted.push(flow_id);
+                continue;
+            };
+            debug!(%flow_id, flow_key = %pages.flow_key, "opened timetable flow");
+            self.flow_cache.lock().unwrap().flow_id = Some(flow_id);
+            return Ok(pages);
+        }
+
+        Err(UrError::FlowNotRecognised { attempted })
+    }
+
+    /// Loads the entry page of a flow, takes its `_flowExecutionKey` and loads the flow page
+    /// with it; `None` when the portal does not know the flow.
+    async fn open_flow(
+        &self,
+        request: FlowRequest<'_>,
+        timings: &mut FetchTimings,
+        deadline: Deadline,
+    ) -> Result<Option<TimetablePages>, UrError> {
+        let FlowRequest {
+            entry_url,
+            flow_id,
+            flow_base,
+            semester,
+        } = request;
+        let started = Instant::now();
+        let first = deadline
+            .run(FetchStep::FlowResolution, async {
+                let first = self
+                    .get_with_headers(entry_url, Some(&self.start_page))
+                    .await
+                    .map_err(|err| {
+                        UrError::request(
+                            format!("failed to load timetable entry page at {entry_url}"),
+                            err,
+                        )
+                    })?;
+                self.ensure_logged_in(&first)?;
+                if is_flow_not_found_page(&first.body) {
+                    return Ok(None);
+                }
+                self.pass_timetable_selection(first).await.map(Some)
+            })
+            .await;
+        timings.flow_resolution += started.elapsed();
+        let Some(first) = first? else {
+            return Ok(None);
+        };
+
+        let (flow_key, key_source) = extract_flow_key_from_html(&first.body)
+            .map(|key| (key, "entry page"))
+            .or_else(|| {
+                extract_flow_key_from_url(&first.final_url).map(|key| (key, "redirect target"))
+            })
+            .or_else(|| extract_flow_key_from_url(entry_url).map(|key| (key, "entry URL")))
+            .ok_or(UrError::FlowKeyNotFound)?;
+        debug!(%flow_key, key_source, "found flow execution key");
+
+        let mut full_url = build_timetable_uri(flow_base, flow_id, Some(&flow_key));
+        if let Some(semester) = semester {
+            full_url
+                .query_pairs_mut()
+                .append_pair(&self.semester_param, &semester.term_key());
+        }
+
+        let started = Instant::now();
+        let full_page = deadline
+            .run(FetchStep::TimetablePage, async {
+                let full_page = self
+                    .get_with_headers(&full_url, Some(&self.start_page))
+                    .await
+                    .map_err(|err| {
+                        UrError::request(
+                            format!("failed to load full timetable page at {full_url}"),
+                            err,
+                        )
+                    })?;
+                self.ensure_logged_in(&full_page)?;
+                if is_flow_execution_expired_page(&full_page.body) {
+                    return Err(UrError::FlowExecutionExpired);
+                }
+                if is_flow_not_found_page(&full_page.body) {
+                    return Ok(None);
+                }
+                self.pass_timetable_selection(full_page).await.map(Some)
+            })
+            .await;
+        timings.timetable_page += started.elapsed();
+
+        Ok(full_page?.map(|full_page| TimetablePages {
+            first: Some(first),
+            flow_key,
+            full_url,
+            full_page,
+            semester,
+        }))
+    }
+
+    /// Submits the "please select a timetable" page, choosing the personal plan, when the
+    /// portal shows one instead of the timetable.
+    async fn pass_timetable_selection(&self, page: FetchResult) -> Result<FetchResult, UrError> {
+        if !is_timetable_selection_page(&page.body) {
+            return Ok(page);
+        }
+        let (action, form) = find_timetable_selection_form(&page.body, &page.final_url)
+            .ok_or_else(|| {
+                UrError::Parse("timetable selection page has no usable form".to_string())
+            })?;
+        let selected = self
+            .post_form_with_headers(&action, Some(&page.final_url), &form)
+            .await
+            .map_err(|err| {
+                UrError::request(
+                    format!("failed to submit timetable selection to {action}"),
+                    err,
+                )
+            })?;
+        self.ensure_logged_in(&selected)?;
+        Ok(selected)
+    }
+
+    fn flow_candidates(&self) -> Vec<String> {
+        let mut candidates = Vec::with_capacity(self.flow_ids.len() + 1);
+        if let Some(cached) = self.flow_cache.lock().unwrap().flow_id.clone() {
+            candidates.push(cached);
+        }
+        for id in &self.flow_ids {
+            if !candidates.contains(id) {
+                candidates.push(id.clone());
+            }
+        }
+        candidates
+    }
+
+    /// GETs `url` like a browser tab would, following up to [`MAX_META_REFRESHES`]
+    /// "please wait" pages that move on through a `<meta http-equiv="refresh">`.
+    ///
+    /// Responses declaring a content type other than HTML, like the calendar export, are not
+    /// searched for a refresh.
+    async fn get_with_headers(
+        &self,
+        url: &Url,
+        referer: Option<&Url>,
+    ) -> anyhow::Result<FetchResult> {
+        let mut page = self
+            .get_with_request_headers(url, referer, &HeaderMap::new())
+            .await?;
+        let mut refreshed_from = Vec::new();
+        while refreshed_from.len() < MAX_META_REFRESHES
+            && page.status.is_success()
+            && may_be_html(&page)
+        {
+            let Some(target) = find_meta_refresh(&page.body, &page.final_url)
+                .filter(|target| *target != page.final_url)
+            else {
+                break;
+            };
+            debug!(from = %page.final_url, to = %target, "following meta refresh");
+            let next = self
+                .get_with_request_headers(&target, Some(&page.final_url), &HeaderMap::new())
+                .await?;
+            refreshed_from.push(std::mem::replace(&mut page, next).final_url);
+        }
+        page.refreshed_from = refreshed_from;
+        Ok(page)
+    }
+
+    #[instrument(name = "get", level = "debug", skip_all, fields(%url))]
+    async fn get_with_request_headers(
+        &self,
+        url: &Url,
+        referer: Option<&Url>,
+        headers: &HeaderMap,
+    ) -> anyhow::Result<FetchResult> {
+        self.with_retries("GET", url, || async move {
+            let result = self.transport.get_with(url, referer, headers).await?;
+            trace!(
+                status = %result.status,
+                final_url = %result.final_url,
+                body_len = result.body.len(),
+                "GET"
+            );
+            reject_proxy_auth(result)
+        })
+        .await
+    }
+
+    async fn post_form_with_headers(
+        &self,
+        url: &Url,
+        referer: Option<&Url>,
+        form: &[(String, String)],
+    ) -> anyhow::Result<FetchResult> {
+        self.with_retries("POST", url, || self.send_form(url, referer, form))
+            .await
+    }
+
+    /// Posts `form` without retrying, for forms with credentials or one-time codes.
+    #[instrument(name = "post", level = "debug", skip_all, fields(%url))]
+    async fn post_form_once(
+        &self,
+        url: &Url,
+        referer: Option<&Url>,
+        form: &[(String, String)],
+    ) -> anyhow::Result<FetchResult> {
+        self.dispatch("POST", url, 1, self.send_form(url, referer, form))
+            .await
+    }
+
+    async fn send_form(
+        &self,
+        url: &Url,
+        referer: Option<&Url>,
+        form: &[(String, String)],
+    ) -> anyhow::Result<FetchResult> {
+        let result = self.transport.post_form(url, referer, form).await?;
+        trace!(
+            status = %result.status,
+            final_url = %result.final_url,
+            body_len = result.body.len(),
+            "POST"
+        );
+        reject_proxy_auth(result)
+    }
+
+    /// Repeats `send` as the retry policy allows while it fails with a server error, a
+    /// timeout or a dropped connection, and once after a `429 Too Many Requests`.
+    ///
+    /// `send` returns a plain future rather than being an async closure, whose futures are
+    /// not known to be `Send` for every borrow and would make the fetch unspawnable.
+    async fn with_retries<F>(
+        &self,
+        method: &'static str,
+        url: &Url,
+        send: impl Fn() -> F,
+    ) -> anyhow::Result<FetchResult>
+    where
+        F: Future<Output = anyhow::Result<FetchResult>>,
+    {
+        let attempts = self.retry.max_attempts.max(1);
+        let mut last_status = None;
+        // `attempt` counts against the policy; the retry of a first `429` comes on top of it,
+        // so `sent` can run one ahead.
+        let mut attempt = 1;
+        let mut sent = 1;
+        let mut was_rate_limited = false;
+        loop {
+            let result = self.dispatch(method, url, sent, send()).await;
+            let gave_up = |last_status, last_error| GaveUp {
+                attempts: sent,
+                last_status,
+                last_error,
+            };
+            if let Ok(page) = &result
+                && page.status == StatusCode::TOO_MANY_REQUESTS
+            {
+                if was_rate_limited {
+                    return Err(gave_up(Some(page.status), None).into());
+                }
+                was_rate_limited = true;
+                last_status = Some(page.status);
+                let delay =
+                    retry_after(&page.headers).unwrap_or_else(|| self.retry.delay_before(attempt));
+                debug!(%url, attempt = sent, ?delay, "rate limited, retrying");
+                tokio::time::sleep(delay).await;
+                sent += 1;
+                continue;
+            }
+            let retryable = match &result {
+                Ok(page) => {
+                    last_status = Some(page.status);
+                    page.status.is_server_error()
+                }
+                Err(err) => is_retryable(err),
+            };
+            if !retryable || attempts == 1 {
+                return result;
+            }
+            if attempt >= attempts {
+                return Err(gave_up(last_status, result.err()).into());
+            }
+            let delay = self.retry.delay_before(attempt);
+            debug!(%url, attempt = sent, ?delay, "request failed, retrying");
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+            sent += 1;
+        }
+    }
+
+    /// Sends one request once the throttle allows it and reports it to the request observer.
+    async fn dispatch(
+        &self,
+        method: &'static str,
+        url: &Url,
+        attempt: u32,
+        request: impl Future<Output = anyhow::Result<FetchResult>>,
+    ) -> anyhow::Result<FetchResult> {
+        self.throttle.wait().await;
+        let collecting_metrics = METRICS.try_with(|_| ()).is_ok();
+        if self.request_observer.is_none() && !collecting_metrics {
+            return request.await;
+        }
+        let started = Instant::now();
+        let result = request.await;
+        let info = RequestInfo {
+            method,
+            url: url.clone(),
+            status: match &result {
+                Ok(page) => Some(page.status),
+                Err(err) if err.is::<ProxyRejected>() => {
+                    Some(StatusCode::PROXY_AUTHENTICATION_REQUIRED)
+                }
+                Err(_) => None,
+            },
+            elapsed: started.elapsed(),
+            attempt,
+        };
+        if let Some(observer) = &self.request_observer {
+            observer(&info);
+        }
+        record_metrics(|metrics| {
+            if let Ok(page) = &result {
+                metrics.bytes_downloaded += page.body.len() as u64;
+            }
+            if attempt > 1 {
+                metrics.retries += 1;
+            }
+            metrics.requests.push(info);
+        });
+        result
+    }
+}
+
+/// Whether `response` is or may be an HTML page: it declares an HTML content type or none.
+fn may_be_html(response: &FetchResult) -> bool {
+    response
+        .headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_none_or(|value| value.to_ascii_lowercase().contains("html"))
+}
+
+/// Fails with [`UrError::IcsDownloadFailed`] unless the export link answered with a success
+/// status; a `304` only counts when a cached calendar backs it.
+fn ensure_download_succeeded(response: &FetchResult) -> Result<(), UrError> {
+    if !response.status.is_success() {
+        return Err(UrError::IcsDownloadFailed {
+            status: response.status,
+        });
+    }
+    Ok(())
+}
+
+/// Fails with [`UrError::IcsDownloadReturnedHtml`] when `response` is a page rather than a
+/// calendar, as when an expired session or a portal error answers the export link.
+///
+/// Only a `text/calendar` content type or a body opening with `BEGIN:VCALENDAR` counts as a
+/// calendar; empty bodies, JSON and plain-text errors fail as well.
+fn ensure_calendar(response: &FetchResult) -> Result<(), UrError> {
+    let calendar_type = response
+        .headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| {
+            value
+                .trim()
+                .to_ascii_lowercase()
+                .starts_with("text/calendar")
+        });
+    let body = response.body.trim_start_matches('\u{feff}').trim_start();
+    let opens_calendar = body
+        .get(.."BEGIN:VCALENDAR".len())
+        .is_some_and(|head| head.eq_ignore_ascii_case("BEGIN:VCALENDAR"));
+    if calendar_type || opens_calendar {
+        return Ok(());
+    }
+    Err(UrError::IcsDownloadReturnedHtml {
+        final_url: response.final_url.clone(),
+    })
+}
+
+/// Fails with [`UrError::PortalMaintenance`] when `page` is the portal's maintenance page.
+fn ensure_no_maintenance(page: &FetchResult) -> Result<(), UrError> {
+    if is_maintenance_page(&page.body) {
+        return Err(UrError::PortalMaintenance {
+            until: find_maintenance_end(&page.body),
+        });
+    }
+    Ok(())
+}
+
+fn reject_proxy_auth(result: FetchResult) -> anyhow::Result<FetchResult> {
+    if result.status == StatusCode::PROXY_AUTHENTICATION_REQUIRED {
+        return Err(ProxyRejected.into());
+    }
+    Ok(result)
+}
+
+/// The pause a `Retry-After` header asks for, given in seconds or as an HTTP date and capped
+/// at [`MAX_RETRY_AFTER`].
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    let delay = match value.parse::<u64>() {
+        Ok(secs) => Duration::from_secs(secs),
+        Err(_) => {
+            let at = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+            (at.with_timezone(&Utc) - Utc::now())
+                .to_std()
+                .unwrap_or_default()
+        }
+    };
+    Some(delay.min(MAX_RETRY_AFTER))
+}
+
+/// Whether a failed request is worth repeating: timeouts and broken connections are, errors
+/// the portal or transport reported deliberately are not.
+fn is_retryable(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| {
+        if let Some(err) = cause.downcast_ref::<reqwest::Error>() {
+            return err.is_timeout() || err.is_connect();
+        }
+        cause.downcast_ref::<std::io::Error>().is_some_and(|err| {
+            matches!(
+                err.kind(),
+                std::io::ErrorKind::ConnectionReset
+                    | std::io::ErrorKind::ConnectionAborted
+                    | std::io::ErrorKind::BrokenPipe
+                    | std::io::ErrorKind::TimedOut
+                    | std::io::ErrorKind::UnexpectedEof
+            )
+        })
+    })
+}
+
+fn build_timetable_uri(base: &Url, flow_id: &str, flow_key: Option<&str>) -> Url {
+    let mut result = base.clone();
+    {
+        let mut qp = result.query_pairs_mut();
+        qp.clear();
+        qp.append_pair("_flowId", flow_id);
+        if let Some(key) = flow_key {
+            qp.append_pair("_flowExecutionKey", key);
+        }
+    }
+    result
+}
+
+fn extract_flow_id_from_url(url: &Url) -> Option<String> {
+    url.query_pairs()
+        .find(|(key, _)| key == "_flowId")
+        .map(|(_, value)| value.into_owned())
+}
+
+fn extract_flow_key_from_url(url: &Url) -> Option<String> {
+    for (key, value) in url.query_pairs() {
+        if key == "_flowExecutionKey" {
+            return Some(value.into_owned());
+        }
+    }
+    None
+}
+
+/// Appends `_flowExecutionKey` to portal URLs that were discovered without one; links to other
+/// hosts (e.g. an already tokenized export URL) are left untouched.
+fn with_flow_key(mut url: Url, base: &Url, flow_key: &str) -> Url {
+    if url.host_str() != base.host_str() || extract_flow_key_from_url(&url).is_some() {
+        return url;
+    }
+    url.query_pairs_mut()
+        .append_pair("_flowExecutionKey", flow_key);
+    url
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cache::MemoryCache, parsing::dom::contains_calendar_hint, transport::mock::MockTransport,
+    };
+
+    #[test]
     fn formats_entries_into_lines() {
         let entries = vec![
             TimetableEntry::new(
                 "2025-01-01".to_string(),
-                "10:00 - 12:00".to_string(),
+                "10:00 - 12:00".parse().ok(),
                 "Sample Lecture".to_string(),
                 "Room 101".to_string(),
                 None,
             ),
             TimetableEntry::new(
                 "2025-01-02".to_string(),
-                "".to_string(),
+                None,
                 "Consultation".to_string(),
                 "Building A".to_string(),
                 None,
@@ -321,6 +2235,1435 @@ mod tests {
         assert!(formatted.contains("Consultation @ Building A"));
     }
 
+    #[test]
+    fn relative_ics_link_receives_page_flow_key() {
+        let base = Url::parse("https://campusportal.ur.de").unwrap();
+        let html = r#"<a href="/qisserver/pages/plan/individualTimetableCalendarExport.faces?user=1">iCal</a>"#;
+        let found = find_ics_url(html, &base).unwrap();
+        assert!(extract_flow_key_from_url(&found).is_none());
+
+        let url = with_flow_key(found, &base, "e2s1");
+        assert_eq!(
+            url.as_str(),
+            "https://campusportal.ur.de/qisserver/pages/plan/individualTimetableCalendarExport.faces?user=1&_flowExecutionKey=e2s1"
+        );
+    }
+
+    #[test]
+    fn ics_link_with_key_or_foreign_host_is_kept() {
+        let base = Url::parse("https://campusportal.ur.de").unwrap();
+        let keyed =
+            Url::parse("https://campusportal.ur.de/export.ics?_flowExecutionKey=e1s1").unwrap();
+        assert_eq!(with_flow_key(keyed.clone(), &base, "e2s1"), keyed);
+
+        let foreign = Url::parse("https://calendar.example.org/feed.ics").unwrap();
+        assert_eq!(with_flow_key(foreign.clone(), &base, "e2s1"), foreign);
+    }
+
+    const FLOW_NOT_FOUND: &str =
+        "<html><body><p>Der Flow 'old-flow' wurde nicht gefunden.</p></body></html>";
+    const ENTRY_PAGE: &str =
+        r#"<html><body><input type="hidden" name="_flowExecutionKey" value="e1s1"/></body></html>"#;
+    const FULL_PAGE: &str = r#"<html><body><textarea id="cal_add">https://portal.test/qisserver/calendarExport.ics?user=7</textarea></body></html>"#;
+    const ICS: &str = "BEGIN:VCALENDAR\nBEGIN:VEVENT\nSUMMARY:Analysis\nDTSTART:20250101T100000Z\nEND:VEVENT\nEND:VCALENDAR";
+
+    fn two_flow_client() -> (Arc<MockTransport>, UrConnect) {
+        let transport = Arc::new(
+            MockTransport::default()
+                .route("hisinoneStartPage", "<html><body>Willkommen</body></html>")
+                .route("_flowId=old-flow", FLOW_NOT_FOUND)
+                .route("_flowId=new-flow&_flowExecutionKey=e1s1", FULL_PAGE)
+                .route("_flowId=new-flow", ENTRY_PAGE)
+                .route("calendarExport.ics", ICS),
+        );
+        let config = PortalConfig {
+            base_url: "https://portal.test".to_string(),
+            flow_ids: vec!["old-flow".to_string(), "new-flow".to_string()],
+            ..PortalConfig::default()
+        };
+        let client = UrConnect::with_transport(config, transport.clone()).unwrap();
+        (transport, client)
+    }
+
+    #[tokio::test]
+    async fn falls_through_to_next_flow_id_and_remembers_it() {
+        let (transport, client) = two_flow_client();
+
+        let entries = client.get_timetable().await.unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].title, "Analysis");
+        assert_eq!(
+            client.flow_cache.lock().unwrap().flow_id.as_deref(),
+            Some("new-flow")
+        );
+        assert!(
+            transport
+                .requests()
+                .iter()
+                .any(|r| r.contains("_flowId=old-flow"))
+        );
+
+        let before = transport.requests().len();
+        client.get_timetable().await.unwrap();
+        assert!(
+            transport.requests()[before..]
+                .iter()
+                .all(|r| !r.contains("_flowId=old-flow"))
+        );
+    }
+
+    #[tokio::test]
+    async fn applies_entry_mappers_in_order() {
+        let (_, client) = two_flow_client();
+        let client = client
+            .with_entry_mapper(Box::new(|mut entry| {
+                entry.title = entry.title.to_uppercase();
+                entry
+            }))
+            .with_entry_mapper(Box::new(|mut entry| {
+                entry.title.push_str(" (UR)");
+                entry
+            }));
+
+        let entries = client.get_timetable().await.unwrap();
+        assert_eq!(entries[0].title, "ANALYSIS (UR)");
+    }
+
+    #[tokio::test]
+    async fn follows_study_submenu_to_timetable_link() {
+        let landing = r#"<html><body><a href="/qisserver/pages/cs/sys/portal/subMenu.faces?navigationPosition=studies">Mein Studium</a></body></html>"#;
+        let submenu = r#"<html><body><a href="/qisserver/pages/plan/individualTimetable.xhtml?_flowId=menu-flow&amp;navigationPosition=studies,plan">Stundenplan</a></body></html>"#;
+        let transport = Arc::new(
+            MockTransport::default()
+                .route("hisinoneStartPage", landing)
+                .route("subMenu.faces", submenu)
+                .route("_flowId=old-flow", FLOW_NOT_FOUND)
+                .route("calendarExport.ics", ICS)
+                .route("_flowId=menu-flow&_flowExecutionKey=e1s1", FULL_PAGE)
+                .route("_flowId=menu-flow", ENTRY_PAGE),
+        );
+        let config = PortalConfig {
+            base_url: "https://portal.test".to_string(),
+            flow_ids: vec!["old-flow".to_string()],
+            follow_submenu: true,
+            ..PortalConfig::default()
+        };
+        let client = UrConnect::with_transport(config.clone(), transport.clone()).unwrap();
+
+        let entries = client.get_timetable().await.unwrap();
+        assert_eq!(entries[0].title, "Analysis");
+        assert!(
+            transport
+                .requests()
+                .iter()
+                .any(|r| r.contains("subMenu.faces"))
+        );
+
+        let config = PortalConfig {
+            follow_submenu: false,
+            ..config
+        };
+        let client = UrConnect::with_transport(config, transport).unwrap();
+        assert!(client.get_timetable().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_json_ld_without_ics_export() {
+        let full_page = r#"<html><head><script type="application/ld+json">{"@type":"Event","name":"Analysis","startDate":"2025-01-01T10:00:00","location":"H 1"}</script></head><body></body></html>"#;
+        let transport = Arc::new(
+            MockTransport::default()
+                .route("hisinoneStartPage", "<html></html>")
+                .route("_flowExecutionKey=e1s1", full_page)
+                .route("_flowId=", ENTRY_PAGE),
+        );
+        let config = PortalConfig {
+            base_url: "https://portal.test".to_string(),
+            ..PortalConfig::default()
+        };
+        let client = UrConnect::with_transport(config, transport.clone()).unwrap();
+
+        let entries = client.get_timetable().await.unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(
+            (entries[0].title.as_str(), entries[0].location.as_str()),
+            ("Analysis", "H 1")
+        );
+    }
+
+    #[tokio::test]
+    async fn serves_entries_from_the_cache_until_the_ttl_passes_or_it_is_invalidated() {
+        let transport = Arc::new(
+            MockTransport::default()
+                .route("hisinoneStartPage", "<html></html>")
+                .route("_flowId=new-flow&_flowExecutionKey=e1s1", FULL_PAGE)
+                .route("_flowId=new-flow", ENTRY_PAGE)
+                .route("calendarExport.ics", ICS),
+        );
+        let build = |ttl| {
+            UrConnect::builder()
+                .portal(PortalConfig {
+                    base_url: "https://portal.test".to_string(),
+                    flow_ids: vec!["new-flow".to_string()],
+                    ..PortalConfig::default()
+                })
+                .transport(transport.clone())
+                .cache_ttl(ttl)
+                .build()
+                .unwrap()
+        };
+
+        let client = Arc::new(build(Duration::from_secs(3600)));
+        let first = client.get_timetable().await.unwrap();
+        let fetched = transport.requests().len();
+        let shared = client.clone();
+        let second = tokio::spawn(async move { shared.get_timetable().await })
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(first, second);
+        assert_eq!(transport.requests().len(), fetched);
+
+        client.invalidate_cache();
+        client.get_timetable().await.unwrap();
+        assert!(transport.requests().len() > fetched);
+
+        let expired = build(Duration::ZERO);
+        expired.get_timetable().await.unwrap();
+        let fetched = transport.requests().len();
+        expired.get_timetable().await.unwrap();
+        assert!(transport.requests().len() > fetched);
+    }
+
+    #[tokio::test]
+    async fn navigates_to_other_flows() {
+        let transport = Arc::new(
+            MockTransport::default()
+                .route(
+                    "hisinoneStartPage",
+                    r#"<a href="/qisserver/pages/cm/exa/overview.xhtml?_flowId=exams-flow&amp;navigationPosition=studies">Prüfungen</a>"#,
+                )
+                .route(
+                    "overview.xhtml?_flowId=exams-flow&_flowExecutionKey=e1s1",
+                    "<html><table id=\"exams\"></table></html>",
+                )
+                .route("overview.xhtml?_flowId=exams-flow", ENTRY_PAGE)
+                .route("_flowId=catalog-flow", FLOW_NOT_FOUND),
+        );
+        let client = UrConnect::with_transport(
+            PortalConfig {
+                base_url: "https://portal.test".to_string(),
+                ..PortalConfig::default()
+            },
+            transport.clone(),
+        )
+        .unwrap();
+
+        let page = client.navigate_flow("exams-flow").await.unwrap();
+        assert_eq!(page.flow_key, "e1s1");
+        assert!(page.html.contains("id=\"exams\""));
+        assert_eq!(
+            page.final_url.as_str(),
+            "https://portal.test/qisserver/pages/cm/exa/overview.xhtml?_flowId=exams-flow&_flowExecutionKey=e1s1"
+        );
+        assert!(
+            page.entry_url
+                .as_str()
+                .ends_with("navigationPosition=studies")
+        );
+
+        let err = client.navigate_flow("catalog-flow").await.unwrap_err();
+        assert!(
+            matches!(&err, UrError::FlowNotRecognised { attempted } if attempted == &["catalog-flow"]),
+            "{err}"
+        );
+        assert!(
+            transport
+                .requests()
+                .iter()
+                .any(|r| r.contains("individualTimetable.xhtml?_flowId=catalog-flow"))
+        );
+    }
+
+    #[tokio::test]
+    async fn follows_meta_refresh_pages_up_to_a_bound() {
+        let transport = Arc::new(
+            MockTransport::default()
+                .route(
+                    "GET https://portal.test/qisserver/pages/start.xhtml",
+                    include_str!("../tests/fixtures/meta_refresh_chain.html"),
+                )
+                .route(
+                    "wait.xhtml",
+                    include_str!("../tests/fixtures/meta_refresh.html"),
+                )
+                .route("_flowExecutionKey=e1s1", FULL_PAGE)
+                .route(
+                    "loop",
+                    r#"<meta http-equiv="refresh" content="0;url=loop">"#,
+                )
+                .route(
+                    "hop1",
+                    r#"<meta http-equiv="refresh" content="0;url=hop2">"#,
+                )
+                .route(
+                    "hop2",
+                    r#"<meta http-equiv="refresh" content="0;url=hop3">"#,
+                )
+                .route(
+                    "hop3",
+                    r#"<meta http-equiv="refresh" content="0;url=hop4">"#,
+                )
+                .route(
+                    "hop4",
+                    r#"<meta http-equiv="refresh" content="0;url=hop5">"#,
+                ),
+        );
+        let client = UrConnect::with_transport(
+            PortalConfig {
+                base_url: "https://portal.test".to_string(),
+                ..PortalConfig::default()
+            },
+            transport.clone(),
+        )
+        .unwrap();
+        let url =
+            |path: &str| Url::parse(&format!("https://portal.test/qisserver/{path}")).unwrap();
+
+        let page = client
+            .get_with_headers(&url("pages/start.xhtml"), None)
+            .await
+            .unwrap();
+        assert_eq!(page.body, FULL_PAGE);
+        assert!(page.final_url.as_str().ends_with("_flowExecutionKey=e1s1"));
+        assert_eq!(
+            page.refreshed_from,
+            [
+                url("pages/start.xhtml"),
+                url("pages/cs/sys/portal/wait.xhtml")
+            ]
+        );
+
+        let page = client.get_with_headers(&url("loop"), None).await.unwrap();
+        assert!(page.refreshed_from.is_empty());
+
+        let page = client.get_with_headers(&url("hop1"), None).await.unwrap();
+        assert_eq!(page.final_url, url("hop4"));
+        assert_eq!(page.refreshed_from, [url("hop1"), url("hop2"), url("hop3")]);
+    }
+
+    #[tokio::test]
+    async fn reports_redirects_to_the_login_as_an_expired_session() {
+        let login_url = "https://portal.test/qisserver/rds?state=user&type=1&category=auth.login";
+        let transport = Arc::new(
+            MockTransport::default()
+                .route("hisinoneStartPage", "<html></html>")
+                .redirect("_flowExecutionKey=e1s1", login_url)
+                .route(
+                    login_url,
+                    "<html><body>Bitte melden Sie sich an.</body></html>",
+                )
+                .route("_flowId=", ENTRY_PAGE),
+        );
+        let config = PortalConfig {
+            base_url: "https://portal.test".to_string(),
+            ..PortalConfig::default()
+        };
+        let client = UrConnect::with_transport(config.clone(), transport).unwrap();
+        let err = client.get_timetable().await.unwrap_err();
+        assert!(matches!(err, UrError::SessionExpired), "{err}");
+
+        let sso = Arc::new(
+            MockTransport::default()
+                .route("hisinoneStartPage", "<html></html>")
+                .redirect(
+                    "_flowId=",
+                    "https://idp.test/idp/profile/SAML2/Redirect/SSO",
+                )
+                .route("idp.test", "<html></html>"),
+        );
+        let client = UrConnect::with_transport(config, sso).unwrap();
+        let err = client.get_timetable().await.unwrap_err();
+        assert!(matches!(err, UrError::SessionExpired), "{err}");
+    }
+
+    #[tokio::test]
+    async fn opens_the_flow_again_when_the_portal_rejects_the_key() {
+        const EXPIRED: &str =
+            "<html><body><!-- NoSuchFlowExecutionException: e1s1 --></body></html>";
+        let transport = Arc::new(
+            MockTransport::default()
+                .answering_first("_flowId=new-flow", ENTRY_PAGE, 1)
+                .route("hisinoneStartPage", "<html></html>")
+                .route("_flowId=new-flow&_flowExecutionKey=e1s1", EXPIRED)
+                .route("_flowId=new-flow&_flowExecutionKey=e2s1", FULL_PAGE)
+                .route("_flowId=new-flow", &ENTRY_PAGE.replace("e1s1", "e2s1"))
+                .route("calendarExport.ics", ICS),
+        );
+        let config = PortalConfig {
+            base_url: "https://portal.test".to_string(),
+            flow_ids: vec!["new-flow".to_string()],
+            ..PortalConfig::default()
+        };
+        let client = UrConnect::with_transport(config.clone(), transport.clone()).unwrap();
+
+        let entries = client.get_timetable().await.unwrap();
+        assert_eq!(entries[0].title, "Analysis");
+        let keys: Vec<_> = transport
+            .requests()
+            .into_iter()
+            .filter(|r| r.contains("individualTimetable.xhtml?_flowId=new-flow&"))
+            .collect();
+        assert_eq!(keys.len(), 2, "{keys:?}");
+        assert!(keys[1].contains("e2s1"));
+
+        let rejecting = Arc::new(
+            MockTransport::default()
+                .route("hisinoneStartPage", "<html></html>")
+                .route("_flowExecutionKey=e1s1", EXPIRED)
+                .route("_flowId=new-flow", ENTRY_PAGE),
+        );
+        let client = UrConnect::with_transport(config, rejecting.clone()).unwrap();
+        let err = client.get_timetable().await.unwrap_err();
+        assert!(matches!(err, UrError::FlowExecutionExpired), "{err}");
+        assert_eq!(
+            rejecting
+                .requests()
+                .iter()
+                .filter(|r| r.contains("_flowExecutionKey=e1s1"))
+                .count(),
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn asks_the_timetable_page_for_the_chosen_semester() {
+        let semester_choice = format!(
+            "{FULL_PAGE}<select><option>Sommersemester 2025</option>\
+             <option>Wintersemester 2024/25</option></select>"
+        );
+        let transport = Arc::new(
+            MockTransport::default()
+                .route("hisinoneStartPage", "<html></html>")
+                .route("_flowId=new-flow&_flowExecutionKey=e1s1", &semester_choice)
+                .route("_flowId=new-flow", ENTRY_PAGE)
+                .route("calendarExport.ics", ICS),
+        );
+        let client = UrConnect::with_transport(
+            PortalConfig {
+                base_url: "https://portal.test".to_string(),
+                flow_ids: vec!["new-flow".to_string()],
+                ..PortalConfig::default()
+            },
+            transport.clone(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            client.list_semesters().await.unwrap(),
+            [SemesterId::winter(2024), SemesterId::summer(2025)]
+        );
+
+        let entries = client
+            .get_timetable_for_semester(SemesterId::winter(2024))
+            .await
+            .unwrap();
+        assert_eq!(entries[0].title, "Analysis");
+        assert!(
+            transport
+                .requests()
+                .iter()
+                .any(|r| r.contains("_flowExecutionKey=e1s1&semester=20242"))
+        );
+        assert!(client.flow_cache.lock().unwrap().export.is_none());
+    }
+
+    #[tokio::test]
+    async fn cached_fetch_downloads_the_remembered_export_directly() {
+        let (transport, client) = flaky_client(0, 1);
+        client.get_timetable_cached().await.unwrap();
+        assert_eq!(transport.requests().len(), 4);
+
+        let entries = client.get_timetable_cached().await.unwrap();
+        assert_eq!(entries[0].title, "Analysis");
+        let requests = transport.requests();
+        assert_eq!(requests.len(), 5);
+        assert!(requests[4].contains("calendarExport.ics"));
+    }
+
+    #[tokio::test]
+    async fn cached_fetch_navigates_again_when_the_export_is_unusable() {
+        let (transport, client) = flaky_client(0, 1);
+        let stale = Url::parse("https://portal.test/qisserver/stale.ics").unwrap();
+        client.flow_cache.lock().unwrap().export = Some((stale.clone(), stale));
+
+        let entries = client.get_timetable_cached().await.unwrap();
+        assert_eq!(entries[0].title, "Analysis");
+        let requests = transport.requests();
+        assert_eq!(requests.len(), 5);
+        assert!(requests[0].contains("stale.ics"));
+        let (export, _) = client.flow_cache.lock().unwrap().export.clone().unwrap();
+        assert!(export.path().ends_with("calendarExport.ics"));
+    }
+
+    #[tokio::test]
+    async fn known_flow_key_skips_landing_and_entry_pages() {
+        let (transport, client) = two_flow_client();
+        client.flow_cache.lock().unwrap().flow_id = Some("new-flow".to_string());
+
+        let entries = client.get_timetable_with_flow_key("e1s1").await.unwrap();
+        assert_eq!(entries[0].title, "Analysis");
+
+        let requests = transport.requests();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[0].contains("_flowId=new-flow&_flowExecutionKey=e1s1"));
+        assert!(requests[1].contains("calendarExport.ics"));
+    }
+
+    #[tokio::test]
+    async fn reports_time_spent_in_each_phase() {
+        let transport = Arc::new(
+            MockTransport::default()
+                .route("hisinoneStartPage", "<html><body>Willkommen</body></html>")
+                .route("_flowId=new-flow&_flowExecutionKey=e1s1", FULL_PAGE)
+                .route("_flowId=new-flow", ENTRY_PAGE)
+                .route("calendarExport.ics", ICS)
+                .with_delay(Duration::from_millis(5)),
+        );
+        let config = PortalConfig {
+            base_url: "https://portal.test".to_string(),
+            flow_ids: vec!["new-flow".to_string()],
+            ..PortalConfig::default()
+        };
+        let client = UrConnect::with_transport(config, transport).unwrap();
+
+        let (entries, timings) = client.get_timetable_timed().await.unwrap();
+        assert_eq!(entries.len(), 1);
+        for phase in [
+            timings.landing,
+            timings.flow_resolution,
+            timings.timetable_page,
+            timings.ics_download,
+        ] {
+            assert!(phase >= Duration::from_millis(5), "{timings:?}");
+        }
+        assert!(timings.parse > Duration::ZERO);
+        assert!(timings.total() >= Duration::from_millis(20));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn deadline_names_the_step_it_ran_out_in() {
+        let transport = Arc::new(
+            MockTransport::default()
+                .route("hisinoneStartPage", "<html><body>Willkommen</body></html>")
+                .route("_flowId=new-flow&_flowExecutionKey=e1s1", FULL_PAGE)
+                .route("_flowId=new-flow", ENTRY_PAGE)
+                .route("calendarExport.ics", ICS)
+                .with_delay(Duration::from_millis(80)),
+        );
+        let client = UrConnect::builder()
+            .portal(PortalConfig {
+                base_url: "https://portal.test".to_string(),
+                flow_ids: vec!["new-flow".to_string()],
+                ..PortalConfig::default()
+            })
+            .transport(transport.clone())
+            .deadline(Duration::from_millis(120))
+            .build()
+            .unwrap();
+
+        let err = client.get_timetable().await.unwrap_err();
+        assert!(
+            matches!(
+                err,
+                UrError::Timeout {
+                    step: FetchStep::FlowResolution
+                }
+            ),
+            "{err}"
+        );
+        assert_eq!(err.to_string(), "timed out during the timetable flow page");
+        assert!(err.is_transient());
+        assert!(!transport.requests().iter().any(|r| r.contains("e1s1")));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn deadline_covers_the_cached_export_download() {
+        let transport = Arc::new(
+            MockTransport::default()
+                .route("calendarExport.ics", ICS)
+                .with_delay(Duration::from_millis(200)),
+        );
+        let client = UrConnect::builder()
+            .portal(PortalConfig {
+                base_url: "https://portal.test".to_string(),
+                ..PortalConfig::default()
+            })
+            .transport(transport.clone())
+            .deadline(Duration::from_millis(120))
+            .build()
+            .unwrap();
+        let export = Url::parse("https://portal.test/qisserver/calendarExport.ics").unwrap();
+        client.flow_cache.lock().unwrap().export = Some((export.clone(), export));
+
+        let err = client.get_timetable_cached().await.unwrap_err();
+        assert!(
+            matches!(
+                err,
+                UrError::Timeout {
+                    step: FetchStep::IcsDownload
+                }
+            ),
+            "{err}"
+        );
+        // The download was cut off before answering, and no navigation followed.
+        assert!(transport.requests().is_empty());
+    }
+
+    /// Serves a preloaded response and counts lookups.
+    struct PreloadedCache {
+        response: CachedResponse,
+        max_age: Duration,
+        lookups: Mutex<Vec<Url>>,
+    }
+
+    impl ResponseCache for PreloadedCache {
+        fn get(&self, url: &Url) -> Option<CachedResponse> {
+            self.lookups.lock().unwrap().push(url.clone());
+            Some(self.response.clone())
+        }
+
+        fn put(&self, _url: &Url, _response: CachedResponse) {}
+
+        fn max_age(&self) -> Duration {
+            self.max_age
+        }
+    }
+
+    #[tokio::test]
+    async fn fresh_cache_hit_skips_ics_download() {
+        let (transport, client) = two_flow_client();
+        let cache = Arc::new(PreloadedCache {
+            response: CachedResponse {
+                body: ICS.replace("Analysis", "Cached Analysis"),
+                etag: None,
+                last_modified: None,
+                stored_at: SystemTime::now(),
+            },
+            max_age: Duration::from_secs(300),
+            lookups: Mutex::default(),
+        });
+        let client = client.with_response_cache(cache.clone());
+
+        let entries = client.get_timetable().await.unwrap();
+        assert_eq!(entries[0].title, "Cached Analysis");
+        assert!(
+            transport
+                .requests()
+                .iter()
+                .all(|r| !r.contains("calendarExport.ics"))
+        );
+        assert_eq!(
+            cache.lookups.lock().unwrap().as_slice(),
+            [Url::parse("https://portal.test/qisserver/calendarExport.ics?user=7").unwrap()]
+        );
+    }
+
+    #[tokio::test]
+    async fn stale_cache_entry_is_revalidated_with_its_etag() {
+        let transport = Arc::new(
+            MockTransport::default()
+                .route("hisinoneStartPage", "<html></html>")
+                .route_status("calendarExport.ics", StatusCode::NOT_MODIFIED, "")
+                .route("_flowId=new-flow&_flowExecutionKey=e1s1", FULL_PAGE)
+                .route("_flowId=new-flow", ENTRY_PAGE),
+        );
+        let config = PortalConfig {
+            base_url: "https://portal.test".to_string(),
+            flow_ids: vec!["new-flow".to_string()],
+            ..PortalConfig::default()
+        };
+        let cache = Arc::new(PreloadedCache {
+            response: CachedResponse {
+                body: ICS.to_string(),
+                etag: Some("\"v1\"".to_string()),
+                last_modified: None,
+                stored_at: SystemTime::now(),
+            },
+            max_age: Duration::ZERO,
+            lookups: Mutex::default(),
+        });
+        let client = UrConnect::with_transport(config, transport.clone())
+            .unwrap()
+            .with_response_cache(cache);
+
+        let entries = client.get_timetable().await.unwrap();
+        assert_eq!(entries[0].title, "Analysis");
+        let headers = transport.request_headers();
+        let download = headers.last().unwrap();
+        assert_eq!(download.get(IF_NONE_MATCH).unwrap(), "\"v1\"");
+    }
+
+    #[tokio::test]
+    async fn memory_cache_serves_repeated_fetches() {
+        let (transport, client) = two_flow_client();
+        let cache = Arc::new(MemoryCache::new(Duration::from_secs(300)));
+        let client = client.with_response_cache(cache.clone());
+
+        client.get_timetable().await.unwrap();
+        client.get_timetable().await.unwrap();
+        let downloads = transport
+            .requests()
+            .iter()
+            .filter(|r| r.contains("calendarExport.ics"))
+            .count();
+        assert_eq!(downloads, 1);
+    }
+
+    #[tokio::test]
+    async fn error_statuses_of_the_download_are_not_calendars() {
+        let transport = |status| {
+            Arc::new(
+                MockTransport::default()
+                    .route("hisinoneStartPage", "<html></html>")
+                    .route_status("calendarExport.ics", status, "")
+                    .route("_flowId=new-flow&_flowExecutionKey=e1s1", FULL_PAGE)
+                    .route("_flowId=new-flow", ENTRY_PAGE),
+            )
+        };
+        let config = PortalConfig {
+            base_url: "https://portal.test".to_string(),
+            flow_ids: vec!["new-flow".to_string()],
+            ..PortalConfig::default()
+        };
+
+        let missing =
+            UrConnect::with_transport(config.clone(), transport(StatusCode::NOT_FOUND)).unwrap();
+        let err = missing.get_timetable().await.unwrap_err();
+        assert!(
+            matches!(err, UrError::IcsDownloadFailed { status } if status == StatusCode::NOT_FOUND),
+            "{err}"
+        );
+
+        let cache = Arc::new(MemoryCache::new(Duration::from_secs(300)));
+        let evicted = UrConnect::with_transport(config, transport(StatusCode::NOT_MODIFIED))
+            .unwrap()
+            .with_response_cache(cache);
+        let err = evicted.get_timetable().await.unwrap_err();
+        assert!(
+            matches!(err, UrError::IcsDownloadFailed { status } if status == StatusCode::NOT_MODIFIED),
+            "{err}"
+        );
+    }
+
+    fn flaky_client(failures: u32, max_attempts: u32) -> (Arc<MockTransport>, UrConnect) {
+        failing_client(StatusCode::SERVICE_UNAVAILABLE, failures, max_attempts)
+    }
+
+    fn failing_client(
+        status: StatusCode,
+        failures: u32,
+        max_attempts: u32,
+    ) -> (Arc<MockTransport>, UrConnect) {
+        let transport = Arc::new(
+            MockTransport::default()
+                .route("hisinoneStartPage", "<html></html>")
+                .route("_flowId=new-flow&_flowExecutionKey=e1s1", FULL_PAGE)
+                .route("_flowId=new-flow", ENTRY_PAGE)
+                .failing("calendarExport.ics", status, failures)
+                .route("calendarExport.ics", ICS),
+        );
+        let client = UrConnect::builder()
+            .portal(PortalConfig {
+                base_url: "https://portal.test".to_string(),
+                flow_ids: vec!["new-flow".to_string()],
+                ..PortalConfig::default()
+            })
+            .retry(RetryPolicy {
+                max_attempts,
+                base_delay_ms: 1,
+                jitter_percent: 100,
+            })
+            .transport(transport.clone())
+            .build()
+            .unwrap();
+        (transport, client)
+    }
+
+    #[tokio::test]
+    async fn retries_server_errors_until_the_portal_answers() {
+        let (transport, client) = flaky_client(2, 3);
+        let entries = client.get_timetable().await.unwrap();
+        assert_eq!(entries[0].title, "Analysis");
+        let downloads = transport
+            .requests()
+            .iter()
+            .filter(|r| r.contains("calendarExport.ics"))
+            .count();
+        assert_eq!(downloads, 3);
+
+        let (_, client) = flaky_client(5, 2);
+        let err = client.get_timetable().await.unwrap_err();
+        assert!(
+            matches!(
+                &err,
+                UrError::RetriesExhausted {
+                    attempts: 2,
+                    last_status: Some(StatusCode::SERVICE_UNAVAILABLE),
+                    ..
+                }
+            ),
+            "{err}"
+        );
+        assert!(
+            err.to_string()
+                .contains("after 2 attempts (last status 503")
+        );
+    }
+
+    #[tokio::test]
+    async fn reports_every_request_to_the_observer() {
+        let seen: Arc<Mutex<Vec<RequestInfo>>> = Arc::default();
+        let log = seen.clone();
+        let transport = Arc::new(
+            MockTransport::default()
+                .route("hisinoneStartPage", "<html></html>")
+                .route("_flowId=new-flow&_flowExecutionKey=e1s1", FULL_PAGE)
+                .route("_flowId=new-flow", ENTRY_PAGE)
+                .failing("calendarExport.ics", StatusCode::BAD_GATEWAY, 1)
+                .route("calendarExport.ics", ICS),
+        );
+        let client = UrConnect::builder()
+            .portal(PortalConfig {
+                base_url: "https://portal.test".to_string(),
+                flow_ids: vec!["new-flow".to_string()],
+                ..PortalConfig::default()
+            })
+            .transport(transport)
+            .retry(RetryPolicy {
+                max_attempts: 2,
+                base_delay_ms: 1,
+                jitter_percent: 0,
+            })
+            .request_observer(move |info| log.lock().unwrap().push(info.clone()))
+            .build()
+            .unwrap();
+
+        client.get_timetable().await.unwrap();
+
+        let seen = seen.lock().unwrap();
+        let summary: Vec<(&str, Option<StatusCode>, u32)> = seen
+            .iter()
+            .map(|info| (info.method, info.status, info.attempt))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("GET", Some(StatusCode::OK), 1),
+                ("GET", Some(StatusCode::OK), 1),
+                ("GET", Some(StatusCode::OK), 1),
+                ("GET", Some(StatusCode::BAD_GATEWAY), 1),
+                ("GET", Some(StatusCode::OK), 2),
+            ]
+        );
+        assert!(seen[0].url.path().ends_with("hisinoneStartPage.faces"));
+        assert!(seen[4].url.path().ends_with("calendarExport.ics"));
+    }
+
+    #[tokio::test]
+    async fn collects_metrics_of_one_fetch() {
+        let (_, client) = flaky_client(1, 2);
+
+        let (entries, metrics) = client.get_timetable_with_metrics().await.unwrap();
+        assert_eq!(entries[0].title, "Analysis");
+        assert_eq!(metrics.requests.len(), 5);
+        assert_eq!(metrics.retries, 1);
+        assert_eq!(
+            metrics.bytes_downloaded,
+            ("<html></html>".len() + ENTRY_PAGE.len() + FULL_PAGE.len() + ICS.len()) as u64
+        );
+        assert_eq!(metrics.source, Some(CalendarSource::FullTimetablePage));
+
+        // Calls without metrics leave nothing behind for the next one.
+        client.get_timetable().await.unwrap();
+        let (_, metrics) = client.get_timetable_with_metrics().await.unwrap();
+        assert_eq!(metrics.requests.len(), 4);
+        assert_eq!(metrics.retries, 0);
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_client_errors() {
+        for status in [StatusCode::UNAUTHORIZED, StatusCode::FORBIDDEN] {
+            let (transport, client) = failing_client(status, 1, 3);
+            assert!(client.get_timetable().await.is_err());
+            let downloads = transport
+                .requests()
+                .iter()
+                .filter(|r| r.contains("calendarExport.ics"))
+                .count();
+            assert_eq!(downloads, 1, "{status}");
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn waits_as_long_as_retry_after_asks_then_retries_once() {
+        let rate_limited = |retry_after: &str, times: u32, retry: RetryPolicy| {
+            let transport = Arc::new(
+                MockTransport::default()
+                    .route("hisinoneStartPage", "<html></html>")
+                    .route("_flowId=new-flow&_flowExecutionKey=e1s1", FULL_PAGE)
+                    .route("_flowId=new-flow", ENTRY_PAGE)
+                    .failing_with_headers(
+                        "calendarExport.ics",
+                        StatusCode::TOO_MANY_REQUESTS,
+                        HeaderMap::from_iter([(
+                            RETRY_AFTER,
+                            HeaderValue::from_str(retry_after).unwrap(),
+                        )]),
+                        times,
+                    )
+                    .route("calendarExport.ics", ICS),
+            );
+            let client = UrConnect::builder()
+                .portal(PortalConfig {
+                    base_url: "https://portal.test".to_string(),
+                    flow_ids: vec!["new-flow".to_string()],
+                    ..PortalConfig::default()
+                })
+                .transport(transport.clone())
+                .retry(retry)
+                .build()
+                .unwrap();
+            (transport, client)
+        };
+        let downloads = |transport: &MockTransport| {
+            transport
+                .requests()
+                .iter()
+                .filter(|r| r.contains("calendarExport.ics"))
+                .count()
+        };
+
+        let (transport, client) = rate_limited("30", 1, RetryPolicy::default());
+        let started = tokio::time::Instant::now();
+        assert_eq!(client.get_timetable().await.unwrap()[0].title, "Analysis");
+        assert!(started.elapsed() >= Duration::from_secs(30));
+        assert_eq!(downloads(&transport), 2);
+
+        let (transport, client) = rate_limited("0", 1, RetryPolicy::never());
+        assert_eq!(client.get_timetable().await.unwrap()[0].title, "Analysis");
+        assert_eq!(downloads(&transport), 2);
+
+        let (_, client) = rate_limited("0", 2, RetryPolicy::default());
+        let err = client.get_timetable().await.unwrap_err();
+        assert!(
+            matches!(
+                &err,
+                UrError::RetriesExhausted {
+                    attempts: 2,
+                    last_status: Some(StatusCode::TOO_MANY_REQUESTS),
+                    ..
+                }
+            ),
+            "{err}"
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn rate_limit_retry_comes_on_top_of_the_policy() {
+        let limited_client = |server_errors: u32, retry: RetryPolicy| {
+            let transport = Arc::new(
+                MockTransport::default()
+                    .route("hisinoneStartPage", "<html></html>")
+                    .route("_flowId=new-flow&_flowExecutionKey=e1s1", FULL_PAGE)
+                    .route("_flowId=new-flow", ENTRY_PAGE)
+                    .failing_with_headers(
+                        "calendarExport.ics",
+                        StatusCode::TOO_MANY_REQUESTS,
+                        HeaderMap::from_iter([(RETRY_AFTER, HeaderValue::from_static("0"))]),
+                        if server_errors == 0 { 2 } else { 1 },
+                    )
+                    .failing(
+                        "calendarExport.ics",
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        server_errors,
+                    )
+                    .route("calendarExport.ics", ICS),
+            );
+            let client = UrConnect::builder()
+                .portal(PortalConfig {
+                    base_url: "https://portal.test".to_string(),
+                    flow_ids: vec!["new-flow".to_string()],
+                    ..PortalConfig::default()
+                })
+                .transport(transport.clone())
+                .retry(retry)
+                .build()
+                .unwrap();
+            (transport, client)
+        };
+        let downloads = |transport: &MockTransport| {
+            transport
+                .requests()
+                .iter()
+                .filter(|r| r.contains("calendarExport.ics"))
+                .count()
+        };
+
+        // 429, 500, 500, then the calendar: three attempts plus the rate-limit retry.
+        let three_attempts = RetryPolicy {
+            max_attempts: 3,
+            ..RetryPolicy::default()
+        };
+        let (transport, client) = limited_client(2, three_attempts);
+        assert_eq!(client.get_timetable().await.unwrap().len(), 1);
+        assert_eq!(downloads(&transport), 4);
+
+        // A second 429 gives up even when the policy never retries.
+        let (transport, client) = limited_client(0, RetryPolicy::never());
+        let err = client.get_timetable().await.unwrap_err();
+        assert!(
+            matches!(
+                &err,
+                UrError::RetriesExhausted {
+                    attempts: 2,
+                    last_status: Some(StatusCode::TOO_MANY_REQUESTS),
+                    ..
+                }
+            ),
+            "{err}"
+        );
+        assert_eq!(downloads(&transport), 2);
+    }
+
+    #[test]
+    fn reads_retry_after_as_seconds_or_date() {
+        let headers = |value: &str| HeaderMap::from_iter([(RETRY_AFTER, value.parse().unwrap())]);
+        assert_eq!(retry_after(&headers("7")), Some(Duration::from_secs(7)));
+        assert_eq!(retry_after(&headers("86400")), Some(MAX_RETRY_AFTER));
+        assert_eq!(
+            retry_after(&headers("Wed, 21 Oct 2015 07:28:00 GMT")),
+            Some(Duration::ZERO)
+        );
+        let soon = (Utc::now() + chrono::Duration::seconds(30)).to_rfc2822();
+        let delay = retry_after(&headers(&soon)).unwrap();
+        assert!(delay > Duration::from_secs(25) && delay <= Duration::from_secs(30));
+        assert_eq!(retry_after(&headers("soon")), None);
+        assert_eq!(retry_after(&HeaderMap::new()), None);
+    }
+
+    #[tokio::test]
+    async fn spaces_requests_of_tasks_sharing_the_client() {
+        let transport = Arc::new(
+            MockTransport::default()
+                .route("hisinoneStartPage", "<html></html>")
+                .route("_flowId=new-flow&_flowExecutionKey=e1s1", FULL_PAGE)
+                .route("_flowId=new-flow", ENTRY_PAGE)
+                .route("calendarExport.ics", ICS),
+        );
+        let client = UrConnect::builder()
+            .portal(PortalConfig {
+                base_url: "https://portal.test".to_string(),
+                flow_ids: vec!["new-flow".to_string()],
+                ..PortalConfig::default()
+            })
+            .transport(transport.clone())
+            .min_request_interval(Duration::from_millis(40))
+            .build()
+            .unwrap();
+
+        let started = Instant::now();
+        let (first, second) = tokio::join!(client.get_timetable(), client.get_timetable());
+        assert!(first.is_ok() && second.is_ok());
+        let requests = transport.requests().len() as u32;
+        assert!(requests >= 8);
+        assert!(started.elapsed() >= Duration::from_millis(40) * (requests - 1));
+    }
+
+    #[tokio::test]
+    async fn never_repeats_the_login_post() {
+        let transport = Arc::new(
+            MockTransport::default()
+                .route(
+                    "hisinoneStartPage",
+                    include_str!("../tests/fixtures/login.html"),
+                )
+                .route_status("POST", StatusCode::BAD_GATEWAY, ""),
+        );
+        let client = UrConnect::builder()
+            .base_url("https://portal.test")
+            .transport(transport.clone())
+            .build()
+            .unwrap();
+
+        let err = client.login("student", "secret").await.unwrap_err();
+        assert!(
+            matches!(err, UrError::LoginFailed { status } if status == StatusCode::BAD_GATEWAY)
+        );
+        assert_eq!(transport.posted_forms().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn fails_fast_without_credential_fields() {
+        let transport = Arc::new(MockTransport::default().route(
+            "hisinoneStartPage",
+            r#"<html><body><form action="/rds"><input type="hidden" name="ajax-token" value="t"/>
+            <input type="password" name="pin"/></form></body></html>"#,
+        ));
+        let client = UrConnect::builder()
+            .base_url("https://portal.test")
+            .transport(transport.clone())
+            .build()
+            .unwrap();
+
+        let err = client.login("student", "secret").await.unwrap_err();
+        assert!(
+            matches!(&err, UrError::LoginFormNotFound { url } if url.as_str().contains("hisinoneStartPage")),
+            "{err}"
+        );
+        assert!(transport.posted_forms().is_empty());
+    }
+
+    #[tokio::test]
+    async fn posts_no_credentials_to_a_challenge_page() {
+        let transport = Arc::new(MockTransport::default().route(
+            "hisinoneStartPage",
+            include_str!("../tests/fixtures/challenge.html"),
+        ));
+        let client = UrConnect::builder()
+            .base_url("https://portal.test")
+            .transport(transport.clone())
+            .build()
+            .unwrap();
+
+        let err = client.login("student", "secret").await.unwrap_err();
+        assert!(matches!(err, UrError::RateLimited), "{err}");
+        assert!(transport.posted_forms().is_empty());
+    }
+
+    #[tokio::test]
+    async fn reports_maintenance_on_login_and_fetch() {
+        let transport = Arc::new(MockTransport::default().route(
+            "hisinoneStartPage",
+            include_str!("../tests/fixtures/maintenance.html"),
+        ));
+        let client = UrConnect::builder()
+            .base_url("https://portal.test")
+            .transport(transport.clone())
+            .build()
+            .unwrap();
+        let announced =
+            chrono::NaiveDate::from_ymd_opt(2026, 10, 14).and_then(|d| d.and_hms_opt(6, 0, 0));
+
+        let err = client.login("student", "secret").await.unwrap_err();
+        assert!(
+            matches!(err, UrError::PortalMaintenance { until } if until == announced),
+            "{err}"
+        );
+        assert_eq!(
+            err.to_string(),
+            "the portal is down for maintenance until 2026-10-14 06:00"
+        );
+        assert!(transport.posted_forms().is_empty());
+
+        let err = client.get_timetable().await.unwrap_err();
+        assert!(matches!(err, UrError::PortalMaintenance { .. }), "{err}");
+    }
+
+    const IDP_LOGIN: &str = r#"<html><body>
+        <form action="/idp/profile/SAML2/Redirect/SSO?execution=e1s2" method="post">
+          <input type="hidden" name="csrf_token" value="_c5a1"/>
+          <input type="text" name="j_username"/>
+          <input type="password" name="j_password"/>
+          <button type="submit" name="_eventId_proceed">Login</button>
+        </form></body></html>"#;
+    const IDP_SAML_RESPONSE: &str = r#"<html><body onload="document.forms[0].submit()">
+        <form action="https://portal.test/Shibboleth.sso/SAML2/POST" method="post">
+          <input type="hidden" name="RelayState" value="ss:mem:42"/>
+          <input type="hidden" name="SAMLResponse" value="PHNhbWxwOlJlc3BvbnNlLz4="/>
+        </form></body></html>"#;
+
+    fn idp_client(idp_answer: &str) -> (Arc<MockTransport>, UrConnect) {
+        let transport = Arc::new(
+            MockTransport::default()
+                .route(
+                    "hisinoneStartPage",
+                    include_str!("../tests/fixtures/login.html"),
+                )
+                .redirect(
+                    "POST https://portal.test/qisserver/rds",
+                    "https://idp.test/idp/profile/SAML2/Redirect/SSO?execution=e1s1",
+                )
+                .route("GET https://idp.test/idp/profile", IDP_LOGIN)
+                .route("POST https://idp.test/idp/profile", idp_answer)
+                .route(
+                    "POST https://portal.test/Shibboleth.sso/SAML2/POST",
+                    include_str!("../tests/fixtures/landing.html"),
+                ),
+        );
+        let config = PortalConfig {
+            base_url: "https://portal.test".to_string(),
+            ..PortalConfig::default()
+        };
+        let client = UrConnect::with_transport(config, transport.clone()).unwrap();
+        (transport, client)
+    }
+
+    #[tokio::test]
+    async fn completes_login_through_external_identity_provider() {
+        let (transport, client) = idp_client(IDP_SAML_RESPONSE);
+        client.login("student", "secret").await.unwrap();
+
+        let forms = transport.posted_forms();
+        let (idp_request, idp_form) = &forms[1];
+        assert!(idp_request.contains("idp.test/idp/profile/SAML2/Redirect/SSO?execution=e1s2"));
+        for field in [
+            ("csrf_token", "_c5a1"),
+            ("_eventId_proceed", ""),
+            ("j_username", "student"),
+            ("j_password", "secret"),
+        ] {
+            assert!(
+                idp_form.contains(&(field.0.to_string(), field.1.to_string())),
+                "{field:?}"
+            );
+        }
+        let (acs_request, acs_form) = &forms[2];
+        assert_eq!(
+            acs_request,
+            "POST https://portal.test/Shibboleth.sso/SAML2/POST"
+        );
+        assert!(acs_form.iter().any(|(name, _)| name == "SAMLResponse"));
+        assert!(acs_form.iter().any(|(name, _)| name == "RelayState"));
+    }
+
+    #[tokio::test]
+    async fn reports_credentials_rejected_by_identity_provider() {
+        let rejected = IDP_LOGIN.replace(
+            "<button",
+            r#"<p class="form-element form-error">The password you entered was incorrect.</p><button"#,
+        );
+        let (_, client) = idp_client(&rejected);
+        let err = client.login("student", "wrong").await.unwrap_err();
+        assert!(
+            matches!(&err, UrError::IdpRejected { message: Some(message) }
+                if message == "The password you entered was incorrect."),
+            "{err}"
+        );
+    }
+
+    const OTP_PAGE: &str = r#"<html><body>
+        <span id="contextInformation" data-user-logged-in="false"></span>
+        <form action="/qisserver/pages/cs/sys/portal/secondFactor.faces" method="post">
+          <input type="hidden" name="ajax-token" value="t1"/>
+          <label for="otp">Einmalpasswort</label>
+          <input type="text" id="otp" name="loginForm:otp" autocomplete="one-time-code"/>
+          <button type="submit" name="loginForm:confirm">Bestätigen</button>
+        </form></body></html>"#;
+
+    fn otp_client(otp_answer: &str) -> (Arc<MockTransport>, UrConnect) {
+        let transport = Arc::new(
+            MockTransport::default()
+                .route(
+                    "hisinoneStartPage",
+                    include_str!("../tests/fixtures/login.html"),
+                )
+                .route("POST https://portal.test/qisserver/rds", OTP_PAGE)
+                .route(
+                    "POST https://portal.test/qisserver/pages/cs/sys/portal/secondFactor",
+                    otp_answer,
+                ),
+        );
+        let config = PortalConfig {
+            base_url: "https://portal.test".to_string(),
+            ..PortalConfig::default()
+        };
+        let client = UrConnect::with_transport(config, transport.clone()).unwrap();
+        (transport, client)
+    }
+
+    #[tokio::test]
+    async fn submits_one_time_code_when_asked() {
+        let (transport, client) = otp_client(include_str!("../tests/fixtures/landing.html"));
+        assert!(matches!(
+            client.login("student", "secret").await,
+            Err(UrError::SecondFactorRequired)
+        ));
+
+        client
+            .login_with_otp("student", "secret", " 123456 ")
+            .await
+            .unwrap();
+        let (_, form) = transport.posted_forms().pop().unwrap();
+        assert!(form.contains(&("loginForm:otp".to_string(), "123456".to_string())));
+        assert!(form.contains(&("ajax-token".to_string(), "t1".to_string())));
+    }
+
+    #[tokio::test]
+    async fn reports_rejected_one_time_code() {
+        let rejected = OTP_PAGE.replace(
+            "<form",
+            r#"<div class="messages"><p class="error">Das Einmalpasswort ist ungültig.</p></div><form"#,
+        );
+        let (_, client) = otp_client(&rejected);
+        let err = client
+            .login_with_otp("student", "secret", "000000")
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(&err, UrError::InvalidOtp { message: Some(message) }
+                if message == "Das Einmalpasswort ist ungültig."),
+            "{err}"
+        );
+    }
+
+    #[tokio::test]
+    async fn raw_download_reports_missing_or_empty_exports() {
+        let empty = Arc::new(
+            MockTransport::default()
+                .route("hisinoneStartPage", "<html></html>")
+                .route("_flowExecutionKey=e1s1", FULL_PAGE)
+                .route("_flowId=", ENTRY_PAGE)
+                .route("calendarExport.ics", "BEGIN:VCALENDAR\nEND:VCALENDAR"),
+        );
+        let client = UrConnect::with_transport(PortalConfig::default(), empty).unwrap();
+        assert!(matches!(
+            client.download_ics().await,
+            Err(UrError::EmptyTimetable)
+        ));
+
+        let no_export = Arc::new(
+            MockTransport::default()
+                .route("hisinoneStartPage", "<html></html>")
+                .route("_flowExecutionKey=e1s1", "<html>no export</html>")
+                .route("_flowId=", ENTRY_PAGE),
+        );
+        let client = UrConnect::with_transport(PortalConfig::default(), no_export).unwrap();
+        assert!(matches!(
+            client.download_ics().await,
+            Err(UrError::IcsUrlNotFound)
+        ));
+
+        let error_page = Arc::new(
+            MockTransport::default()
+                .route("hisinoneStartPage", "<html></html>")
+                .route("_flowExecutionKey=e1s1", FULL_PAGE)
+                .route("_flowId=", ENTRY_PAGE)
+                .route(
+                    "calendarExport.ics",
+                    "<!DOCTYPE html><html><body>Interner Fehler</body></html>",
+                ),
+        );
+        let client = UrConnect::with_transport(PortalConfig::default(), error_page).unwrap();
+        let err = client.download_ics().await.unwrap_err();
+        assert!(
+            matches!(&err, UrError::IcsDownloadReturnedHtml { final_url }
+                if final_url.path().ends_with("calendarExport.ics")),
+            "{err}"
+        );
+        assert!(matches!(
+            client.get_timetable().await,
+            Err(UrError::IcsDownloadReturnedHtml { .. })
+        ));
+    }
+
+    #[test]
+    fn tells_calendars_from_html_pages() {
+        let response = |body: &str, content_type: Option<&str>| {
+            let mut headers = HeaderMap::new();
+            if let Some(content_type) = content_type {
+                headers.insert(CONTENT_TYPE, HeaderValue::from_str(content_type).unwrap());
+            }
+            FetchResult {
+                body: body.to_string(),
+                final_url: Url::parse("https://portal.test/qisserver/login").unwrap(),
+                status: StatusCode::OK,
+                headers,
+                refreshed_from: Vec::new(),
+            }
+        };
+
+        let login = response(
+            include_str!("../tests/fixtures/login.html"),
+            Some("text/html;charset=UTF-8"),
+        );
+        let err = ensure_calendar(&login).unwrap_err();
+        assert!(
+            matches!(&err, UrError::IcsDownloadReturnedHtml { final_url }
+                if final_url.path() == "/qisserver/login"),
+            "{err}"
+        );
+        assert!(err.to_string().contains("session may have expired"));
+
+        assert!(ensure_calendar(&response("\u{feff}begin:vcalendar\r\n", None)).is_ok());
+        assert!(ensure_calendar(&response(ICS, Some("text/calendar"))).is_ok());
+        assert!(ensure_calendar(&response("", None)).is_err());
+        assert!(may_be_html(&login) && may_be_html(&response("", None)));
+        assert!(!may_be_html(&response(
+            ICS,
+            Some("text/calendar; charset=utf-8")
+        )));
+        assert!(
+            ensure_calendar(&response(r#"{"error":"denied"}"#, Some("application/json"))).is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn reports_attempted_flow_ids_when_none_work() {
+        let transport = Arc::new(
+            MockTransport::default()
+                .route("hisinoneStartPage", "<html></html>")
+                .route("_flowId=", FLOW_NOT_FOUND),
+        );
+        let config = PortalConfig {
+            base_url: "https://portal.test".to_string(),
+            flow_ids: vec!["a-flow".to_string(), "b-flow".to_string()],
+            ..PortalConfig::default()
+        };
+        let client = UrConnect::with_transport(config, transport).unwrap();
+        let err = client.get_timetable().await.unwrap_err();
+        assert!(
+            matches!(&err, UrError::FlowNotRecognised { attempted } if attempted.len() == 2),
+            "{err}"
+        );
+        assert!(err.to_string().contains("a-flow, b-flow"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn submits_timetable_selection_before_ics_discovery() {
+        let transport = Arc::new(
+            MockTransport::default()
+                .route("hisinoneStartPage", "<html><body>Willkommen</body></html>")
+                .route(
+                    "individualTimetableCalendarExport",
+                    include_str!("../tests/fixtures/timetable.ics"),
+                )
+                .route(
+                    "POST https://campusportal.ur.de/qisserver/pages/plan/individualTimetable.xhtml",
+                    include_str!("../tests/fixtures/timetable_full.html"),
+                )
+                .route(
+                    "_flowExecutionKey=e1s2",
+                    include_str!("../tests/fixtures/timetable_full.html"),
+                )
+                .route(
+                    "_flowId=individualTimetableSchedule-flow",
+                    include_str!("../tests/fixtures/timetable_selection.html"),
+                ),
+        );
+        let client = UrConnect::with_transport(PortalConfig::default(), transport.clone()).unwrap();
+
+        let entries = client.get_timetable().await.unwrap();
+        assert_eq!(entries.len(), 3);
+        let requests = transport.requests();
+        assert!(requests.iter().any(|r| r.starts_with("POST ")));
+        assert!(
+            requests
+                .last()
+                .unwrap()
+                .contains("individualTimetableCalendarExport")
+        );
+    }
+
     #[test]
     fn calendar_hint_matches_variants() {
         assert!(contains_calendar_hint("individualTimetableCalendarExport"));
@@ -0,0 +1,283 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    hash::{BuildHasher, RandomState},
+    time::Duration,
+};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "serde")]
+use crate::error::UrError;
+
+/// The origin of the University of Regensburg portal.
+pub const DEFAULT_BASE_URL: &str = "https://campusportal.ur.de";
+
+/// Flow ids the UR portal has used for the personal timetable, newest first.
+pub const DEFAULT_FLOW_IDS: &[&str] = &["individualTimetableSchedule-flow", "scheduler-flow"];
+
+/// The browser `User-Agent` sent by default; append an identifier to it with
+/// [`UrConnectBuilder::user_agent`](crate::UrConnectBuilder::user_agent).
+pub const DEFAULT_USER_AGENT: &str =
+    "Mozilla/5.0 (Macintosh; Intel Mac OS X 10.15; rv:128.0) Gecko/20100101 Firefox/128.0";
+
+/// Locations of the HISinOne pages the client navigates.
+///
+/// Paths are joined onto `base_url`; the defaults describe the University of Regensburg portal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(default))]
+pub struct PortalConfig {
+    pub base_url: String,
+    pub start_page_path: String,
+    pub login_path: String,
+    /// The `auth.logout` page that ends the session.
+    pub logout_path: String,
+    pub timetable_path: String,
+    /// Timetable flow ids tried in order until the portal accepts one.
+    pub flow_ids: Vec<String>,
+    /// Follow the "My Studies" submenu when the landing page has no direct timetable link.
+    pub follow_submenu: bool,
+    /// The timetable page query parameter set to a
+    /// [`SemesterId::term_key`](crate::SemesterId::term_key) by
+    /// [`UrConnect::get_timetable_for_semester`](crate::UrConnect::get_timetable_for_semester).
+    pub semester_param: String,
+}
+
+impl Default for PortalConfig {
+    fn default() -> Self {
+        Self {
+            base_url: DEFAULT_BASE_URL.to_string(),
+            start_page_path: "/qisserver/pages/cs/sys/portal/hisinoneStartPage.faces".to_string(),
+            login_path: "/qisserver/rds?state=user&type=1&category=auth.login".to_string(),
+            logout_path: "/qisserver/rds?state=user&type=4&category=auth.logout".to_string(),
+            timetable_path: "/qisserver/pages/plan/individualTimetable.xhtml".to_string(),
+            flow_ids: DEFAULT_FLOW_IDS.iter().map(|id| id.to_string()).collect(),
+            follow_submenu: false,
+            semester_param: "semester".to_string(),
+        }
+    }
+}
+
+/// Everything needed to build a [`UrConnect`](crate::UrConnect) with its own HTTP client.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(default))]
+pub struct UrConnectConfig {
+    pub portal: PortalConfig,
+    /// Per-request timeout in seconds.
+    pub timeout_secs: u64,
+    /// Timeout for establishing a connection, in seconds.
+    pub connect_timeout_secs: u64,
+    /// Time allowed for a whole timetable fetch, in seconds; unlimited when `None`.
+    pub deadline_secs: Option<u64>,
+    pub retry: RetryPolicy,
+    /// Minimum pause between two requests in milliseconds, across all tasks sharing the
+    /// client; 0 (the default) sends them back to back.
+    pub min_request_interval_ms: u64,
+    /// A proxy every request is sent through, overriding the environment.
+    pub proxy: Option<ProxyConfig>,
+    /// Without an explicit `proxy`, use the one named by `HTTPS_PROXY`/`HTTP_PROXY`/
+    /// `ALL_PROXY`, skipping hosts listed in `NO_PROXY`. On by default.
+    pub use_env_proxy: bool,
+    /// PEM certificates trusted as roots in addition to the built-in ones, e.g. the private
+    /// root CA of a TLS-intercepting network.
+    pub root_certificates: Vec<String>,
+    /// Accepts any server certificate, expired or forged ones included. Only for lab setups.
+    pub danger_accept_invalid_certs: bool,
+    /// Headers sent with every request, by name.
+    pub headers: BTreeMap<String, String>,
+}
+
+impl Default for UrConnectConfig {
+    fn default() -> Self {
+        let headers = [
+            (
+                "Accept",
+                "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8",
+            ),
+            ("Accept-Language", "de-DE,de;q=0.9,en;q=0.5"),
+            ("Connection", "keep-alive"),
+            ("User-Agent", DEFAULT_USER_AGENT),
+        ];
+        Self {
+            portal: PortalConfig::default(),
+            timeout_secs: 60,
+            connect_timeout_secs: 10,
+            deadline_secs: None,
+            retry: RetryPolicy::default(),
+            min_request_interval_ms: 0,
+            proxy: None,
+            use_env_proxy: true,
+            root_certificates: Vec::new(),
+            danger_accept_invalid_certs: false,
+            headers: headers
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+        }
+    }
+}
+
+/// An HTTP(S) proxy, e.g. `http://proxy.uni-regensburg.de:3128`.
+///
+/// `Debug` hides the password.
+#[derive(Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ProxyConfig {
+    pub url: String,
+    /// Basic authentication for the proxy; credentials in `url` work as well.
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+impl ProxyConfig {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            username: None,
+            password: None,
+        }
+    }
+}
+
+impl fmt::Debug for ProxyConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProxyConfig")
+            .field("url", &self.url)
+            .field("username", &self.username)
+            .field("password", &self.password.as_ref().map(|_| "<redacted>"))
+            .finish()
+    }
+}
+
+/// How requests failing with a server error (5xx), a timeout or a dropped connection are
+/// repeated.
+///
+/// A `429 Too Many Requests` is retried once on top of [`max_attempts`](Self::max_attempts),
+/// even under [`never`](Self::never), after the pause its `Retry-After` header asks for (at
+/// most a minute); without the header the usual backoff applies.
+///
+/// Forms carrying credentials or one-time codes are never sent twice, so that a flaky portal
+/// cannot lock the account.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(default))]
+pub struct RetryPolicy {
+    /// Attempts per request, the first one included; 1 disables retrying all but a `429`.
+    pub max_attempts: u32,
+    /// Delay before the first retry in milliseconds; it doubles with every further retry.
+    pub base_delay_ms: u64,
+    /// Up to this share of each delay, in percent, is added at random so that clients failing
+    /// together do not retry in lockstep.
+    pub jitter_percent: u32,
+}
+
+impl RetryPolicy {
+    /// Sends every request once, except for the single retry of a `429`.
+    pub fn never() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Self::default()
+        }
+    }
+
+    /// The pause before retry number `retry` (counting from 1).
+    pub fn delay_before(&self, retry: u32) -> Duration {
+        let base = self
+            .base_delay_ms
+            .saturating_mul(1 << retry.saturating_sub(1).min(16));
+        let jitter = base.saturating_mul(u64::from(self.jitter_percent.min(100))) / 100;
+        let random = RandomState::new().hash_one(retry);
+        Duration::from_millis(base + random % (jitter + 1))
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay_ms: 500,
+            jitter_percent: 20,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl UrConnectConfig {
+    /// Renders the configuration as TOML; omitted keys fall back to the defaults on import.
+    pub fn to_toml(&self) -> Result<String, UrError> {
+        toml::to_string_pretty(self).map_err(|source| UrError::Serialize {
+            context: "failed to serialize client configuration as TOML".to_string(),
+            source,
+        })
+    }
+
+    pub fn from_toml(input: &str) -> Result<Self, UrError> {
+        toml::from_str(input).map_err(|err| {
+            UrError::Parse(format!("failed to parse client configuration TOML: {err}"))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "serde")]
+    use crate::client::UrConnect;
+
+    #[test]
+    fn retry_delays_double_and_stay_within_the_jitter() {
+        let policy = RetryPolicy {
+            max_attempts: 5,
+            base_delay_ms: 100,
+            jitter_percent: 50,
+        };
+        for (retry, base) in [(1, 100), (2, 200), (3, 400)] {
+            let delay = policy.delay_before(retry).as_millis();
+            assert!((base..=base * 3 / 2).contains(&delay), "{retry}: {delay}");
+        }
+        let exact = RetryPolicy {
+            jitter_percent: 0,
+            ..policy
+        };
+        assert_eq!(exact.delay_before(4), Duration::from_millis(800));
+        assert_eq!(
+            exact.delay_before(u32::MAX),
+            Duration::from_millis(100 << 16)
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn toml_roundtrip_builds_a_client() {
+        let mut config = UrConnectConfig {
+            timeout_secs: 15,
+            ..UrConnectConfig::default()
+        };
+        config.portal.base_url = "https://portal.example.org".to_string();
+        config.portal.flow_ids = vec!["scheduler-flow".to_string()];
+        config.portal.follow_submenu = true;
+        config
+            .headers
+            .insert("Accept-Language".to_string(), "de-DE,de;q=0.9".to_string());
+
+        let restored = UrConnectConfig::from_toml(&config.to_toml().unwrap()).unwrap();
+        assert_eq!(restored, config);
+        assert!(UrConnect::from_config(&restored).is_ok());
+
+        let partial = UrConnectConfig::from_toml(
+            "timeout_secs = 5\n[portal]\nbase_url = \"https://portal.example.org\"\n",
+        )
+        .unwrap();
+        assert_eq!(
+            partial.portal.start_page_path,
+            PortalConfig::default().start_page_path
+        );
+        assert_eq!(partial.headers, UrConnectConfig::default().headers);
+
+        let mut invalid = config;
+        invalid
+            .headers
+            .insert("Bad Header".to_string(), "x".to_string());
+        assert!(UrConnect::from_config(&invalid).is_err());
+    }
+}
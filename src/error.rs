@@ -0,0 +1,246 @@
+//! The error type returned by the client.
+
+use std::fmt;
+
+use reqwest::StatusCode;
+use thiserror::Error;
+
+/// Everything that can go wrong while talking to the portal.
+///
+/// The variants are coarse on purpose: they tell a caller whether retrying, asking for a new
+/// password or giving up makes sense. The `Display` text names the step that failed; the
+/// underlying cause, if any, is available through [`std::error::Error::source`].
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum UrError {
+    /// A request could not be sent or its response could not be read.
+    #[error("{context}")]
+    Network {
+        context: String,
+        #[source]
+        source: reqwest::Error,
+    },
+    /// A custom [`HttpTransport`](crate::transport::HttpTransport) failed.
+    #[error("{context}")]
+    Transport {
+        context: String,
+        #[source]
+        source: anyhow::Error,
+    },
+    /// The portal URLs, flow ids or headers are unusable.
+    #[error("{0}")]
+    Config(String),
+    /// A credentials environment variable is unset, empty or not valid UTF-8.
+    #[error("environment variable {name} is not set or empty")]
+    MissingEnvVar { name: String },
+    /// The proxy answered with `407 Proxy Authentication Required`: the proxy credentials
+    /// are missing or wrong.
+    #[error("the proxy rejected the request: proxy authentication required")]
+    ProxyAuthRequired,
+    #[error("login failed with status {status}")]
+    LoginFailed { status: StatusCode },
+    #[error("logout failed with status {status}")]
+    LogoutFailed { status: StatusCode },
+    /// The portal answered the login with its login form again; `message` is the error banner
+    /// it showed, if any.
+    #[error(
+        "invalid login credentials{}",
+        message.as_deref().map(|m| format!(": {m}")).unwrap_or_default()
+    )]
+    InvalidCredentials { message: Option<String> },
+    /// The portal locked the account after too many failed logins; `message` is the banner
+    /// it showed, if any.
+    #[error(
+        "the account is locked{}",
+        message.as_deref().map(|m| format!(": {m}")).unwrap_or_default()
+    )]
+    AccountLocked { message: Option<String> },
+    /// The page expected to hold the login form has no username and password inputs.
+    #[error("no login form with username and password fields found at {url}")]
+    LoginFormNotFound { url: reqwest::Url },
+    /// The login form asks for a CAPTCHA; log in through a browser once before retrying.
+    #[error("the portal asks for a CAPTCHA")]
+    CaptchaRequired,
+    /// The portal is down for maintenance; `until` is the end it announced, in the portal's
+    /// local time, if it named one.
+    #[error(
+        "the portal is down for maintenance{}",
+        until.map(|t| format!(" until {}", t.format("%Y-%m-%d %H:%M"))).unwrap_or_default()
+    )]
+    PortalMaintenance {
+        until: Option<chrono::NaiveDateTime>,
+    },
+    /// The portal served a bot challenge or rate-limit page instead of its login form, usually
+    /// after several quick logins; wait a few minutes before trying again.
+    #[error("the portal answered with a challenge page; too many requests, try again later")]
+    RateLimited,
+    /// The external identity provider did not accept the credentials; `message` is the error
+    /// it showed, if any.
+    #[error(
+        "identity provider rejected the login{}",
+        message.as_deref().map(|m| format!(": {m}")).unwrap_or_default()
+    )]
+    IdpRejected { message: Option<String> },
+    /// The account has two-factor authentication enabled; log in with
+    /// [`UrConnect::login_with_otp`](crate::UrConnect::login_with_otp) instead.
+    #[error("the portal asks for a one-time code")]
+    SecondFactorRequired,
+    /// The one-time code was wrong or expired; asking for a fresh one may help.
+    #[error(
+        "one-time code was not accepted{}",
+        message.as_deref().map(|m| format!(": {m}")).unwrap_or_default()
+    )]
+    InvalidOtp { message: Option<String> },
+    /// The portal showed its login form where a logged-in page was expected, e.g. because a
+    /// restored session timed out.
+    #[error("the portal session has expired; log in again")]
+    SessionExpired,
+    /// Every configured flow id led to a "flow not found" page.
+    #[error("portal did not recognise any timetable flow id (tried {})", attempted.join(", "))]
+    FlowNotRecognised { attempted: Vec<String> },
+    /// A request of `step` ran into the request timeout, or the fetch as a whole ran past its
+    /// deadline (see [`UrConnectBuilder::deadline`](crate::UrConnectBuilder::deadline)).
+    #[error("timed out during the {step}")]
+    Timeout { step: FetchStep },
+    /// The portal kept answering with a server error or the connection kept failing until
+    /// the [`RetryPolicy`](crate::config::RetryPolicy) gave up.
+    #[error(
+        "{context} after {attempts} attempts{}",
+        last_status.map(|s| format!(" (last status {s})")).unwrap_or_default()
+    )]
+    RetriesExhausted {
+        context: String,
+        attempts: u32,
+        /// The status of the last response received; `None` if none came back.
+        last_status: Option<StatusCode>,
+        /// The error of the last attempt, when it failed without a response.
+        #[source]
+        source: Option<anyhow::Error>,
+    },
+    /// The portal rejected the `_flowExecutionKey` of the timetable flow, even after opening
+    /// the flow again for a fresh one.
+    #[error("the portal rejected the _flowExecutionKey of the timetable flow")]
+    FlowExecutionExpired,
+    #[error("could not determine _flowExecutionKey for timetable")]
+    FlowKeyNotFound,
+    #[error("could not locate ICS URL in timetable pages")]
+    IcsUrlNotFound,
+    /// The export link answered with an HTML page or another body that is not a calendar,
+    /// most often the login page of an expired session.
+    #[error(
+        "the ICS download returned a page from {final_url} instead of a calendar; the \
+         session may have expired"
+    )]
+    IcsDownloadReturnedHtml { final_url: reqwest::Url },
+    /// The export link answered with an error status instead of a calendar.
+    #[error("the ICS download failed with status {status}")]
+    IcsDownloadFailed { status: StatusCode },
+    #[error("no events were parsed from the ICS response")]
+    EmptyTimetable,
+    /// A page or document did not have the expected shape.
+    #[error("{0}")]
+    Parse(String),
+    /// Entries or a configuration could not be written as TOML.
+    #[cfg(feature = "serde")]
+    #[error("{context}")]
+    Serialize {
+        context: String,
+        #[source]
+        source: toml::ser::Error,
+    },
+    /// Entries could not be written as JSON.
+    #[cfg(feature = "serde")]
+    #[error("{context}")]
+    SerializeJson {
+        context: String,
+        #[source]
+        source: serde_json::Error,
+    },
+}
+
+/// The steps of a timetable fetch, as named by [`UrError::Timeout`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum FetchStep {
+    /// Logging in again after the session expired.
+    Login,
+    /// The landing page (and the study submenu, when followed).
+    Landing,
+    /// The timetable entry page yielding the `_flowExecutionKey`.
+    FlowResolution,
+    TimetablePage,
+    IcsDownload,
+}
+
+impl fmt::Display for FetchStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Login => "login",
+            Self::Landing => "landing page",
+            Self::FlowResolution => "timetable flow page",
+            Self::TimetablePage => "full timetable page",
+            Self::IcsDownload => "ICS download",
+        })
+    }
+}
+
+/// Raised by the client's request helpers for a `407` response; [`UrError::request`] turns it
+/// into [`UrError::ProxyAuthRequired`].
+#[derive(Debug, Error)]
+#[error("proxy authentication required")]
+pub(crate) struct ProxyRejected;
+
+/// Raised by the client's request helpers once retrying gave up; [`UrError::request`] turns
+/// it into [`UrError::RetriesExhausted`].
+#[derive(Debug, Error)]
+#[error("gave up after {attempts} attempts")]
+pub(crate) struct GaveUp {
+    pub(crate) attempts: u32,
+    pub(crate) last_status: Option<StatusCode>,
+    #[source]
+    pub(crate) last_error: Option<anyhow::Error>,
+}
+
+impl UrError {
+    /// Wraps a transport failure, keeping `reqwest` errors recognisable as [`Self::Network`].
+    pub(crate) fn request(context: impl Into<String>, source: anyhow::Error) -> Self {
+        // `reqwest` reports a 407 to an HTTPS tunnel only through the message of a private
+        // error type.
+        if source.chain().any(|cause| {
+            cause.is::<ProxyRejected>()
+                || cause.to_string().ends_with("proxy authorization required")
+        }) {
+            return Self::ProxyAuthRequired;
+        }
+        let context = context.into();
+        let source = match source.downcast::<GaveUp>() {
+            Ok(gave_up) => {
+                return Self::RetriesExhausted {
+                    context,
+                    attempts: gave_up.attempts,
+                    last_status: gave_up.last_status,
+                    source: gave_up.last_error,
+                };
+            }
+            Err(source) => source,
+        };
+        match source.downcast::<reqwest::Error>() {
+            Ok(source) => Self::Network { context, source },
+            Err(source) => Self::Transport { context, source },
+        }
+    }
+
+    /// Whether the same call may succeed when simply repeated later.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Self::Network { .. }
+            | Self::Transport { .. }
+            | Self::Timeout { .. }
+            | Self::RetriesExhausted { .. } => true,
+            Self::LoginFailed { status } | Self::IcsDownloadFailed { status } => {
+                status.is_server_error()
+            }
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,437 @@
+//! Conversions of timetable entries into other formats.
+
+use chrono::{DateTime, NaiveTime, Utc};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "serde")]
+use crate::error::UrError;
+use crate::{
+    model::{EventStatus, TimeRange, TimetableEntry},
+    parsing::ics::{parse_raw_property_date, recurrence_from_rule, unescape_text},
+};
+
+/// Properties rebuilt from the entry's fields rather than copied from `extra`.
+const MODELLED_PROPERTIES: &[&str] = &[
+    "UID",
+    "DTSTART",
+    "DTEND",
+    "SUMMARY",
+    "DESCRIPTION",
+    "LOCATION",
+    "RRULE",
+    "STATUS",
+];
+
+/// Renders `entries` as an iCalendar document with one `VEVENT` per entry.
+///
+/// Entries parsed with [`parse_ics_full`](crate::parsing::ics::parse_ics_full) carry their
+/// source properties in `extra`. Those are written back in source order; a modelled property
+/// (summary, description, location, times, UID, recurrence, status) is copied verbatim while
+/// it still matches the entry's field and re-rendered from the field otherwise.
+pub fn to_ics(entries: &[TimetableEntry]) -> String {
+    let mut out = String::new();
+    for line in [
+        "BEGIN:VCALENDAR",
+        "VERSION:2.0",
+        "PRODID:-//ur-connect//timetable//EN",
+    ] {
+        push_line(&mut out, line);
+    }
+    let stamp = format_utc(Utc::now());
+    for entry in entries {
+        push_line(&mut out, "BEGIN:VEVENT");
+        let mut written: Vec<&str> = Vec::new();
+        let mut has_stamp = false;
+        for (head, value) in &entry.extra {
+            let name = head
+                .split(';')
+                .next()
+                .unwrap_or_default()
+                .to_ascii_uppercase();
+            has_stamp |= name == "DTSTAMP";
+            match MODELLED_PROPERTIES.iter().find(|p| **p == name) {
+                Some(modelled) if written.contains(modelled) => {}
+                Some(modelled) => {
+                    written.push(modelled);
+                    if still_matches(entry, modelled, head, value) {
+                        push_line(&mut out, &format!("{head}:{value}"));
+                    } else if let Some(line) = modelled_line(entry, modelled) {
+                        push_line(&mut out, &line);
+                    }
+                }
+                // The parser folded the duration into `end`, which is written as DTEND.
+                None if name == "DURATION" && entry.end.is_some() => {}
+                None => push_line(&mut out, &format!("{head}:{value}")),
+            }
+        }
+        if !has_stamp {
+            push_line(&mut out, &format!("DTSTAMP:{stamp}"));
+        }
+        for modelled in MODELLED_PROPERTIES {
+            if !written.contains(modelled)
+                && let Some(line) = modelled_line(entry, modelled)
+            {
+                push_line(&mut out, &line);
+            }
+        }
+        push_line(&mut out, "END:VEVENT");
+    }
+    push_line(&mut out, "END:VCALENDAR");
+    out
+}
+
+fn still_matches(entry: &TimetableEntry, property: &str, head: &str, raw: &str) -> bool {
+    let raw_text = raw.trim();
+    match property {
+        "UID" => entry.uid.as_deref() == Some(raw_text),
+        "DTSTART" => parse_raw_property_date(head, raw) == entry.start,
+        "DTEND" => parse_raw_property_date(head, raw) == entry.end,
+        "SUMMARY" => unescape_text(raw_text).trim() == entry.title,
+        "DESCRIPTION" => Some(unescape_text(raw_text).trim()) == entry.description.as_deref(),
+        "LOCATION" => unescape_text(raw_text).trim() == entry.location,
+        "RRULE" => {
+            recurrence_from_rule(raw) == entry.recurrence
+                && raw.parse().ok() == entry.recurrence_rule
+        }
+        "STATUS" => EventStatus::from_ics(raw_text) == entry.status,
+        _ => false,
+    }
+}
+
+fn modelled_line(entry: &TimetableEntry, property: &str) -> Option<String> {
+    let value = match property {
+        "UID" => entry
+            .uid
+            .clone()
+            .unwrap_or_else(|| format!("{}@ur-connect", entry.stable_id())),
+        "DTSTART" | "DTEND" if entry.all_day => {
+            let at = if property == "DTSTART" {
+                entry.start?
+            } else {
+                entry.end?
+            };
+            return Some(format!(
+                "{property};VALUE=DATE:{}",
+                at.date_naive().format("%Y%m%d")
+            ));
+        }
+        "DTSTART" => format_utc(entry.start?),
+        "DTEND" => format_utc(entry.end?),
+        "SUMMARY" if !entry.title.is_empty() => escape_text(&entry.title),
+        "DESCRIPTION" => escape_text(entry.description.as_deref()?),
+        "LOCATION" if !entry.location.is_empty() => escape_text(&entry.location),
+        "RRULE" => match &entry.recurrence_rule {
+            Some(rule) => rule.to_string(),
+            None => format!("FREQ={}", entry.recurrence.as_ref()?.as_freq()),
+        },
+        "STATUS" if entry.status != EventStatus::Confirmed => entry.status.as_ics().to_string(),
+        _ => return None,
+    };
+    Some(format!("{property}:{value}"))
+}
+
+fn format_utc(instant: impl Into<DateTime<Utc>>) -> String {
+    instant.into().format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+/// Escapes a TEXT value as RFC 5545 section 3.3.11 requires.
+fn escape_text(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            ';' => escaped.push_str("\\;"),
+            ',' => escaped.push_str("\\,"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => {}
+            other => escaped.push(other),
+        }
+    }
+    escaped
+}
+
+/// Appends `line` folded at 75 octets, with CRLF line endings.
+fn push_line(out: &mut String, line: &str) {
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > 75 {
+            out.push_str("\r\n ");
+            width = 1;
+        }
+        out.push(c);
+        width += c.len_utf8();
+    }
+    out.push_str("\r\n");
+}
+
+/// Renders `entries` as CSV with a header row and the columns date, start, end, title,
+/// location and recurrence, quoted as RFC 4180 describes.
+pub fn entries_to_csv(entries: &[TimetableEntry]) -> String {
+    let mut out = String::from("date,start,end,title,location,recurrence\r\n");
+    for entry in entries {
+        let time = |pick: fn(&TimeRange) -> Option<NaiveTime>| {
+            entry
+                .time_range
+                .as_ref()
+                .and_then(pick)
+                .map(|time| time.format("%H:%M").to_string())
+                .unwrap_or_default()
+        };
+        let fields = [
+            entry.date.clone(),
+            time(|range| Some(range.start)),
+            time(|range| range.end),
+            entry.title.clone(),
+            entry.location.clone(),
+            entry
+                .recurrence
+                .as_ref()
+                .map(|recurrence| recurrence.as_freq().to_string())
+                .unwrap_or_default(),
+        ];
+        let row: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+        out.push_str(&row.join(","));
+        out.push_str("\r\n");
+    }
+    out
+}
+
+/// Quotes `value` when it contains a separator, a quote or a line break.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// The TOML document layout: one `[[entry]]` table per timetable entry.
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize)]
+struct TomlDocument {
+    #[serde(default)]
+    entry: Vec<TimetableEntry>,
+}
+
+/// Renders `entries` as a human-editable TOML document.
+#[cfg(feature = "serde")]
+pub fn to_toml(entries: &[TimetableEntry]) -> Result<String, UrError> {
+    let document = TomlDocument {
+        entry: entries.to_vec(),
+    };
+    toml::to_string_pretty(&document).map_err(|source| UrError::Serialize {
+        context: "failed to serialize entries as TOML".to_string(),
+        source,
+    })
+}
+
+/// Renders `entries` as a pretty-printed JSON array, with `start` and `end` as RFC 3339
+/// strings.
+#[cfg(feature = "serde")]
+pub fn entries_to_json(entries: &[TimetableEntry]) -> Result<String, UrError> {
+    serde_json::to_string_pretty(entries).map_err(|source| UrError::SerializeJson {
+        context: "failed to serialize entries as JSON".to_string(),
+        source,
+    })
+}
+
+/// Reads entries from a TOML document as written by [`to_toml`].
+///
+/// Every field except the `[[entry]]` table itself may be omitted, which makes hand-written
+/// override files short.
+#[cfg(feature = "serde")]
+pub fn from_toml(input: &str) -> Result<Vec<TimetableEntry>, UrError> {
+    let document: TomlDocument = toml::from_str(input)
+        .map_err(|err| UrError::Parse(format!("failed to parse TOML entries: {err}")))?;
+    Ok(document.entry)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        model::Recurrence,
+        parsing::ics::{parse_ics, parse_ics_full},
+    };
+
+    #[test]
+    fn unknown_properties_survive_ics_roundtrip() {
+        let source = "BEGIN:VCALENDAR\r\nVERSION:2.0\r\nBEGIN:VEVENT\r\nUID:ana@ur\r\nX-FOO;X-PARAM=1:bar\r\nDTSTART;TZID=Europe/Berlin:20250101T100000\r\nSUMMARY:Analysis\r\nCATEGORIES:Vorlesung\r\nEND:VEVENT\r\nEND:VCALENDAR\r\n";
+        let mut entries = parse_ics_full(source);
+        entries[0].location = "H 1, Raum 2".to_string();
+
+        let written = to_ics(&entries);
+        let event: Vec<&str> = written
+            .lines()
+            .skip_while(|line| *line != "BEGIN:VEVENT")
+            .collect();
+        assert_eq!(
+            &event[..6],
+            [
+                "BEGIN:VEVENT",
+                "UID:ana@ur",
+                "X-FOO;X-PARAM=1:bar",
+                "DTSTART;TZID=Europe/Berlin:20250101T100000",
+                "SUMMARY:Analysis",
+                "CATEGORIES:Vorlesung",
+            ]
+        );
+        assert!(written.contains("LOCATION:H 1\\, Raum 2\r\n"));
+        assert_eq!(parse_ics_full(&written)[0].location, "H 1, Raum 2");
+
+        let reparsed = parse_ics_full(&written);
+        assert!(
+            reparsed[0]
+                .extra
+                .contains(&("X-FOO;X-PARAM=1".to_string(), "bar".to_string()))
+        );
+        assert_eq!(reparsed[0].start, entries[0].start);
+        assert_eq!(reparsed[0].uid, entries[0].uid);
+    }
+
+    #[test]
+    fn modelled_fields_survive_ics_roundtrip() {
+        let source = "BEGIN:VCALENDAR\r\nVERSION:2.0\r\nBEGIN:VEVENT\r\nDTSTART;TZID=Europe/Berlin:20250101T100000\r\nDTEND;TZID=Europe/Berlin:20250101T120000\r\nSUMMARY:Analysis\\; Übung\r\nLOCATION:H 1\\, Raum 2\r\nRRULE:FREQ=WEEKLY;INTERVAL=2;COUNT=7\r\nEND:VEVENT\r\nEND:VCALENDAR\r\n";
+        let entries = parse_ics(source);
+        assert!(entries[0].recurrence_rule.is_some());
+
+        let written = to_ics(&entries);
+        assert!(written.contains("DTSTART:20250101T090000Z\r\n"));
+        assert!(written.contains("SUMMARY:Analysis\\; Übung\r\n"));
+        let reparsed = parse_ics(&written);
+        assert_eq!(reparsed.len(), 1);
+        assert_eq!(reparsed[0].title, "Analysis; Übung");
+        assert_eq!(reparsed[0].location, "H 1, Raum 2");
+        assert_eq!(reparsed[0].start, entries[0].start);
+        assert_eq!(reparsed[0].end, entries[0].end);
+        assert_eq!(reparsed[0].recurrence, Some(Recurrence::Weekly));
+        assert_eq!(reparsed[0].recurrence_rule, entries[0].recurrence_rule);
+    }
+
+    #[test]
+    fn writes_all_day_entries_as_dates() {
+        let source = "BEGIN:VCALENDAR\r\nBEGIN:VEVENT\r\nDTSTART;VALUE=DATE:20241001\r\nDTEND;VALUE=DATE:20241002\r\nSUMMARY:Dies Academicus\r\nEND:VEVENT\r\nEND:VCALENDAR\r\n";
+        let written = to_ics(&parse_ics(source));
+        assert!(written.contains("DTSTART;VALUE=DATE:20241001\r\n"));
+        assert!(written.contains("DTEND;VALUE=DATE:20241002\r\n"));
+        let (reparsed, source) = (&parse_ics(&written)[0], &parse_ics(source)[0]);
+        assert!(reparsed.all_day);
+        assert_eq!((reparsed.start, reparsed.end), (source.start, source.end));
+    }
+
+    #[test]
+    fn writes_a_duration_as_the_end() {
+        let source = "BEGIN:VCALENDAR\r\nBEGIN:VEVENT\r\nDTSTART:20250101T100000Z\r\nDURATION:PT90M\r\nSUMMARY:Analysis\r\nEND:VEVENT\r\nEND:VCALENDAR\r\n";
+        let entries = parse_ics_full(source);
+        let written = to_ics(&entries);
+        assert!(written.contains("DTEND:20250101T113000Z\r\n"));
+        assert!(!written.contains("DURATION"));
+    }
+
+    #[test]
+    fn folds_long_lines_at_75_octets() {
+        let entry = TimetableEntry::new(
+            "2025-01-01".into(),
+            "10:00 - 12:00".parse().ok(),
+            "Übung ".repeat(20),
+            String::new(),
+            None,
+        );
+        let written = to_ics(&[entry]);
+        assert!(written.split("\r\n").all(|line| line.len() <= 75));
+        let unfolded = written.replace("\r\n ", "");
+        assert!(unfolded.contains(&format!("SUMMARY:{}\r\n", "Übung ".repeat(20))));
+    }
+
+    #[test]
+    fn csv_quotes_fields_with_commas_quotes_and_line_breaks() {
+        let mut seminar = TimetableEntry::new(
+            "2025-01-01".into(),
+            "10:00 - 12:00".parse().ok(),
+            "Analysis, Übung".into(),
+            "H 1".into(),
+            Some(Recurrence::Weekly),
+        );
+        seminar.location = "Raum \"Aquarium\"\nGebäude 2".into();
+        let open_end = TimetableEntry::new(
+            "2025-01-02".into(),
+            "08:00".parse().ok(),
+            "Sprechstunde".into(),
+            String::new(),
+            None,
+        );
+
+        let csv = entries_to_csv(&[seminar, open_end]);
+        assert_eq!(
+            csv,
+            "date,start,end,title,location,recurrence\r\n\
+             2025-01-01,10:00,12:00,\"Analysis, Übung\",\"Raum \"\"Aquarium\"\"\nGebäude 2\",WEEKLY\r\n\
+             2025-01-02,08:00,,Sprechstunde,,\r\n"
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn json_has_the_entry_fields_and_rfc3339_times() {
+        let entries = parse_ics(
+            "BEGIN:VCALENDAR\r\nBEGIN:VEVENT\r\nUID:ana@ur\r\nDTSTART:20250101T090000Z\r\nDTEND:20250101T110000Z\r\nSUMMARY:Analysis\r\nLOCATION:H 1\r\nEND:VEVENT\r\nEND:VCALENDAR\r\n",
+        );
+        let json = entries_to_json(&entries).unwrap();
+        assert!(json.starts_with("[\n  {"), "{json}");
+
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        let entry = &value[0];
+        for key in ["title", "location", "start", "end", "uid", "status"] {
+            assert!(entry.get(key).is_some(), "{key} missing from {json}");
+        }
+        assert_eq!(entry["title"], "Analysis");
+        let start = entry["start"].as_str().unwrap();
+        assert_eq!(
+            DateTime::parse_from_rfc3339(start).unwrap(),
+            entries[0].start.unwrap()
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn toml_roundtrip_preserves_entries() {
+        let mut seminar = TimetableEntry::new(
+            "2025-01-01".into(),
+            "10:00 - 12:00".parse().ok(),
+            "Seminar, Gruppe \"B\"".into(),
+            "H 1".into(),
+            Some(Recurrence::Custom("FORTNIGHTLY".into())),
+        );
+        seminar.uid = Some("seminar@ur.de".into());
+        let consultation = TimetableEntry::new(
+            "2025-01-02".into(),
+            "14:00".parse().ok(),
+            "Sprechstunde".into(),
+            String::new(),
+            Some(Recurrence::Weekly),
+        );
+        let entries = vec![seminar, consultation];
+
+        let toml = to_toml(&entries).unwrap();
+        assert!(toml.contains("[[entry]]"));
+        assert!(toml.contains("time_range = \"10:00 - 12:00\""));
+        assert_eq!(from_toml(&toml).unwrap(), entries);
+    }
+
+    #[test]
+    fn toml_accepts_sparse_hand_written_entries() {
+        let input = r#"
+            [[entry]]
+            date = "2025-03-03"
+            title = "Lerngruppe"
+            time_range = "18:00 - 20:00"
+        "#;
+        let entries = from_toml(input).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].time(), "18:00 - 20:00");
+        assert!(entries[0].location.is_empty() && entries[0].uid.is_none());
+
+        assert!(from_toml("[[entry]]\ntime_range = \"late\"").is_err());
+    }
+}
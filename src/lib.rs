@@ -1,6 +1,34 @@
+#[cfg(feature = "blocking")]
+pub mod blocking;
+pub mod builder;
+pub mod cache;
 pub mod client;
+pub mod config;
+pub mod error;
+pub mod export;
 pub mod model;
+pub mod monitor;
 pub mod parsing;
+pub mod schedule;
+pub mod session;
+#[cfg(feature = "test-util")]
+pub mod testing;
+pub mod transport;
 
-pub use client::UrConnect;
-pub use model::{Recurrence, TimetableEntry};
+pub use builder::UrConnectBuilder;
+pub use cache::{CachedResponse, MemoryCache, ResponseCache};
+pub use client::{
+    CalendarSource, DebugCapture, EntryMapper, FetchMetrics, FetchTimings, FlowPage, KeepAlive,
+    RequestInfo, RequestObserver, TimetableResult, UrConnect,
+};
+pub use config::{DEFAULT_USER_AGENT, PortalConfig, ProxyConfig, RetryPolicy, UrConnectConfig};
+pub use error::{FetchStep, UrError};
+pub use model::{
+    EventKind, EventStatus, Freq, ParseRecurrenceRuleError, ParseSemesterIdError,
+    ParseTimeRangeError, Recurrence, RecurrenceRule, Season, SemesterId, TimeRange, TimetableEntry,
+    classify_kind,
+};
+pub use monitor::{MonitorConfig, MonitorEvent, TimetableMonitor};
+pub use schedule::RefreshOutcome;
+pub use session::{Credentials, PASSWORD_ENV_VAR, SessionState, USER_ENV_VAR};
+pub use transport::{FetchResult, HttpTransport};
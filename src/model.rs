@@ -1,44 +1,255 @@
-use std::fmt;
+use std::{fmt, str::FromStr};
+
+use chrono::{
+    DateTime, Datelike, Duration, IsoWeek, Local, LocalResult, NaiveDate, NaiveDateTime, NaiveTime,
+    TimeZone, Timelike, Utc, Weekday,
+};
+use chrono_tz::Tz;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use thiserror::Error;
 
 /// Represents a single timetable entry downloaded from the campus portal.
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(default))]
 pub struct TimetableEntry {
     pub date: String,
-    pub time: String,
+    pub time_range: Option<TimeRange>,
+    /// The event's `SUMMARY`, left empty when the export has none rather than invented;
+    /// `Display` shows [`UNTITLED`] in its place.
     pub title: String,
+    /// The event's `DESCRIPTION` (lecturers, course numbers, notes), kept apart from the
+    /// title; `{:#}` formatting appends its start.
+    pub description: Option<String>,
     pub location: String,
     pub recurrence: Option<Recurrence>,
+    /// The complete `RRULE`; `recurrence` is its simplified view.
+    pub recurrence_rule: Option<RecurrenceRule>,
+    /// The event's `STATUS`; a cancelled session is kept so callers can flag it.
+    pub status: EventStatus,
+    /// When the entry begins.
+    pub start: Option<DateTime<Local>>,
+    /// When the entry ends; exclusive, as with the ICS `DTEND`.
+    pub end: Option<DateTime<Local>>,
+    /// Whether the entry spans whole days, as an ICS `DTSTART;VALUE=DATE` does; `start` is
+    /// then midnight and `time_range` stays `None`.
+    pub all_day: bool,
+    /// The zone the export gave `DTSTART` in (its `TZID`, or UTC for `Z` times); `None` for
+    /// floating times and unknown zones.
+    pub time_zone: Option<Tz>,
+    /// The ICS `UID` of the event, when the export provides one.
+    pub uid: Option<String>,
+    /// The type of course, classified by [`classify_kind`] from the title and the ICS
+    /// `CATEGORIES`.
+    pub kind: Option<EventKind>,
+    /// The event's raw `(NAME;PARAMS, value)` properties in source order; only filled when
+    /// parsing with `FieldMask::RAW`.
+    pub extra: Vec<(String, String)>,
 }
 
 impl TimetableEntry {
     pub fn new(
         date: String,
-        time: String,
+        time_range: Option<TimeRange>,
         title: String,
         location: String,
         recurrence: Option<Recurrence>,
     ) -> Self {
+        let (start, end) = instants_from_text(&date, time_range);
+        let kind = classify_kind(&title, &[]);
         Self {
             date,
-            time,
+            time_range,
             title,
+            description: None,
             location,
             recurrence,
+            recurrence_rule: None,
+            status: EventStatus::Confirmed,
+            start,
+            end,
+            all_day: false,
+            time_zone: None,
+            uid: None,
+            kind,
+            extra: Vec::new(),
+        }
+    }
+
+    /// The start as a wall-clock time in the zone of the export, e.g. to show a Berlin
+    /// lecture at 08:00 on a server running in UTC; `None` without a start or known zone.
+    pub fn start_in_source_zone(&self) -> Option<DateTime<Tz>> {
+        Some(self.start?.with_timezone(&self.time_zone?))
+    }
+
+    /// How long the entry lasts, if both ends are known.
+    pub fn duration(&self) -> Option<Duration> {
+        Some(self.end? - self.start?)
+    }
+
+    /// The last calendar day the entry occupies.
+    ///
+    /// The end is exclusive, so an entry ending exactly at midnight (like an all-day event
+    /// with `DTEND;VALUE=DATE` set to the following day) does not reach into that day.
+    pub fn last_day(&self) -> Option<NaiveDate> {
+        let start = self.start?;
+        let Some(end) = self.end.filter(|end| *end > start) else {
+            return Some(start.date_naive());
+        };
+        let last = if end.time() == NaiveTime::MIN {
+            end.date_naive().pred_opt()?
+        } else {
+            end.date_naive()
+        };
+        Some(last)
+    }
+
+    /// The ISO 8601 week (and week-based year) the entry starts in.
+    pub fn iso_week(&self) -> Option<IsoWeek> {
+        Some(self.start?.date_naive().iso_week())
+    }
+
+    /// The number of calendar days the entry occupies, counting the first and last day.
+    pub fn day_count(&self) -> Option<u32> {
+        let first = self.start?.date_naive();
+        let days = (self.last_day()? - first).num_days() + 1;
+        u32::try_from(days).ok()
+    }
+
+    /// The rooms of `location` when it lists several, e.g. `["Raum H2", "Raum H3"]` for
+    /// `Raum H2, Raum H3`; a single room comes back as the only part.
+    ///
+    /// Semicolons always separate rooms. A comma only does when every part it leaves contains
+    /// a digit, as room numbers do, so that a building and its room such as
+    /// `Zentrales Hörsaalgebäude, H 2` stay one location. Empty parts are dropped.
+    pub fn location_parts(&self) -> Vec<&str> {
+        self.location
+            .split(';')
+            .flat_map(|part| {
+                let rooms: Vec<&str> = part.split(',').map(str::trim).collect();
+                if rooms
+                    .iter()
+                    .all(|room| room.contains(|c: char| c.is_ascii_digit()))
+                {
+                    rooms
+                } else {
+                    vec![part.trim()]
+                }
+            })
+            .filter(|part| !part.is_empty())
+            .collect()
+    }
+
+    /// The time range in its textual `HH:MM - HH:MM` form, or an empty string when unknown.
+    pub fn time(&self) -> String {
+        self.time_range
+            .map(|range| range.to_string())
+            .unwrap_or_default()
+    }
+
+    /// Compares only the fields a user would notice: `date`, `time_range`, the
+    /// [last day](Self::last_day), `title`, `location`, `recurrence` (including the full
+    /// `recurrence_rule`) and `status`.
+    ///
+    /// Left out are `uid` and `extra` (provenance and raw payload), `description`, `start`,
+    /// `end`, `all_day` and `time_zone` (reflected in the date, time and last day) and `kind`
+    /// (derived from the title). Two exports of the same event thus compare equal here even
+    /// when the derived `PartialEq` says otherwise.
+    pub fn semantically_equal(&self, other: &Self) -> bool {
+        self.date == other.date
+            && self.time_range == other.time_range
+            && self.last_day() == other.last_day()
+            && self.title == other.title
+            && self.location == other.location
+            && self.recurrence == other.recurrence
+            && self.recurrence_rule == other.recurrence_rule
+            && self.status == other.status
+    }
+
+    /// A hash over the fields compared by [`semantically_equal`](Self::semantically_equal).
+    ///
+    /// Needs the fields of [`FieldMask::CONTENT_HASH`](crate::parsing::ics::FieldMask); for
+    /// entries parsed with fewer, use
+    /// [`parse_ics_content_hashes`](crate::parsing::ics::parse_ics_content_hashes), which
+    /// fails instead of hashing the empty fields.
+    pub fn content_hash(&self) -> u64 {
+        let recurrence = self
+            .recurrence
+            .as_ref()
+            .map(|rule| rule.to_string())
+            .unwrap_or_default();
+        let time = self.time();
+        let mut parts = vec![
+            self.date.as_str(),
+            &time,
+            &self.title,
+            &self.location,
+            &recurrence,
+        ];
+        // Only hashed when present, so entries without a rule keep their earlier hashes.
+        let rule = self.recurrence_rule.as_ref().map(|rule| rule.to_string());
+        parts.extend(rule.as_deref());
+        // Likewise only for entries reaching past the day they start on.
+        let last_day = self
+            .last_day()
+            .filter(|last| self.start.is_some_and(|start| start.date_naive() != *last))
+            .map(|last| last.to_string());
+        parts.extend(last_day.as_deref());
+        if self.status != EventStatus::Confirmed {
+            parts.push(self.status.as_ics());
         }
+        fnv1a(&parts)
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.status == EventStatus::Cancelled
+    }
+
+    /// An identifier derived from the title, date and start time.
+    ///
+    /// The location and end time are deliberately left out so an entry keeps its id when the
+    /// portal moves it to another room or extends it. Used to match entries between snapshots
+    /// when no `UID` is available.
+    ///
+    /// Needs the fields of [`FieldMask::STABLE_ID`](crate::parsing::ics::FieldMask); for
+    /// entries parsed with fewer, use
+    /// [`parse_ics_stable_ids`](crate::parsing::ics::parse_ics_stable_ids), which fails
+    /// instead of deriving the id from empty fields.
+    pub fn stable_id(&self) -> String {
+        let start = self
+            .time_range
+            .map(|range| range.start.format("%H:%M").to_string())
+            .unwrap_or_default();
+        format!("{:016x}", fnv1a(&[&self.title, &self.date, &start]))
     }
 }
 
+/// Shown instead of an empty title when the entry has a date, time or location.
+pub const UNTITLED: &str = "(untitled)";
+
 impl fmt::Display for TimetableEntry {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let mut parts = Vec::new();
+        match self.status {
+            EventStatus::Confirmed => {}
+            EventStatus::Tentative => parts.push("[TENTATIVE]"),
+            EventStatus::Cancelled => parts.push("[CANCELLED]"),
+        }
         if !self.date.is_empty() {
             parts.push(self.date.as_str());
         }
-        if !self.time.is_empty() {
-            parts.push(self.time.as_str());
+        let time = if self.all_day {
+            String::new()
+        } else {
+            self.time()
+        };
+        if !time.is_empty() {
+            parts.push(time.as_str());
         }
         if !self.title.is_empty() {
             parts.push(self.title.as_str());
+        } else if !parts.is_empty() || !self.location.is_empty() {
+            parts.push(UNTITLED);
         }
         let mut line = parts.join(" ");
         if !self.location.is_empty() {
@@ -51,17 +262,41 @@ impl fmt::Display for TimetableEntry {
 
         if let Some(rule) = &self.recurrence {
             if line.is_empty() {
-                write!(f, "{}", rule)
+                line = rule.to_string();
             } else {
-                write!(f, "{} • {}", line, rule)
+                line = format!("{} • {}", line, rule);
             }
-        } else {
-            write!(f, "{}", line)
         }
+        if f.alternate()
+            && let Some(description) = &self.description
+        {
+            let summary = truncate_description(description);
+            if line.is_empty() {
+                line = summary;
+            } else {
+                line = format!("{} – {}", line, summary);
+            }
+        }
+        write!(f, "{}", line)
     }
 }
 
+/// The first line of `description`, cut to [`DESCRIPTION_PREVIEW_CHARS`] characters.
+fn truncate_description(description: &str) -> String {
+    let first_line = description.lines().next().unwrap_or_default().trim();
+    let more = first_line.len() < description.trim().len();
+    let mut preview: String = first_line.chars().take(DESCRIPTION_PREVIEW_CHARS).collect();
+    if more || preview.len() < first_line.len() {
+        preview.push('…');
+    }
+    preview
+}
+
+/// How much of the description `{:#}` formatting of an entry shows.
+const DESCRIPTION_PREVIEW_CHARS: usize = 60;
+
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Recurrence {
     Daily,
     Weekly,
@@ -70,6 +305,114 @@ pub enum Recurrence {
     Custom(String),
 }
 
+/// Whether an event takes place, from its ICS `STATUS`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum EventStatus {
+    /// `CONFIRMED`, and events without a `STATUS`.
+    #[default]
+    Confirmed,
+    /// `TENTATIVE`: scheduled, but not settled yet.
+    Tentative,
+    /// `CANCELLED`, or announced by a `METHOD:CANCEL` calendar.
+    Cancelled,
+}
+
+impl EventStatus {
+    /// Reads a `STATUS` value; unknown values (including the to-do statuses) count as
+    /// confirmed.
+    pub fn from_ics(value: &str) -> Self {
+        match value.trim().to_ascii_uppercase().as_str() {
+            "TENTATIVE" => Self::Tentative,
+            "CANCELLED" => Self::Cancelled,
+            _ => Self::Confirmed,
+        }
+    }
+
+    /// The `STATUS` value for this status.
+    pub fn as_ics(self) -> &'static str {
+        match self {
+            Self::Confirmed => "CONFIRMED",
+            Self::Tentative => "TENTATIVE",
+            Self::Cancelled => "CANCELLED",
+        }
+    }
+}
+
+/// The "Veranstaltungsart" of a course.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum EventKind {
+    /// Vorlesung.
+    Lecture,
+    /// Seminar, including Pro-, Haupt- and Oberseminare.
+    Seminar,
+    /// Übung.
+    Exercise,
+    /// Tutorium.
+    Tutorial,
+    /// Praktikum.
+    Practical,
+}
+
+/// Recognises the course type from `categories` first, then from `title`.
+///
+/// Within one text the keyword that appears first wins, so "Übung zur Vorlesung Analysis" is
+/// an exercise and "Vorlesung mit Übung" a lecture.
+pub fn classify_kind(title: &str, categories: &[String]) -> Option<EventKind> {
+    categories
+        .iter()
+        .find_map(|category| kind_in_text(category))
+        .or_else(|| kind_in_text(title))
+}
+
+fn kind_in_text(text: &str) -> Option<EventKind> {
+    const KEYWORDS: &[(&str, EventKind)] = &[
+        ("vorlesung", EventKind::Lecture),
+        ("seminar", EventKind::Seminar),
+        ("übung", EventKind::Exercise),
+        ("uebung", EventKind::Exercise),
+        ("tutorium", EventKind::Tutorial),
+        ("praktikum", EventKind::Practical),
+    ];
+    const ABBREVIATIONS: &[(&str, EventKind)] = &[
+        ("vl", EventKind::Lecture),
+        ("ü", EventKind::Exercise),
+        ("ue", EventKind::Exercise),
+    ];
+
+    let lower = text.to_lowercase();
+    let mut found: Option<(usize, EventKind)> = None;
+    let mut consider = |position: usize, kind: EventKind| {
+        if found.is_none_or(|(best, _)| position < best) {
+            found = Some((position, kind));
+        }
+    };
+    for (keyword, kind) in KEYWORDS {
+        if let Some(position) = lower.find(keyword) {
+            consider(position, *kind);
+        }
+    }
+    let mut offset = 0;
+    for word in lower.split(|c: char| !c.is_alphanumeric()) {
+        if let Some((_, kind)) = ABBREVIATIONS.iter().find(|(abbr, _)| *abbr == word) {
+            consider(offset, *kind);
+        }
+        offset += word.len() + 1;
+    }
+    found.map(|(_, kind)| kind)
+}
+
+/// The standard `RRULE` frequencies, for matching without string comparison.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Freq {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
 impl Recurrence {
     pub fn from_freq(freq: &str) -> Option<Self> {
         match freq.to_ascii_uppercase().as_str() {
@@ -81,6 +424,180 @@ impl Recurrence {
             _ => None,
         }
     }
+
+    /// The standard frequency, or `None` for [`Recurrence::Custom`].
+    pub fn freq(&self) -> Option<Freq> {
+        match self {
+            Recurrence::Daily => Some(Freq::Daily),
+            Recurrence::Weekly => Some(Freq::Weekly),
+            Recurrence::Monthly => Some(Freq::Monthly),
+            Recurrence::Yearly => Some(Freq::Yearly),
+            Recurrence::Custom(_) => None,
+        }
+    }
+
+    /// The `RRULE` `FREQ` value, the inverse of [`Recurrence::from_freq`].
+    pub fn as_freq(&self) -> &str {
+        match self {
+            Recurrence::Daily => "DAILY",
+            Recurrence::Weekly => "WEEKLY",
+            Recurrence::Monthly => "MONTHLY",
+            Recurrence::Yearly => "YEARLY",
+            Recurrence::Custom(value) => value,
+        }
+    }
+}
+
+impl Freq {
+    /// The `RRULE` `FREQ` value.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Freq::Daily => "DAILY",
+            Freq::Weekly => "WEEKLY",
+            Freq::Monthly => "MONTHLY",
+            Freq::Yearly => "YEARLY",
+        }
+    }
+}
+
+/// The parts of an `RRULE` needed to expand a timetable series.
+///
+/// Parsed with [`str::parse`] from the rule value, e.g.
+/// `FREQ=WEEKLY;INTERVAL=2;UNTIL=20250131T000000Z;BYDAY=TU,TH`; `Display` writes it back in
+/// that form. Ordinals in `BYDAY` (`1MO`, `-1FR`) keep only the weekday, and parts other
+/// than the ones below are dropped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RecurrenceRule {
+    pub freq: Freq,
+    /// Repeat every `interval`-th period; at least 1.
+    pub interval: u32,
+    /// The last moment an occurrence may start at, inclusive.
+    pub until: Option<DateTime<Local>>,
+    /// The total number of occurrences, the first one included.
+    pub count: Option<u32>,
+    /// The weekdays an occurrence falls on, in rule order.
+    pub by_day: Vec<Weekday>,
+}
+
+impl RecurrenceRule {
+    /// Every single period, without end.
+    pub fn new(freq: Freq) -> Self {
+        Self {
+            freq,
+            interval: 1,
+            until: None,
+            count: None,
+            by_day: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("invalid RRULE {0:?}")]
+pub struct ParseRecurrenceRuleError(String);
+
+impl FromStr for RecurrenceRule {
+    type Err = ParseRecurrenceRuleError;
+
+    fn from_str(rule: &str) -> Result<Self, Self::Err> {
+        let invalid = || ParseRecurrenceRuleError(rule.to_string());
+        let mut freq = None;
+        let mut parsed = Self::new(Freq::Daily);
+        for part in rule.split(';').filter(|part| !part.trim().is_empty()) {
+            let (key, value) = part.split_once('=').ok_or_else(invalid)?;
+            let value = value.trim();
+            match key.trim().to_ascii_uppercase().as_str() {
+                "FREQ" => freq = Recurrence::from_freq(value).and_then(|r| r.freq()),
+                "INTERVAL" => {
+                    parsed.interval = value.parse().ok().filter(|n| *n > 0).ok_or_else(invalid)?
+                }
+                "COUNT" => parsed.count = Some(value.parse().map_err(|_| invalid())?),
+                "UNTIL" => parsed.until = Some(parse_until(value).ok_or_else(invalid)?),
+                "BYDAY" => {
+                    parsed.by_day = value
+                        .split(',')
+                        .map(|day| parse_weekday(day.trim()).ok_or_else(invalid))
+                        .collect::<Result<_, _>>()?
+                }
+                _ => {}
+            }
+        }
+        parsed.freq = freq.ok_or_else(invalid)?;
+        Ok(parsed)
+    }
+}
+
+impl fmt::Display for RecurrenceRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "FREQ={}", self.freq.as_str())?;
+        if self.interval != 1 {
+            write!(f, ";INTERVAL={}", self.interval)?;
+        }
+        if let Some(until) = self.until {
+            write!(
+                f,
+                ";UNTIL={}",
+                until.with_timezone(&Utc).format("%Y%m%dT%H%M%SZ")
+            )?;
+        }
+        if let Some(count) = self.count {
+            write!(f, ";COUNT={count}")?;
+        }
+        if !self.by_day.is_empty() {
+            let days: Vec<&str> = self.by_day.iter().map(|day| ics_weekday(*day)).collect();
+            write!(f, ";BYDAY={}", days.join(","))?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads an `UNTIL` value; a bare date includes the whole day.
+fn parse_until(value: &str) -> Option<DateTime<Local>> {
+    if let Some(utc) = value.strip_suffix(['Z', 'z']) {
+        let naive = NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S").ok()?;
+        return Some(Utc.from_utc_datetime(&naive).with_timezone(&Local));
+    }
+    if let Ok(naive) = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S") {
+        return Some(to_local_datetime(naive));
+    }
+    let date = NaiveDate::parse_from_str(value, "%Y%m%d").ok()?;
+    Some(to_local_datetime(date.and_hms_opt(23, 59, 59)?))
+}
+
+fn parse_weekday(day: &str) -> Option<Weekday> {
+    let code = day.get(day.len().checked_sub(2)?..)?;
+    let ordinal = &day[..day.len() - 2];
+    if !ordinal.is_empty()
+        && ordinal
+            .trim_start_matches(['+', '-'])
+            .parse::<u8>()
+            .is_err()
+    {
+        return None;
+    }
+    match code.to_ascii_uppercase().as_str() {
+        "MO" => Some(Weekday::Mon),
+        "TU" => Some(Weekday::Tue),
+        "WE" => Some(Weekday::Wed),
+        "TH" => Some(Weekday::Thu),
+        "FR" => Some(Weekday::Fri),
+        "SA" => Some(Weekday::Sat),
+        "SU" => Some(Weekday::Sun),
+        _ => None,
+    }
+}
+
+fn ics_weekday(day: Weekday) -> &'static str {
+    match day {
+        Weekday::Mon => "MO",
+        Weekday::Tue => "TU",
+        Weekday::Wed => "WE",
+        Weekday::Thu => "TH",
+        Weekday::Fri => "FR",
+        Weekday::Sat => "SA",
+        Weekday::Sun => "SU",
+    }
 }
 
 impl fmt::Display for Recurrence {
@@ -94,3 +611,555 @@ impl fmt::Display for Recurrence {
         }
     }
 }
+
+/// Start and optional end time of an entry, kept at minute precision.
+///
+/// The textual form is `HH:MM - HH:MM`, or just `HH:MM` when the end is unknown. The absence of
+/// any time is represented by `Option<TimeRange>` being `None` and renders as an empty string.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TimeRange {
+    pub start: NaiveTime,
+    pub end: Option<NaiveTime>,
+}
+
+impl TimeRange {
+    pub fn new(start: NaiveTime, end: Option<NaiveTime>) -> Self {
+        Self {
+            start: truncate_to_minute(start),
+            end: end.map(truncate_to_minute),
+        }
+    }
+
+    /// Parses the textual form, mapping an empty (or all-whitespace) string to `None`.
+    pub fn parse_optional(input: &str) -> Result<Option<Self>, ParseTimeRangeError> {
+        if input.trim().is_empty() {
+            return Ok(None);
+        }
+        input.parse().map(Some)
+    }
+}
+
+impl FromStr for TimeRange {
+    type Err = ParseTimeRangeError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let error = || ParseTimeRangeError {
+            input: input.to_string(),
+        };
+        let (start, end) = match input.split_once(" - ") {
+            Some((start, end)) => (start, Some(end)),
+            None => (input, None),
+        };
+        let start = parse_clock(start).ok_or_else(error)?;
+        let end = match end {
+            Some(value) => Some(parse_clock(value).ok_or_else(error)?),
+            None => None,
+        };
+        Ok(Self::new(start, end))
+    }
+}
+
+impl fmt::Display for TimeRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.start.format("%H:%M"))?;
+        if let Some(end) = self.end {
+            write!(f, " - {}", end.format("%H:%M"))?;
+        }
+        Ok(())
+    }
+}
+
+/// Serialized as its textual `HH:MM - HH:MM` form.
+#[cfg(feature = "serde")]
+impl Serialize for TimeRange {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for TimeRange {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        text.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Error returned when a string is not a valid `HH:MM` or `HH:MM - HH:MM` time range.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("invalid time range {input:?}, expected \"HH:MM\" or \"HH:MM - HH:MM\"")]
+pub struct ParseTimeRangeError {
+    input: String,
+}
+
+fn parse_clock(value: &str) -> Option<NaiveTime> {
+    let bytes = value.as_bytes();
+    if bytes.len() != 5 || bytes[2] != b':' {
+        return None;
+    }
+    if !bytes[..2].iter().chain(&bytes[3..]).all(u8::is_ascii_digit) {
+        return None;
+    }
+    NaiveTime::parse_from_str(value, "%H:%M").ok()
+}
+
+/// A semester, written `2025-SS` for the summer semester 2025 and `2024-WS` for the winter
+/// semester 2024/25.
+///
+/// Semesters order chronologically. [`UrConnect::list_semesters`](crate::UrConnect::list_semesters)
+/// lists the ones the portal offers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SemesterId {
+    /// The year the semester starts in.
+    pub year: i32,
+    pub season: Season,
+}
+
+/// The half of the academic year a [`SemesterId`] names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Season {
+    Summer,
+    Winter,
+}
+
+impl SemesterId {
+    pub fn summer(year: i32) -> Self {
+        Self {
+            year,
+            season: Season::Summer,
+        }
+    }
+
+    pub fn winter(year: i32) -> Self {
+        Self {
+            year,
+            season: Season::Winter,
+        }
+    }
+
+    /// The HISinOne term key: the year followed by `1` for summer or `2` for winter.
+    pub fn term_key(&self) -> String {
+        let season = match self.season {
+            Season::Summer => 1,
+            Season::Winter => 2,
+        };
+        format!("{}{season}", self.year)
+    }
+}
+
+impl fmt::Display for SemesterId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let season = match self.season {
+            Season::Summer => "SS",
+            Season::Winter => "WS",
+        };
+        write!(f, "{}-{season}", self.year)
+    }
+}
+
+impl FromStr for SemesterId {
+    type Err = ParseSemesterIdError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let error = || ParseSemesterIdError {
+            input: input.to_string(),
+        };
+        let (year, season) = input.trim().split_once('-').ok_or_else(error)?;
+        if year.len() != 4 || !year.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(error());
+        }
+        let year = year.parse().map_err(|_| error())?;
+        match season.to_ascii_uppercase().as_str() {
+            "SS" => Ok(Self::summer(year)),
+            "WS" => Ok(Self::winter(year)),
+            _ => Err(error()),
+        }
+    }
+}
+
+/// Error returned when a string is not a semester like `2025-SS` or `2024-WS`.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("invalid semester {input:?}, expected e.g. \"2025-SS\" or \"2024-WS\"")]
+pub struct ParseSemesterIdError {
+    input: String,
+}
+
+/// Derives start/end instants from the textual `YYYY-MM-DD` date and a time range; an end
+/// before the start is taken to be on the following day.
+fn instants_from_text(
+    date: &str,
+    time_range: Option<TimeRange>,
+) -> (Option<DateTime<Local>>, Option<DateTime<Local>>) {
+    let (Ok(day), Some(range)) = (NaiveDate::parse_from_str(date, "%Y-%m-%d"), time_range) else {
+        return (None, None);
+    };
+    let start = day.and_time(range.start);
+    let end = range.end.map(|end| {
+        let end_day = if end < range.start {
+            day.succ_opt().unwrap_or(day)
+        } else {
+            day
+        };
+        to_local_datetime(end_day.and_time(end))
+    });
+    (Some(to_local_datetime(start)), end)
+}
+
+/// Interprets a floating time in the local zone, picking the earlier instant when a DST
+/// change makes it ambiguous and treating skipped times as UTC.
+pub(crate) fn to_local_datetime(naive: NaiveDateTime) -> DateTime<Local> {
+    match Local.from_local_datetime(&naive) {
+        LocalResult::Single(dt) => dt,
+        LocalResult::Ambiguous(first, second) => {
+            if first.timestamp() <= second.timestamp() {
+                first
+            } else {
+                second
+            }
+        }
+        LocalResult::None => Utc.from_utc_datetime(&naive).with_timezone(&Local),
+    }
+}
+
+/// 64-bit FNV-1a over the given parts; stable across platforms and Rust versions.
+pub(crate) fn fnv1a(parts: &[&str]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for part in parts {
+        for byte in part.bytes().chain(std::iter::once(0xff)) {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+    hash
+}
+
+fn truncate_to_minute(time: NaiveTime) -> NaiveTime {
+    NaiveTime::from_hms_opt(time.hour(), time.minute(), 0).unwrap_or(time)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hm(hour: u32, minute: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn provenance_only_differences_are_semantically_equal() {
+        let mut first = TimetableEntry::new(
+            "2025-01-01".into(),
+            "10:00 - 12:00".parse().ok(),
+            "Analysis".into(),
+            "H 1".into(),
+            Some(Recurrence::Weekly),
+        );
+        first.uid = Some("export-a@ur.de".into());
+        let mut second = first.clone();
+        second.uid = Some("export-b@ur.de".into());
+        second.extra = vec![("X-HIS-ID".into(), "4711".into())];
+
+        assert_ne!(first, second);
+        assert!(first.semantically_equal(&second));
+        assert_eq!(first.content_hash(), second.content_hash());
+
+        second.location = "H 2".into();
+        assert!(!first.semantically_equal(&second));
+        assert_ne!(first.content_hash(), second.content_hash());
+    }
+
+    #[test]
+    fn extending_a_multi_day_entry_is_a_change() {
+        let midnight = |day| Local.with_ymd_and_hms(2025, 3, day, 0, 0, 0).single();
+        let block_course = TimetableEntry {
+            date: "2025-03-10".into(),
+            title: "Blockseminar".into(),
+            start: midnight(10),
+            end: midnight(12),
+            all_day: true,
+            ..TimetableEntry::default()
+        };
+        let mut extended = block_course.clone();
+        extended.end = midnight(13);
+
+        assert!(!block_course.semantically_equal(&extended));
+        assert_ne!(block_course.content_hash(), extended.content_hash());
+
+        let mut same_day = TimetableEntry::new(
+            "2025-03-10".into(),
+            "10:00 - 12:00".parse().ok(),
+            "Analysis".into(),
+            "H 1".into(),
+            None,
+        );
+        let hash = same_day.content_hash();
+        same_day.end = same_day.start.map(|start| start + Duration::hours(3));
+        assert_eq!(same_day.content_hash(), hash);
+    }
+
+    #[test]
+    fn splits_location_lists_into_rooms() {
+        let with_location = |location: &str| TimetableEntry {
+            location: location.to_string(),
+            ..TimetableEntry::default()
+        };
+        assert_eq!(
+            with_location("Raum H2, Raum H3").location_parts(),
+            ["Raum H2", "Raum H3"]
+        );
+        assert_eq!(
+            with_location("H 24; Zentrales Hörsaalgebäude, H 2").location_parts(),
+            ["H 24", "Zentrales Hörsaalgebäude, H 2"]
+        );
+        assert_eq!(
+            with_location("Vielberth-Gebäude, 1. OG").location_parts(),
+            ["Vielberth-Gebäude, 1. OG"]
+        );
+        assert_eq!(with_location(" PT 2.0.5 ").location_parts(), ["PT 2.0.5"]);
+        assert!(with_location("").location_parts().is_empty());
+    }
+
+    #[test]
+    fn new_derives_instants_from_text() {
+        let entry = TimetableEntry::new(
+            "2025-01-01".into(),
+            "22:00 - 01:30".parse().ok(),
+            "Nachtwanderung".into(),
+            String::new(),
+            None,
+        );
+        assert_eq!(entry.duration(), Some(Duration::minutes(210)));
+        assert_eq!(entry.last_day(), NaiveDate::from_ymd_opt(2025, 1, 2));
+        assert_eq!(entry.day_count(), Some(2));
+
+        let untimed = TimetableEntry::new("2025-01-01".into(), None, "X".into(), "".into(), None);
+        assert!(untimed.start.is_none() && untimed.duration().is_none());
+    }
+
+    #[test]
+    fn alternate_display_appends_the_description_start() {
+        let mut entry = TimetableEntry::new(
+            "2025-01-01".into(),
+            "10:00 - 12:00".parse().ok(),
+            "Analysis I".into(),
+            "H 1".into(),
+            None,
+        );
+        assert_eq!(
+            format!("{entry:#}"),
+            "2025-01-01 10:00 - 12:00 Analysis I @ H 1"
+        );
+
+        entry.description = Some("Dozent: Prof. Dr. Beispiel\nBitte Skript mitbringen".into());
+        assert_eq!(
+            format!("{entry}"),
+            "2025-01-01 10:00 - 12:00 Analysis I @ H 1"
+        );
+        assert_eq!(
+            format!("{entry:#}"),
+            "2025-01-01 10:00 - 12:00 Analysis I @ H 1 – Dozent: Prof. Dr. Beispiel…"
+        );
+
+        entry.description = Some("x".repeat(100));
+        let shown = format!("{entry:#}");
+        assert!(shown.ends_with(&format!("{}…", "x".repeat(DESCRIPTION_PREVIEW_CHARS))));
+    }
+
+    #[test]
+    fn classifies_german_course_types() {
+        let kind = |title: &str| classify_kind(title, &[]);
+        assert_eq!(kind("Analysis I (Vorlesung)"), Some(EventKind::Lecture));
+        assert_eq!(kind("Proseminar Zahlentheorie"), Some(EventKind::Seminar));
+        assert_eq!(
+            kind("Übung zur Vorlesung Analysis I"),
+            Some(EventKind::Exercise)
+        );
+        assert_eq!(kind("Tutorium Lineare Algebra"), Some(EventKind::Tutorial));
+        assert_eq!(kind("Physikalisches Praktikum"), Some(EventKind::Practical));
+        assert_eq!(kind("Analysis I - VL"), Some(EventKind::Lecture));
+        assert_eq!(kind("Analysis I"), None);
+        assert_eq!(kind("Sprechstunde"), None);
+
+        assert_eq!(
+            classify_kind("Analysis I", &["Übung".to_string()]),
+            Some(EventKind::Exercise)
+        );
+        assert_eq!(
+            classify_kind("Vorlesung Analysis I", &["Sonstiges".to_string()]),
+            Some(EventKind::Lecture)
+        );
+    }
+
+    #[test]
+    fn freq_covers_standard_recurrences_only() {
+        assert_eq!(Recurrence::Daily.freq(), Some(Freq::Daily));
+        assert_eq!(Recurrence::Weekly.freq(), Some(Freq::Weekly));
+        assert_eq!(Recurrence::Monthly.freq(), Some(Freq::Monthly));
+        assert_eq!(Recurrence::Yearly.freq(), Some(Freq::Yearly));
+        assert_eq!(Recurrence::Custom("FORTNIGHTLY".into()).freq(), None);
+    }
+
+    #[test]
+    fn iso_week_uses_week_based_year() {
+        let week = |date: &str| {
+            TimetableEntry::new(
+                date.into(),
+                "10:00".parse().ok(),
+                "X".into(),
+                "".into(),
+                None,
+            )
+            .iso_week()
+            .map(|w| (w.year(), w.week()))
+        };
+        assert_eq!(week("2025-04-23"), Some((2025, 17)));
+        assert_eq!(week("2024-12-30"), Some((2025, 1)));
+        assert_eq!(week("2021-01-03"), Some((2020, 53)));
+        assert_eq!(week("not a date"), None);
+    }
+
+    #[test]
+    fn stable_id_ignores_location_and_end() {
+        let entry = TimetableEntry::new(
+            "2025-01-01".into(),
+            "10:00 - 12:00".parse().ok(),
+            "Analysis".into(),
+            "H 1".into(),
+            None,
+        );
+        let mut moved = entry.clone();
+        moved.location = "H 2".into();
+        moved.time_range = "10:00 - 12:30".parse().ok();
+        assert_eq!(entry.stable_id(), moved.stable_id());
+
+        let mut later = entry.clone();
+        later.time_range = "14:00 - 16:00".parse().ok();
+        assert_ne!(entry.stable_id(), later.stable_id());
+    }
+
+    #[test]
+    fn time_range_roundtrips_every_emitted_form() {
+        for hour in 0..24 {
+            for minute in [0, 1, 15, 30, 59] {
+                let start = hm(hour, minute);
+                let end = hm((hour + 1) % 24, (minute + 30) % 60);
+                for range in [
+                    TimeRange::new(start, Some(end)),
+                    TimeRange::new(start, None),
+                ] {
+                    let text = range.to_string();
+                    let parsed: TimeRange = text.parse().unwrap();
+                    assert_eq!(parsed, range);
+                    assert_eq!(parsed.to_string(), text);
+                }
+            }
+        }
+
+        assert_eq!(TimeRange::parse_optional("").unwrap(), None);
+        let entry = TimetableEntry::new(String::new(), None, "Title".into(), String::new(), None);
+        assert_eq!(entry.time(), "");
+    }
+
+    #[test]
+    fn time_range_drops_seconds() {
+        let range = TimeRange::new(
+            NaiveTime::from_hms_opt(8, 15, 42).unwrap(),
+            Some(NaiveTime::from_hms_opt(9, 45, 1).unwrap()),
+        );
+        assert_eq!(range.to_string(), "08:15 - 09:45");
+        assert_eq!(range, "08:15 - 09:45".parse().unwrap());
+    }
+
+    #[test]
+    fn time_range_rejects_malformed_input() {
+        for input in [
+            "",
+            "8:00",
+            "08:00-09:00",
+            "08:00 -",
+            "08:00 - ",
+            "24:00",
+            "08:60",
+            "08:00 - 09:00 - 10:00",
+            " 08:00",
+            "08h00",
+            "+8:00",
+        ] {
+            assert!(input.parse::<TimeRange>().is_err(), "accepted {input:?}");
+        }
+        assert!(TimeRange::parse_optional("garbage").is_err());
+    }
+
+    #[test]
+    fn parses_full_recurrence_rules() {
+        let rule: RecurrenceRule = "FREQ=WEEKLY;INTERVAL=2;UNTIL=20250131T000000Z;BYDAY=TU,TH"
+            .parse()
+            .unwrap();
+        assert_eq!(rule.freq, Freq::Weekly);
+        assert_eq!(rule.interval, 2);
+        assert_eq!(rule.count, None);
+        assert_eq!(rule.by_day, vec![Weekday::Tue, Weekday::Thu]);
+        assert_eq!(
+            rule.until,
+            Some(Utc.with_ymd_and_hms(2025, 1, 31, 0, 0, 0).unwrap().into())
+        );
+        assert_eq!(
+            rule.to_string(),
+            "FREQ=WEEKLY;INTERVAL=2;UNTIL=20250131T000000Z;BYDAY=TU,TH"
+        );
+        assert_eq!(rule.to_string().parse::<RecurrenceRule>().unwrap(), rule);
+
+        let monthly: RecurrenceRule = "freq=monthly;count=6;byday=1MO,-1FR;wkst=MO"
+            .parse()
+            .unwrap();
+        assert_eq!(monthly.interval, 1);
+        assert_eq!(monthly.count, Some(6));
+        assert_eq!(monthly.by_day, vec![Weekday::Mon, Weekday::Fri]);
+
+        assert!("INTERVAL=2".parse::<RecurrenceRule>().is_err());
+        assert!("FREQ=HOURLY".parse::<RecurrenceRule>().is_err());
+        assert!("FREQ=WEEKLY;BYDAY=XX".parse::<RecurrenceRule>().is_err());
+        assert!("FREQ=DAILY;INTERVAL=0".parse::<RecurrenceRule>().is_err());
+    }
+
+    #[test]
+    fn semesters_parse_print_and_order() {
+        let summer: SemesterId = "2025-SS".parse().unwrap();
+        let winter: SemesterId = "2024-ws".parse().unwrap();
+        assert_eq!(summer, SemesterId::summer(2025));
+        assert_eq!(winter.to_string(), "2024-WS");
+        assert_eq!(summer.term_key(), "20251");
+        assert_eq!(winter.term_key(), "20242");
+        assert!(winter < summer && summer < SemesterId::winter(2025));
+        for invalid in ["2025", "25-SS", "2025-SO", "SS-2025"] {
+            assert!(invalid.parse::<SemesterId>().is_err(), "{invalid}");
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn entries_and_recurrences_roundtrip_through_json() {
+        assert_eq!(
+            serde_json::to_string(&Recurrence::Weekly).unwrap(),
+            "\"Weekly\""
+        );
+        let custom = Recurrence::Custom("FREQ=WEEKLY;INTERVAL=2".into());
+        let json = serde_json::to_string(&custom).unwrap();
+        assert_eq!(json, r#"{"Custom":"FREQ=WEEKLY;INTERVAL=2"}"#);
+        assert_eq!(serde_json::from_str::<Recurrence>(&json).unwrap(), custom);
+
+        let mut entry = TimetableEntry::new(
+            "2025-01-01".into(),
+            "10:00 - 12:00".parse().ok(),
+            "Analysis".into(),
+            "H 1".into(),
+            Some(custom),
+        );
+        entry.start = Local.with_ymd_and_hms(2025, 1, 1, 10, 0, 0).single();
+        let json = serde_json::to_string(&entry).unwrap();
+        assert_eq!(
+            serde_json::from_str::<TimetableEntry>(&json).unwrap(),
+            entry
+        );
+    }
+}
@@ -0,0 +1,180 @@
+//! Poll-loop bookkeeping for watching a timetable over time.
+//!
+//! [`TimetableMonitor`] does not sleep or spawn anything itself: the caller fetches on its own
+//! runtime (for example through [`UrConnect::poll`](crate::UrConnect::poll)), feeds every
+//! result to [`TimetableMonitor::record`] and waits [`TimetableMonitor::next_interval`] before
+//! the next attempt.
+
+use std::time::Duration;
+
+use crate::{error::UrError, model::TimetableEntry, schedule::timetable_fingerprint};
+
+/// Polling intervals and the circuit-breaker threshold for a [`TimetableMonitor`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MonitorConfig {
+    /// Delay between fetches while the portal answers.
+    pub interval: Duration,
+    /// Consecutive failed fetches after which the monitor reports [`MonitorEvent::Degraded`].
+    pub failure_threshold: u32,
+    /// Delay between fetches while degraded.
+    pub degraded_interval: Duration,
+}
+
+impl Default for MonitorConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(15 * 60),
+            failure_threshold: 3,
+            degraded_interval: Duration::from_secs(60 * 60),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MonitorState {
+    Healthy,
+    /// The last `failure_threshold` or more fetches failed.
+    Degraded,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MonitorEvent {
+    /// The first successful fetch, or one whose entries differ from the previous one (as
+    /// judged by [`timetable_fingerprint`], so reordering alone is no change).
+    Changed(Vec<TimetableEntry>),
+    /// Fetching failed `consecutive_failures` times in a row; polling slows down.
+    Degraded { consecutive_failures: u32 },
+    /// A fetch succeeded again after the monitor was degraded.
+    Recovered,
+}
+
+/// Tracks fetch results and decides what to report and when to fetch next.
+pub struct TimetableMonitor {
+    config: MonitorConfig,
+    last: Option<Vec<TimetableEntry>>,
+    last_fingerprint: Option<u64>,
+    consecutive_failures: u32,
+    state: MonitorState,
+}
+
+impl TimetableMonitor {
+    pub fn new(config: MonitorConfig) -> Self {
+        Self {
+            config,
+            last: None,
+            last_fingerprint: None,
+            consecutive_failures: 0,
+            state: MonitorState::Healthy,
+        }
+    }
+
+    /// Records the outcome of one fetch and returns the events it causes, oldest first.
+    pub fn record(&mut self, result: Result<Vec<TimetableEntry>, UrError>) -> Vec<MonitorEvent> {
+        let mut events = Vec::new();
+        match result {
+            Ok(entries) => {
+                self.consecutive_failures = 0;
+                if self.state == MonitorState::Degraded {
+                    self.state = MonitorState::Healthy;
+                    events.push(MonitorEvent::Recovered);
+                }
+                let fingerprint = timetable_fingerprint(&entries);
+                if self.last_fingerprint != Some(fingerprint) {
+                    events.push(MonitorEvent::Changed(entries.clone()));
+                    self.last_fingerprint = Some(fingerprint);
+                    self.last = Some(entries);
+                }
+            }
+            Err(_) => {
+                self.consecutive_failures = self.consecutive_failures.saturating_add(1);
+                if self.state == MonitorState::Healthy
+                    && self.consecutive_failures >= self.config.failure_threshold
+                {
+                    self.state = MonitorState::Degraded;
+                    events.push(MonitorEvent::Degraded {
+                        consecutive_failures: self.consecutive_failures,
+                    });
+                }
+            }
+        }
+        events
+    }
+
+    /// How long to wait before the next fetch.
+    pub fn next_interval(&self) -> Duration {
+        match self.state {
+            MonitorState::Healthy => self.config.interval,
+            MonitorState::Degraded => self.config.degraded_interval,
+        }
+    }
+
+    pub fn state(&self) -> MonitorState {
+        self.state
+    }
+
+    pub fn consecutive_failures(&self) -> u32 {
+        self.consecutive_failures
+    }
+
+    /// The entries of the last successful fetch.
+    pub fn last_entries(&self) -> Option<&[TimetableEntry]> {
+        self.last.as_deref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(title: &str) -> Vec<TimetableEntry> {
+        vec![TimetableEntry::new(
+            "2025-01-01".to_string(),
+            "10:00 - 12:00".parse().ok(),
+            title.to_string(),
+            "H 1".to_string(),
+            None,
+        )]
+    }
+
+    #[test]
+    fn degrades_after_threshold_and_recovers_on_success() {
+        let config = MonitorConfig {
+            interval: Duration::from_secs(60),
+            failure_threshold: 2,
+            degraded_interval: Duration::from_secs(600),
+        };
+        let mut monitor = TimetableMonitor::new(config);
+
+        assert_eq!(
+            monitor.record(Ok(entries("Analysis"))),
+            vec![MonitorEvent::Changed(entries("Analysis"))]
+        );
+        assert!(monitor.record(Ok(entries("Analysis"))).is_empty());
+
+        assert!(monitor.record(Err(UrError::IcsUrlNotFound)).is_empty());
+        assert_eq!(monitor.state(), MonitorState::Healthy);
+        assert_eq!(monitor.next_interval(), Duration::from_secs(60));
+
+        assert_eq!(
+            monitor.record(Err(UrError::IcsUrlNotFound)),
+            vec![MonitorEvent::Degraded {
+                consecutive_failures: 2
+            }]
+        );
+        assert!(monitor.record(Err(UrError::IcsUrlNotFound)).is_empty());
+        assert_eq!(monitor.state(), MonitorState::Degraded);
+        assert_eq!(monitor.consecutive_failures(), 3);
+        assert_eq!(monitor.next_interval(), Duration::from_secs(600));
+
+        assert_eq!(
+            monitor.record(Ok(entries("Analysis I"))),
+            vec![
+                MonitorEvent::Recovered,
+                MonitorEvent::Changed(entries("Analysis I"))
+            ]
+        );
+        assert_eq!(monitor.state(), MonitorState::Healthy);
+        assert_eq!(monitor.consecutive_failures(), 0);
+        assert_eq!(monitor.next_interval(), Duration::from_secs(60));
+    }
+}
@@ -1,10 +1,20 @@
+use chrono::{NaiveDate, NaiveDateTime};
 use html_escape::decode_html_entities;
 use kuchiki::{ElementData, NodeDataRef, NodeRef, traits::TendrilSink};
 use once_cell::sync::Lazy;
 use regex::Regex;
 use reqwest::Url;
 
-static ICS_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"https?://[A-Za-z0-9\-._~:/?#\[\]@!$&'()*+,;=%]+").unwrap());
+use crate::model::SemesterId;
+
+static ICS_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"https?://[A-Za-z0-9\-._~:/?#\[\]@!$&'()*+,;=%]+").unwrap());
+
+static META_REFRESH_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"(?i)http-equiv\s*=\s*["']?refresh"#).unwrap());
+
+/// The longest delay, in seconds, of a meta refresh that [`find_meta_refresh`] reports.
+const MAX_META_REFRESH_DELAY: f64 = 5.0;
 
 pub fn parse_document(html: &str) -> NodeRef {
     kuchiki::parse_html().one(html)
@@ -25,7 +35,8 @@ pub fn find_input_value(document: &NodeRef, selector: &str, attr: &str) -> Optio
         .and_then(|node| node.attributes.borrow().get(attr).map(|v| v.to_string()))
 }
 
-pub fn find_credential_fields(document: &NodeRef) -> (String, String) {
+/// The names of the username and password inputs of the login form; `None` unless both exist.
+pub fn find_credential_fields(document: &NodeRef) -> Option<(String, String)> {
     let mut user_field = None;
     let mut pass_field = None;
 
@@ -47,13 +58,314 @@ pub fn find_credential_fields(document: &NodeRef) -> (String, String) {
         }
     }
 
-    (
-        user_field.unwrap_or_else(|| "asdf".to_string()),
-        pass_field.unwrap_or_else(|| "fdsa".to_string()),
+    Some((user_field?, pass_field?))
+}
+
+/// Resolves the `action` of the form holding the password field against `base`.
+pub fn find_login_form_action(document: &NodeRef, base: &Url) -> Option<Url> {
+    select_elements(document, "form[action]")
+        .into_iter()
+        .find(|form| {
+            select_elements(form.as_node(), "input[type]")
+                .iter()
+                .any(|input| {
+                    input
+                        .attributes
+                        .borrow()
+                        .get("type")
+                        .is_some_and(|t| t.eq_ignore_ascii_case("password"))
+                })
+        })
+        .and_then(|form| attribute_values(&form, &["action"]).into_iter().next())
+        .and_then(|action| resolve_url(&action, base))
+}
+
+/// The action and pre-filled fields of the form holding the password field.
+///
+/// The fields are the named hidden inputs (e.g. a CSRF token) and the first named submit
+/// button, which some identity providers require to tell "log in" from "cancel".
+pub fn find_login_form(document: &NodeRef, base: &Url) -> Option<(Url, Vec<(String, String)>)> {
+    let action = find_login_form_action(document, base)?;
+    let form = select_elements(document, "form[action]")
+        .into_iter()
+        .find(|form| !select_elements(form.as_node(), "input[type='password']").is_empty())?;
+    let mut fields = hidden_fields(&form);
+    fields.extend(submit_button(&form));
+    Some((action, fields))
+}
+
+/// The second-factor prompt shown after the password.
+pub struct OtpForm {
+    pub action: Url,
+    /// Hidden fields and the submit button, to be posted along with the code.
+    pub fields: Vec<(String, String)>,
+    /// The name of the one-time code input.
+    pub code_field: String,
+}
+
+pub fn find_otp_form(document: &NodeRef, base: &Url) -> Option<OtpForm> {
+    static OTP_NAME_REGEX: Lazy<Regex> =
+        Lazy::new(|| Regex::new(r"(?i)otp|totp|one.?time|tan\b|second.?factor|2fa").unwrap());
+    for form in select_elements(document, "form[action]") {
+        let code_field = select_elements(form.as_node(), "input[name]")
+            .into_iter()
+            .find_map(|input| {
+                let attrs = input.attributes.borrow();
+                let kind = attrs.get("type").unwrap_or("text").to_ascii_lowercase();
+                if !matches!(kind.as_str(), "text" | "number" | "tel" | "password") {
+                    return None;
+                }
+                let name = attrs.get("name")?;
+                let is_code = attrs.get("autocomplete") == Some("one-time-code")
+                    || OTP_NAME_REGEX.is_match(name)
+                    || attrs
+                        .get("id")
+                        .is_some_and(|id| OTP_NAME_REGEX.is_match(id));
+                is_code.then(|| name.to_string())
+            });
+        let Some(code_field) = code_field else {
+            continue;
+        };
+        let action = attribute_values(&form, &["action"]).into_iter().next()?;
+        let mut fields = hidden_fields(&form);
+        fields.extend(submit_button(&form));
+        return Some(OtpForm {
+            action: resolve_url(&action, base)?,
+            fields,
+            code_field,
+        });
+    }
+    None
+}
+
+/// The auto-submitting form an identity provider returns after a successful login, carrying
+/// the `SAMLResponse` back to the portal.
+pub fn find_saml_response_form(
+    document: &NodeRef,
+    base: &Url,
+) -> Option<(Url, Vec<(String, String)>)> {
+    let form = select_elements(document, "form[action]")
+        .into_iter()
+        .find(|form| !select_elements(form.as_node(), "input[name='SAMLResponse']").is_empty())?;
+    let action = attribute_values(&form, &["action"]).into_iter().next()?;
+    Some((resolve_url(&action, base)?, hidden_fields(&form)))
+}
+
+/// The name and value of the first named submit button of `form`.
+fn submit_button(form: &NodeDataRef<ElementData>) -> Option<(String, String)> {
+    let button = select_elements(form.as_node(), "button[name], input[type='submit'][name]")
+        .into_iter()
+        .next()?;
+    let attrs = button.attributes.borrow();
+    let name = attrs.get("name")?.to_string();
+    Some((name, attrs.get("value").unwrap_or_default().to_string()))
+}
+
+fn hidden_fields(form: &NodeDataRef<ElementData>) -> Vec<(String, String)> {
+    select_elements(form.as_node(), "input[type='hidden'][name]")
+        .iter()
+        .filter_map(|input| {
+            let attrs = input.attributes.borrow();
+            let name = attrs.get("name")?.to_string();
+            Some((name, attrs.get("value").unwrap_or_default().to_string()))
+        })
+        .collect()
+}
+
+/// Whether `document` belongs to a logged-in session.
+///
+/// The `data-user-logged-in` marker of the context information decides when present;
+/// otherwise a logout link counts as logged in.
+pub fn is_logged_in_page(document: &NodeRef) -> bool {
+    if let Some(marker) = find_input_value(document, "[data-user-logged-in]", "data-user-logged-in")
+    {
+        return marker.trim().eq_ignore_ascii_case("true");
+    }
+    !select_elements(document, "a[href*='auth.logout']").is_empty()
+}
+
+/// Whether `html` is the login form of a logged-out session.
+pub fn is_login_page(html: &str) -> bool {
+    let document = parse_document(html);
+    !is_logged_in_page(&document)
+        && !select_elements(&document, "input[type='password']").is_empty()
+}
+
+/// Whether `html` is a bot challenge or rate-limit page served in place of the login form,
+/// typically after several quick login attempts.
+///
+/// A page that still has a password field is a login form, possibly with a CAPTCHA (see
+/// [`detect_login_error`]).
+pub fn is_challenge_page(html: &str) -> bool {
+    let document = parse_document(html);
+    if !select_elements(&document, "input[type='password']").is_empty() {
+        return false;
+    }
+    const CHALLENGE: &str = "#challenge-form, #challenge-body, [class*='cf-turnstile'], \
+        [class*='captcha'], [data-sitekey], script[src*='challenge-platform'], \
+        iframe[src*='captcha']";
+    if !select_elements(&document, CHALLENGE).is_empty() {
+        return true;
+    }
+    let title = select_elements(&document, "title, h1")
+        .iter()
+        .map(|node| text_content(node).to_lowercase())
+        .collect::<Vec<_>>()
+        .join(" ");
+    ["too many requests", "zu viele anfragen", "rate limit"]
+        .iter()
+        .any(|marker| title.contains(marker))
+}
+
+/// Whether `html` is the static page the portal serves (with `200 OK`) during maintenance,
+/// recognised by its title or heading.
+///
+/// A heading only counts on a page that is neither logged in nor a login form, so a news
+/// item announcing maintenance on the landing page is not mistaken for it.
+pub fn is_maintenance_page(html: &str) -> bool {
+    let document = parse_document(html);
+    let mentions_maintenance = |selector| {
+        select_elements(&document, selector).iter().any(|node| {
+            let text = text_content(node).to_lowercase();
+            ["wartungsarbeiten", "wartungsmodus", "maintenance"]
+                .iter()
+                .any(|marker| text.contains(marker))
+        })
+    };
+    if mentions_maintenance("title") {
+        return true;
+    }
+    !is_logged_in_page(&document)
+        && select_elements(&document, "input[type='password']").is_empty()
+        && mentions_maintenance("h1, h2")
+}
+
+/// The end of the maintenance a maintenance page announces, e.g. `bis 14.10.2026, 06:00 Uhr`,
+/// in the portal's local time.
+pub fn find_maintenance_end(html: &str) -> Option<NaiveDateTime> {
+    static UNTIL_REGEX: Lazy<Regex> = Lazy::new(|| {
+        Regex::new(r"(?i)\b(?:bis|until)\b[^<\d]{0,40}(\d{1,2})\.(\d{1,2})\.(\d{4}),?\s*(?:um\s*)?(\d{1,2})[:.](\d{2})")
+            .unwrap()
+    });
+    let captures = UNTIL_REGEX.captures(html)?;
+    let number = |i: usize| captures[i].parse::<u32>().ok();
+    NaiveDate::from_ymd_opt(captures[3].parse().ok()?, number(2)?, number(1)?)?.and_hms_opt(
+        number(4)?,
+        number(5)?,
+        0,
     )
 }
 
+/// Why the portal answered a login with its login form again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoginError {
+    InvalidCredentials,
+    /// Too many failed attempts; the account is locked for a while.
+    AccountLocked,
+    /// The form asks for a CAPTCHA, which the client cannot solve.
+    CaptchaRequired,
+}
+
+/// Classifies a page answering the login POST; `None` when it belongs to a logged-in
+/// session.
+pub fn detect_login_error(document: &NodeRef) -> Option<LoginError> {
+    if is_logged_in_page(document) {
+        return None;
+    }
+    const CAPTCHA: &str = "[class*='captcha'], [id*='captcha'], [name*='captcha'], \
+        iframe[src*='captcha'], [data-sitekey]";
+    if !select_elements(document, CAPTCHA).is_empty() {
+        return Some(LoginError::CaptchaRequired);
+    }
+    let message = find_login_error_message(document)
+        .unwrap_or_default()
+        .to_lowercase();
+    let locked = ["gesperrt", "zu viele", "locked", "too many"];
+    if locked.iter().any(|marker| message.contains(marker)) {
+        return Some(LoginError::AccountLocked);
+    }
+    Some(LoginError::InvalidCredentials)
+}
+
+/// The text of the error banner the portal shows above the login form, if any.
+pub fn find_login_error_message(document: &NodeRef) -> Option<String> {
+    [
+        ".messages .error",
+        ".newMessage .error",
+        "#loginErrorMessage",
+        ".errorMessage",
+        ".form-error",
+        "[role='alert']",
+    ]
+    .iter()
+    .flat_map(|selector| select_elements(document, selector))
+    .map(|node| normalize_text(&text_content(&node)))
+    .find(|text| !text.is_empty())
+}
+
 pub fn find_timetable_menu_link(html: &str, base: &Url, flow_id: &str) -> Option<Url> {
+    best_timetable_link(html, base, flow_id).map(|(_, url)| url)
+}
+
+/// The first link in `html` that starts the flow `flow_id`, resolved against `base`.
+pub fn find_flow_link(html: &str, base: &Url, flow_id: &str) -> Option<Url> {
+    let document = parse_document(html);
+    let needle = format!("_flowid={}", flow_id.to_ascii_lowercase());
+    select_elements(&document, "a[href]")
+        .iter()
+        .find_map(|node| {
+            let href = node.attributes.borrow().get("href")?.to_string();
+            let lower = href.to_ascii_lowercase();
+            let at = lower.find(&needle)?;
+            // `_flowId=exam-flow` must not match a link to `exam-flow-archive`.
+            let rest = &lower[at + needle.len()..];
+            if rest.starts_with(|c: char| c != '&' && c != '#') {
+                return None;
+            }
+            resolve_url(&href, base)
+        })
+}
+
+/// Whether `html` links to the timetable by flow id or page name rather than only by a
+/// link text mentioning it.
+pub fn has_strong_timetable_link(html: &str, base: &Url, flow_id: &str) -> bool {
+    best_timetable_link(html, base, flow_id).is_some_and(|(score, _)| score >= 2)
+}
+
+/// Finds the "My Studies" / "Mein Studium" menu entry that usually leads to the timetable.
+pub fn find_study_submenu_link(html: &str, base: &Url) -> Option<Url> {
+    let document = parse_document(html);
+    let mut best: Option<(i32, Url)> = None;
+
+    for node in select_elements(&document, "a[href]") {
+        let href = attribute_values(&node, &["href"]).into_iter().next();
+        let Some(href) = href.filter(|h| !h.starts_with('#') && !h.starts_with("javascript:"))
+        else {
+            continue;
+        };
+        let text = normalize_text(&text_content(&node)).to_lowercase();
+        let score = if text.contains("mein studium") || text.contains("my studies") {
+            2
+        } else if text.contains("studium") || text.contains("studies") {
+            1
+        } else {
+            continue;
+        };
+        if best
+            .as_ref()
+            .is_some_and(|(best_score, _)| *best_score >= score)
+        {
+            continue;
+        }
+        if let Some(url) = resolve_url(&href, base) {
+            best = Some((score, url));
+        }
+    }
+
+    best.map(|(_, url)| url)
+}
+
+fn best_timetable_link(html: &str, base: &Url, flow_id: &str) -> Option<(i32, Url)> {
     let document = parse_document(html);
 
     let flow_id_lower = flow_id.to_ascii_lowercase();
@@ -74,7 +386,9 @@ pub fn find_timetable_menu_link(html: &str, base: &Url, flow_id: &str) -> Option
         let text_lower = text.to_ascii_lowercase();
         let has_flow_id = href_lower.contains(&format!("_flowid={}", flow_id_lower));
         let has_identifier = href_lower.contains("individualtimetable");
-        let has_keyword = text_lower.contains("stundenplan") || text_lower.contains("timetable");
+        let has_keyword = ["stundenplan", "timetable", "schedule"]
+            .iter()
+            .any(|keyword| text_lower.contains(keyword));
 
         let score = if has_flow_id {
             3
@@ -91,18 +405,17 @@ pub fn find_timetable_menu_link(html: &str, base: &Url, flow_id: &str) -> Option
         }
 
         let mut candidate_url = None;
-        if let Ok(abs) = Url::parse(&href) {
-            if abs.scheme().starts_with("http") {
-                candidate_url = Some(abs);
-            }
+        if let Ok(abs) = Url::parse(&href)
+            && abs.scheme().starts_with("http")
+        {
+            candidate_url = Some(abs);
         }
 
-        if candidate_url.is_none() {
-            if let Ok(candidate) = base.join(&href) {
-                if candidate.scheme().starts_with("http") {
-                    candidate_url = Some(candidate);
-                }
-            }
+        if candidate_url.is_none()
+            && let Ok(candidate) = base.join(&href)
+            && candidate.scheme().starts_with("http")
+        {
+            candidate_url = Some(candidate);
         }
 
         if let Some(url) = candidate_url {
@@ -120,47 +433,292 @@ pub fn find_timetable_menu_link(html: &str, base: &Url, flow_id: &str) -> Option
         }
     }
 
-    best.map(|(_, url)| url)
+    best
 }
 
-pub fn extract_flow_key_from_html(html: &str) -> Option<String> {
+/// Detects the error page HISinOne serves when a `_flowId` does not name a known flow.
+pub fn is_flow_not_found_page(html: &str) -> bool {
+    static FLOW_NOT_FOUND_REGEX: Lazy<Regex> = Lazy::new(|| {
+        Regex::new(r"(?i)nosuchflowdefinitionexception|no flow definition|\bflow\b[^<]{0,120}(nicht gefunden|not found)")
+            .unwrap()
+    });
+    FLOW_NOT_FOUND_REGEX.is_match(html)
+}
+
+/// Detects the error page HISinOne serves when a `_flowExecutionKey` is unknown or already
+/// used up, e.g. because requests of the same flow arrived out of order.
+pub fn is_flow_execution_expired_page(html: &str) -> bool {
+    static FLOW_EXECUTION_REGEX: Lazy<Regex> = Lazy::new(|| {
+        Regex::new(r"(?i)nosuchflowexecutionexception|flowexecutionrestorationfailureexception")
+            .unwrap()
+    });
+    FLOW_EXECUTION_REGEX.is_match(html)
+}
+
+/// The semesters offered by the semester choices of a page, e.g. `Sommersemester 2025` or
+/// `WiSe 2024/25`, in chronological order.
+pub fn find_semesters(html: &str) -> Vec<SemesterId> {
+    static SEMESTER_REGEX: Lazy<Regex> = Lazy::new(|| {
+        Regex::new(r"(?i)\b(?:(sommersemester|sommer|summer|sose|ss)|(wintersemester|winter|wise|ws))(?:\s*(?:semester|term))?\s*(\d{4})\b")
+            .unwrap()
+    });
     let document = parse_document(html);
+    let mut semesters: Vec<SemesterId> = select_elements(&document, "option")
+        .iter()
+        .filter_map(|option| {
+            let text = normalize_text(&option.text_contents());
+            let caps = SEMESTER_REGEX.captures(&text)?;
+            let year = caps[3].parse().ok()?;
+            Some(if caps.get(1).is_some() {
+                SemesterId::summer(year)
+            } else {
+                SemesterId::winter(year)
+            })
+        })
+        .collect();
+    semesters.sort_unstable();
+    semesters.dedup();
+    semesters
+}
 
-    for selector in ["input[name='_flowExecutionKey']", "input#_flowExecutionKey"] {
-        if let Ok(mut matches) = document.select(selector) {
-            if let Some(node) = matches.next() {
-                if let Some(value) = node.attributes.borrow().get("value") {
-                    let trimmed = value.trim();
-                    if !trimmed.is_empty() {
-                        return Some(trimmed.to_string());
-                    }
+/// Detects the intermediate page asking which timetable (personal, group, ...) to show.
+pub fn is_timetable_selection_page(html: &str) -> bool {
+    let document = parse_document(html);
+    let text = normalize_text(&document.text_contents()).to_lowercase();
+    let asks_for_selection = [
+        "bitte wählen sie einen stundenplan",
+        "stundenplan auswählen",
+        "please select a timetable",
+        "select a timetable",
+    ]
+    .iter()
+    .any(|phrase| text.contains(phrase));
+
+    asks_for_selection && find_selection_form_node(&document).is_some()
+}
+
+/// Builds the submission for the timetable selection form, preferring the personal plan.
+///
+/// Returns the resolved form action and the fields a browser would send when pressing the
+/// first non-cancel submit button.
+pub fn find_timetable_selection_form(
+    html: &str,
+    base: &Url,
+) -> Option<(Url, Vec<(String, String)>)> {
+    let document = parse_document(html);
+    let form = find_selection_form_node(&document)?;
+
+    let action = attribute_values(&form, &["action"])
+        .into_iter()
+        .next()
+        .and_then(|action| resolve_url(&action, base))
+        .unwrap_or_else(|| base.clone());
+
+    let mut fields = Vec::new();
+    let mut radios: Vec<(String, Vec<FormChoice>)> = Vec::new();
+    let mut submit = None;
+
+    for input in select_elements(form.as_node(), "input, button") {
+        let attrs = input.attributes.borrow();
+        let Some(name) = attrs.get("name").map(str::to_string) else {
+            continue;
+        };
+        let value = attrs.get("value").unwrap_or("").to_string();
+        let kind = attrs
+            .get("type")
+            .map(|t| t.to_ascii_lowercase())
+            .unwrap_or_else(|| {
+                if input.name.local.as_ref() == "button" {
+                    "submit".to_string()
+                } else {
+                    "text".to_string()
+                }
+            });
+
+        match kind.as_str() {
+            "submit" => {
+                let label = format!("{} {}", control_label(&document, &input), value);
+                if submit.is_none() && !is_cancel_label(&label) {
+                    submit = Some((name, value));
+                }
+            }
+            "radio" => {
+                let label = control_label(&document, &input);
+                let checked = attrs.contains("checked");
+                match radios.iter_mut().find(|(group, _)| *group == name) {
+                    Some((_, options)) => options.push((value, label, checked)),
+                    None => radios.push((name, vec![(value, label, checked)])),
+                }
+            }
+            "checkbox" => {
+                if attrs.contains("checked") {
+                    fields.push((name, value));
                 }
             }
+            "button" | "reset" | "image" | "file" => {}
+            _ => fields.push((name, value)),
         }
     }
 
-    if let Ok(mut matches) = document.select("a[href*='_flowExecutionKey=']") {
-        if let Some(node) = matches.next() {
-            if let Some(href) = node.attributes.borrow().get("href") {
-                if let Some(key) = extract_flow_key_from_str(href) {
-                    return Some(key);
-                }
+    for (name, options) in radios {
+        if let Some(value) = choose_option(&options) {
+            fields.push((name, value));
+        }
+    }
+
+    for select in select_elements(form.as_node(), "select") {
+        let Some(name) = select.attributes.borrow().get("name").map(str::to_string) else {
+            continue;
+        };
+        let options: Vec<FormChoice> = select_elements(select.as_node(), "option")
+            .iter()
+            .map(|option| {
+                let attrs = option.attributes.borrow();
+                let label = normalize_text(&option.text_contents());
+                let value = attrs
+                    .get("value")
+                    .map(str::to_string)
+                    .unwrap_or_else(|| label.clone());
+                (value, label, attrs.contains("selected"))
+            })
+            .collect();
+        if let Some(value) = choose_option(&options) {
+            fields.push((name, value));
+        }
+    }
+
+    if let Some(submit) = submit {
+        fields.push(submit);
+    }
+
+    Some((action, fields))
+}
+
+/// The visible text of a form control: a button's own text, or for an `<input>` the text of
+/// the `<label for>` pointing at it or of the `<label>` around it.
+fn control_label(document: &NodeRef, control: &NodeDataRef<ElementData>) -> String {
+    if control.name.local.as_ref() != "input" {
+        return normalize_text(&control.text_contents());
+    }
+    let id = control.attributes.borrow().get("id").map(str::to_string);
+    let pointing = id.and_then(|id| {
+        select_elements(document, "label[for]")
+            .into_iter()
+            .find(|label| label.attributes.borrow().get("for") == Some(id.as_str()))
+    });
+    let enclosing = || {
+        control
+            .as_node()
+            .ancestors()
+            .filter_map(|node| node.into_element_ref())
+            .find(|element| element.name.local.as_ref() == "label")
+    };
+    pointing
+        .or_else(enclosing)
+        .map(|label| normalize_text(&label.text_contents()))
+        .unwrap_or_default()
+}
+
+/// A radio button or `<option>`: value, visible label and whether it is preselected.
+type FormChoice = (String, String, bool);
+
+fn find_selection_form_node(document: &NodeRef) -> Option<NodeDataRef<ElementData>> {
+    select_elements(document, "form")
+        .into_iter()
+        .find(|form| !select_elements(form.as_node(), "select, input[type='radio']").is_empty())
+}
+
+/// Picks the personal plan if offered, otherwise the preselected or first non-empty choice.
+fn choose_option(options: &[FormChoice]) -> Option<String> {
+    let personal = options.iter().find(|(value, label, _)| {
+        let haystack = format!("{value} {label}").to_lowercase();
+        ["persönlich", "personal", "individual"]
+            .iter()
+            .any(|hint| haystack.contains(hint))
+    });
+    personal
+        .or_else(|| options.iter().find(|(_, _, selected)| *selected))
+        .or_else(|| options.iter().find(|(value, _, _)| !value.is_empty()))
+        .map(|(value, _, _)| value.clone())
+}
+
+fn is_cancel_label(label: &str) -> bool {
+    let lower = label.to_lowercase();
+    ["abbrechen", "cancel", "zurück", "back"]
+        .iter()
+        .any(|hint| lower.contains(hint))
+}
+
+/// The target of a `<meta http-equiv="refresh" content="0;url=...">` in `html`, resolved
+/// against `base`.
+///
+/// Refreshes waiting longer than a few seconds, such as the periodic reload of a news page,
+/// are left alone. `html` is only parsed when it mentions a refresh at all.
+pub fn find_meta_refresh(html: &str, base: &Url) -> Option<Url> {
+    if !META_REFRESH_REGEX.is_match(html) {
+        return None;
+    }
+    let document = parse_document(html);
+    select_elements(&document, "meta[http-equiv]")
+        .iter()
+        .find_map(|meta| {
+            let attrs = meta.attributes.borrow();
+            if !attrs.get("http-equiv")?.eq_ignore_ascii_case("refresh") {
+                return None;
+            }
+            let content = attrs.get("content")?;
+            let delay = content
+                .split([';', ','])
+                .next()?
+                .trim()
+                .parse::<f64>()
+                .ok()?;
+            if delay > MAX_META_REFRESH_DELAY {
+                return None;
+            }
+            let idx = content.to_ascii_lowercase().find("url=")?;
+            let target = content[idx + 4..]
+                .trim()
+                .trim_matches(|c| c == '\'' || c == '"');
+            (!target.is_empty())
+                .then(|| base.join(target).ok())
+                .flatten()
+        })
+}
+
+pub fn extract_flow_key_from_html(html: &str) -> Option<String> {
+    let document = parse_document(html);
+
+    for selector in ["input[name='_flowExecutionKey']", "input#_flowExecutionKey"] {
+        if let Ok(mut matches) = document.select(selector)
+            && let Some(node) = matches.next()
+            && let Some(value) = node.attributes.borrow().get("value")
+        {
+            let trimmed = value.trim();
+            if !trimmed.is_empty() {
+                return Some(trimmed.to_string());
             }
         }
     }
 
+    if let Ok(mut matches) = document.select("a[href*='_flowExecutionKey=']")
+        && let Some(node) = matches.next()
+        && let Some(href) = node.attributes.borrow().get("href")
+        && let Some(key) = extract_flow_key_from_str(href)
+    {
+        return Some(key);
+    }
+
     for meta in select_elements(&document, "meta[http-equiv]") {
         let attrs = meta.attributes.borrow();
-        if let Some(http_equiv) = attrs.get("http-equiv") {
-            if http_equiv.eq_ignore_ascii_case("refresh") {
-                if let Some(content) = attrs.get("content") {
-                    if let Some(idx) = content.to_ascii_lowercase().find("url=") {
-                        let url_part = &content[idx + 4..];
-                        if let Some(key) = extract_flow_key_from_str(url_part) {
-                            return Some(key);
-                        }
-                    }
-                }
+        if let Some(http_equiv) = attrs.get("http-equiv")
+            && http_equiv.eq_ignore_ascii_case("refresh")
+            && let Some(content) = attrs.get("content")
+            && let Some(idx) = content.to_ascii_lowercase().find("url=")
+        {
+            let url_part = &content[idx + 4..];
+            if let Some(key) = extract_flow_key_from_str(url_part) {
+                return Some(key);
             }
         }
     }
@@ -217,11 +775,11 @@ pub fn find_ics_url(html: &str, base: &Url) -> Option<Url> {
     for caps in ICS_REGEX.captures_iter(html) {
         if let Some(m) = caps.get(0) {
             let candidate = decode_html_entities(m.as_str()).trim().to_string();
-            if contains_calendar_hint(&candidate) || candidate.to_ascii_lowercase().contains(".ics")
+            if (contains_calendar_hint(&candidate)
+                || candidate.to_ascii_lowercase().contains(".ics"))
+                && let Some(url) = resolve_url(&candidate, base)
             {
-                if let Some(url) = resolve_url(&candidate, base) {
-                    return Some(url);
-                }
+                return Some(url);
             }
         }
     }
@@ -326,15 +884,365 @@ fn resolve_url(candidate: &str, base: &Url) -> Option<Url> {
     if candidate.is_empty() {
         return None;
     }
-    if let Ok(abs) = Url::parse(candidate) {
-        if abs.scheme().starts_with("http") {
-            return Some(abs);
-        }
+    if let Ok(abs) = Url::parse(candidate)
+        && abs.scheme().starts_with("http")
+    {
+        return Some(abs);
     }
-    if let Ok(joined) = base.join(candidate) {
-        if joined.scheme().starts_with("http") {
-            return Some(joined);
-        }
+    if let Ok(joined) = base.join(candidate)
+        && joined.scheme().starts_with("http")
+    {
+        return Some(joined);
     }
     None
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_semester_choices_in_german_and_english() {
+        let html = r#"<select name="term">
+            <option value="20242">Wintersemester 2024/25</option>
+            <option value="20251" selected>SoSe 2025</option>
+            <option value="20251">Summer term 2025</option>
+            <option value="20252">WiSe 2025/26</option>
+            <option value="">Sonntag 2025</option>
+        </select>"#;
+        assert_eq!(
+            find_semesters(html),
+            [
+                SemesterId::winter(2024),
+                SemesterId::summer(2025),
+                SemesterId::winter(2025)
+            ]
+        );
+    }
+
+    #[test]
+    fn finds_study_submenu_and_ignores_weak_timetable_links() {
+        let base = Url::parse(
+            "https://portal.example.org/qisserver/pages/cs/sys/portal/hisinoneStartPage.faces",
+        )
+        .unwrap();
+        let html = r##"<html><body><nav>
+            <a href="#">Studium</a>
+            <a href="subMenu.faces?navigationPosition=studies">Mein Studium</a>
+            <a href="/help">Stundenplan-Hilfe</a>
+            </nav></body></html>"##;
+
+        assert_eq!(
+            find_study_submenu_link(html, &base).unwrap().as_str(),
+            "https://portal.example.org/qisserver/pages/cs/sys/portal/subMenu.faces?navigationPosition=studies"
+        );
+        assert!(find_timetable_menu_link(html, &base, "scheduler-flow").is_some());
+        assert!(!has_strong_timetable_link(html, &base, "scheduler-flow"));
+    }
+
+    #[test]
+    fn finds_menu_links_in_german_and_english() {
+        let base = Url::parse("https://portal.example.org/").unwrap();
+        let flow = "individualTimetableSchedule-flow";
+        for landing in [
+            include_str!("../../tests/fixtures/landing.html"),
+            include_str!("../../tests/fixtures/landing_en.html"),
+        ] {
+            let timetable = find_timetable_menu_link(landing, &base, flow).unwrap();
+            assert!(timetable.path().ends_with("/individualTimetable.xhtml"));
+            let studies = find_study_submenu_link(landing, &base).unwrap();
+            assert!(studies.query().unwrap().contains("studyOverview-flow"));
+            assert!(is_logged_in_page(&parse_document(landing)));
+        }
+
+        for text in ["Mein Stundenplan", "My Timetable", "Schedule"] {
+            let html = format!(r#"<a href="/plan/view.faces">{text}</a>"#);
+            assert_eq!(
+                find_timetable_menu_link(&html, &base, flow)
+                    .unwrap()
+                    .as_str(),
+                "https://portal.example.org/plan/view.faces",
+                "{text}"
+            );
+        }
+    }
+
+    #[test]
+    fn login_form_action_is_resolved_against_base() {
+        let base = Url::parse(
+            "https://portal.example.org/qisserver/pages/cs/sys/portal/hisinoneStartPage.faces",
+        )
+        .unwrap();
+        let html = r#"<html><body>
+            <form id="search" action="/search"><input type="text" name="q"/></form>
+            <form id="login" method="post" action="/qisserver/rds?state=user&amp;type=1&amp;category=auth.login&amp;startpage=portal.vm">
+              <input type="text" name="asdf"/><input type="PASSWORD" name="fdsa"/>
+            </form></body></html>"#;
+        let action = find_login_form_action(&parse_document(html), &base).unwrap();
+        assert_eq!(
+            action.as_str(),
+            "https://portal.example.org/qisserver/rds?state=user&type=1&category=auth.login&startpage=portal.vm"
+        );
+
+        let no_form = parse_document("<html><body><input type='password' name='p'/></body></html>");
+        assert!(find_login_form_action(&no_form, &base).is_none());
+    }
+
+    #[test]
+    fn recognises_logged_in_pages_and_login_errors() {
+        let landing = parse_document(include_str!("../../tests/fixtures/landing.html"));
+        assert!(is_logged_in_page(&landing));
+        assert_eq!(find_login_error_message(&landing), None);
+
+        let failed = parse_document(include_str!("../../tests/fixtures/login_failed.html"));
+        assert!(!is_logged_in_page(&failed));
+        assert_eq!(
+            find_login_error_message(&failed).as_deref(),
+            Some(
+                "Die Anmeldung ist fehlgeschlagen. Bitte überprüfen Sie Benutzerkennung und Passwort."
+            )
+        );
+
+        assert_eq!(detect_login_error(&landing), None);
+        assert_eq!(
+            detect_login_error(&failed),
+            Some(LoginError::InvalidCredentials)
+        );
+        let locked = parse_document(
+            r#"<div class="messages"><p class="error">Ihre Benutzerkennung ist nach zu vielen
+            Fehlversuchen vorübergehend gesperrt.</p></div><input type="password" name="fdsa"/>"#,
+        );
+        assert_eq!(detect_login_error(&locked), Some(LoginError::AccountLocked));
+        let captcha = parse_document(
+            r#"<form><input type="password" name="fdsa"/>
+            <div class="g-recaptcha" data-sitekey="6Lc"></div></form>"#,
+        );
+        assert_eq!(
+            detect_login_error(&captcha),
+            Some(LoginError::CaptchaRequired)
+        );
+        assert!(!is_challenge_page(
+            r#"<form><input type="password" name="fdsa"/>
+            <div class="g-recaptcha" data-sitekey="6Lc"></div></form>"#
+        ));
+
+        let logout_only =
+            parse_document(r#"<a href="/rds?state=user&amp;category=auth.logout">Abmelden</a>"#);
+        assert!(is_logged_in_page(&logout_only));
+        assert!(!is_logged_in_page(&parse_document(include_str!(
+            "../../tests/fixtures/login.html"
+        ))));
+        assert!(is_login_page(include_str!(
+            "../../tests/fixtures/login.html"
+        )));
+        assert!(!is_login_page(include_str!(
+            "../../tests/fixtures/timetable_entry.html"
+        )));
+    }
+
+    #[test]
+    fn selection_page_submits_personal_plan() {
+        let html = include_str!("../../tests/fixtures/timetable_selection.html");
+        assert!(is_timetable_selection_page(html));
+        assert!(!is_timetable_selection_page(include_str!(
+            "../../tests/fixtures/timetable_full.html"
+        )));
+
+        let base =
+            Url::parse("https://campusportal.ur.de/qisserver/pages/plan/individualTimetable.xhtml")
+                .unwrap();
+        let (action, fields) = find_timetable_selection_form(html, &base).unwrap();
+        assert_eq!(
+            action.as_str(),
+            "https://campusportal.ur.de/qisserver/pages/plan/individualTimetable.xhtml?_flowId=individualTimetableSchedule-flow&_flowExecutionKey=e1s1"
+        );
+        let field = |name: &str| {
+            fields
+                .iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, v)| v.as_str())
+        };
+        assert_eq!(field("planSelection:plan"), Some("personal"));
+        assert_eq!(field("_flowExecutionKey"), Some("e1s1"));
+        assert_eq!(field("planSelection:show"), Some("show"));
+        assert_eq!(field("planSelection:cancel"), None);
+        assert_eq!(field("planSelection:remember"), None);
+    }
+
+    #[test]
+    fn selection_page_reads_radio_and_submit_labels() {
+        let html = include_str!("../../tests/fixtures/timetable_selection_radio.html");
+        assert!(is_timetable_selection_page(html));
+
+        let base =
+            Url::parse("https://campusportal.ur.de/qisserver/pages/plan/individualTimetable.xhtml")
+                .unwrap();
+        let (_, fields) = find_timetable_selection_form(html, &base).unwrap();
+        assert!(fields.contains(&("planSelection:plan".to_string(), "1".to_string())));
+        assert!(fields.contains(&("planSelection:show".to_string(), "2".to_string())));
+        assert!(!fields.iter().any(|(name, _)| name == "planSelection:back"));
+    }
+
+    #[test]
+    fn finds_credential_fields_only_when_both_exist() {
+        let login = parse_document(include_str!("../../tests/fixtures/login.html"));
+        assert_eq!(
+            find_credential_fields(&login),
+            Some(("asdf".to_string(), "fdsa".to_string()))
+        );
+        let email = parse_document(
+            r#"<form><input type="EMAIL" name="j_username"/><input type="password" name="j_password"/></form>"#,
+        );
+        assert_eq!(
+            find_credential_fields(&email),
+            Some(("j_username".to_string(), "j_password".to_string()))
+        );
+        let password_only = parse_document(r#"<form><input type="password" name="pin"/></form>"#);
+        assert_eq!(find_credential_fields(&password_only), None);
+        assert_eq!(
+            find_credential_fields(&parse_document(include_str!(
+                "../../tests/fixtures/landing.html"
+            ))),
+            None
+        );
+    }
+
+    #[test]
+    fn detects_challenge_pages() {
+        assert!(is_challenge_page(include_str!(
+            "../../tests/fixtures/challenge.html"
+        )));
+        assert!(is_challenge_page(
+            "<html><head><title>429 Too Many Requests</title></head><body></body></html>"
+        ));
+        assert!(!is_challenge_page(include_str!(
+            "../../tests/fixtures/login.html"
+        )));
+        assert!(!is_challenge_page(include_str!(
+            "../../tests/fixtures/landing.html"
+        )));
+    }
+
+    #[test]
+    fn detects_maintenance_page_and_its_end() {
+        let page = include_str!("../../tests/fixtures/maintenance.html");
+        assert!(is_maintenance_page(page));
+        assert_eq!(
+            find_maintenance_end(page),
+            NaiveDate::from_ymd_opt(2026, 10, 14).and_then(|date| date.and_hms_opt(6, 0, 0))
+        );
+
+        let open_ended = "<html><head><title>Maintenance</title></head><body>\
+            <p>We will be back soon.</p></body></html>";
+        assert!(is_maintenance_page(open_ended));
+        assert_eq!(find_maintenance_end(open_ended), None);
+
+        assert!(!is_maintenance_page(include_str!(
+            "../../tests/fixtures/login.html"
+        )));
+        assert!(!is_maintenance_page(include_str!(
+            "../../tests/fixtures/landing.html"
+        )));
+        assert!(!is_maintenance_page(include_str!(
+            "../../tests/fixtures/landing_news.html"
+        )));
+
+        let heading_only = "<html><head><title>Campusportal</title></head><body>\
+            <h1>Wartungsarbeiten</h1></body></html>";
+        assert!(is_maintenance_page(heading_only));
+    }
+
+    #[test]
+    fn finds_links_starting_a_flow() {
+        let base = Url::parse("https://portal.example.org/qisserver/pages/start.xhtml").unwrap();
+        let html = r#"<nav>
+            <a href="/qisserver/pages/cm/exa/archive.xhtml?_flowId=examsOverview-flow-archive">Archiv</a>
+            <a href="/qisserver/pages/cm/exa/overview.xhtml?_flowId=examsOverview-flow&amp;navigationPosition=hisinoneMeinStudium">Prüfungen</a>
+            </nav>"#;
+        assert_eq!(
+            find_flow_link(html, &base, "examsOverview-flow")
+                .unwrap()
+                .as_str(),
+            "https://portal.example.org/qisserver/pages/cm/exa/overview.xhtml?_flowId=examsOverview-flow&navigationPosition=hisinoneMeinStudium"
+        );
+        assert!(find_flow_link(html, &base, "examsOverview-flow-archive").is_some());
+        assert_eq!(find_flow_link(html, &base, "courseCatalog-flow"), None);
+    }
+
+    #[test]
+    fn finds_meta_refresh_targets() {
+        let base = Url::parse("https://portal.example.org/qisserver/pages/start.xhtml").unwrap();
+        assert_eq!(
+            find_meta_refresh(
+                include_str!("../../tests/fixtures/meta_refresh.html"),
+                &base
+            )
+            .unwrap()
+            .as_str(),
+            "https://portal.example.org/qisserver/pages/plan/individualTimetable.xhtml?_flowId=individualTimetableSchedule-flow&_flowExecutionKey=e1s1"
+        );
+        assert_eq!(
+            find_meta_refresh(
+                include_str!("../../tests/fixtures/meta_refresh_chain.html"),
+                &base
+            )
+            .unwrap()
+            .as_str(),
+            "https://portal.example.org/qisserver/pages/cs/sys/portal/wait.xhtml"
+        );
+        assert_eq!(
+            find_meta_refresh(r#"<meta http-equiv="refresh" content="300">"#, &base),
+            None
+        );
+        assert_eq!(
+            find_meta_refresh(
+                r#"<meta http-equiv="refresh" content="300;url=/qisserver/news">"#,
+                &base
+            ),
+            None
+        );
+        assert_eq!(
+            find_meta_refresh(
+                r#"<meta http-equiv="refresh" content="2; URL='/qisserver/wait'">"#,
+                &base
+            )
+            .unwrap()
+            .path(),
+            "/qisserver/wait"
+        );
+        assert_eq!(
+            find_meta_refresh(include_str!("../../tests/fixtures/landing.html"), &base),
+            None
+        );
+    }
+
+    #[test]
+    fn detects_flow_not_found_page() {
+        let page = r#"<html><head><title>Fehler</title></head><body>
+            <div class="errorMessage">Der angeforderte Flow 'individualTimetableSchedule-flow'
+            wurde nicht gefunden.</div>
+            <!-- org.springframework.webflow.definition.registry.NoSuchFlowDefinitionException -->
+            </body></html>"#;
+        assert!(is_flow_not_found_page(page));
+
+        let timetable = r#"<html><body><form id="plan">
+            <input type="hidden" name="_flowExecutionKey" value="e1s1"/>
+            <h1>Stundenplan</h1><p>Termin nicht gefunden?</p></form></body></html>"#;
+        assert!(!is_flow_not_found_page(timetable));
+    }
+
+    #[test]
+    fn detects_expired_flow_execution_page() {
+        let page = r#"<html><body><div class="errorMessage">Die Flow-Ausführung 'e1s1' wurde
+            nicht gefunden.</div>
+            <!-- org.springframework.webflow.execution.repository.NoSuchFlowExecutionException -->
+            </body></html>"#;
+        assert!(is_flow_execution_expired_page(page));
+
+        let restore = "<pre>FlowExecutionRestorationFailureException: e3s2</pre>";
+        assert!(is_flow_execution_expired_page(restore));
+
+        let unknown_flow = "<!-- NoSuchFlowDefinitionException -->";
+        assert!(!is_flow_execution_expired_page(unknown_flow));
+    }
+}
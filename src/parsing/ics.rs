@@ -1,72 +1,531 @@
-use std::io::Cursor;
+use std::{
+    fmt,
+    io::Cursor,
+    ops::{BitOr, BitOrAssign},
+};
 
-use chrono::{DateTime, Local, LocalResult, NaiveDate, NaiveDateTime, TimeZone, Utc};
-use ical::{parser::ical::IcalParser, property::Property};
+use chrono::{DateTime, Duration, Local, LocalResult, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use ical::{PropertyParser, parser::ical::IcalParser, property::Property};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use thiserror::Error;
 
-use crate::model::{Recurrence, TimetableEntry};
+use crate::model::{
+    EventStatus, Recurrence, TimeRange, TimetableEntry, classify_kind, to_local_datetime,
+};
+
+/// Which parts of an event [`parse_ics_with`] extracts; skipped fields stay empty/`None`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FieldMask(u8);
+
+impl FieldMask {
+    /// `SUMMARY` into `title`.
+    pub const TITLE: Self = Self(1);
+    /// `DTSTART`/`DTEND` into `date` and `time_range`.
+    pub const TIMES: Self = Self(1 << 1);
+    /// `LOCATION` into `location`.
+    pub const LOCATION: Self = Self(1 << 2);
+    /// `DESCRIPTION` into `description`.
+    pub const DESCRIPTION: Self = Self(1 << 3);
+    /// `RRULE` into `recurrence`.
+    pub const RECURRENCE: Self = Self(1 << 4);
+    /// Every raw property of the event, in source order, into `extra`.
+    pub const RAW: Self = Self(1 << 5);
+    /// `CATEGORIES`, used together with the title to classify `kind`.
+    pub const CATEGORIES: Self = Self(1 << 6);
+
+    /// Fields read by [`TimetableEntry::stable_id`].
+    pub const STABLE_ID: Self = Self(Self::TITLE.0 | Self::TIMES.0);
+    /// Fields read by [`TimetableEntry::content_hash`] and
+    /// [`TimetableEntry::semantically_equal`].
+    pub const CONTENT_HASH: Self =
+        Self(Self::TITLE.0 | Self::TIMES.0 | Self::LOCATION.0 | Self::RECURRENCE.0);
+
+    const NAMES: [(Self, &'static str); 7] = [
+        (Self::TITLE, "TITLE"),
+        (Self::TIMES, "TIMES"),
+        (Self::LOCATION, "LOCATION"),
+        (Self::DESCRIPTION, "DESCRIPTION"),
+        (Self::RECURRENCE, "RECURRENCE"),
+        (Self::RAW, "RAW"),
+        (Self::CATEGORIES, "CATEGORIES"),
+    ];
+
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    pub const fn all() -> Self {
+        Self((1 << 7) - 1)
+    }
+
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// The fields of `self` that are not in `other`.
+    pub const fn difference(self, other: Self) -> Self {
+        Self(self.0 & !other.0)
+    }
+
+    /// Fails with the missing fields unless `self` contains all of `required`.
+    pub fn require(self, required: Self) -> Result<(), MissingFields> {
+        let missing = required.difference(self);
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(MissingFields { missing })
+        }
+    }
+}
+
+impl Default for FieldMask {
+    /// Everything except [`FieldMask::RAW`], matching [`parse_ics`].
+    fn default() -> Self {
+        Self::all().difference(Self::RAW)
+    }
+}
+
+impl BitOr for FieldMask {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for FieldMask {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
+impl fmt::Debug for FieldMask {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "(empty)");
+        }
+        let names: Vec<&str> = Self::NAMES
+            .iter()
+            .filter(|(flag, _)| self.contains(*flag))
+            .map(|(_, name)| *name)
+            .collect();
+        write!(f, "{}", names.join(" | "))
+    }
+}
+
+/// Error returned when a computation needs fields the parse options skipped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+#[error("parse options skip fields required here: {missing:?}")]
+pub struct MissingFields {
+    pub missing: FieldMask,
+}
+
+/// Something in the calendar that was tolerated rather than rejected.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ParseWarning {
+    /// A `TZID` that is not in the tz database; its times were read as local time.
+    #[error("unknown time zone {tzid:?}, times were interpreted as local time")]
+    UnknownTimeZone { tzid: String },
+}
+
+/// The entries of a calendar together with the problems met while parsing it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ParseReport {
+    pub entries: Vec<TimetableEntry>,
+    /// Each distinct problem once, in the order first met.
+    pub warnings: Vec<ParseWarning>,
+    /// Events left out because they had neither a start nor a summary or description.
+    pub skipped_events: usize,
+    /// The `X-WR-CALNAME` of the first calendar, if it has one.
+    pub calendar_name: Option<String>,
+    /// The `PRODID` of the first calendar, naming the software that wrote it.
+    pub product_id: Option<String>,
+}
+
+/// Options for [`parse_ics_with`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ParseOptions {
+    fields: FieldMask,
+}
+
+impl ParseOptions {
+    /// Restricts extraction to `fields`.
+    pub fn fields(mut self, fields: FieldMask) -> Self {
+        self.fields = fields;
+        self
+    }
+
+    pub fn field_mask(&self) -> FieldMask {
+        self.fields
+    }
+}
 
 pub fn parse_ics(content: &str) -> Vec<TimetableEntry> {
+    parse_ics_with(content, &ParseOptions::default())
+}
+
+/// Parses `content` keeping every raw property in `extra`, so that
+/// [`to_ics`](crate::export::to_ics) can write unknown properties back out.
+pub fn parse_ics_full(content: &str) -> Vec<TimetableEntry> {
+    parse_ics_with(content, &ParseOptions::default().fields(FieldMask::all()))
+}
+
+/// Parses `content`, extracting only the fields selected in `options`.
+pub fn parse_ics_with(content: &str, options: &ParseOptions) -> Vec<TimetableEntry> {
+    parse_ics_report(content, options).entries
+}
+
+/// Like [`parse_ics_with`], also collecting the [`ParseWarning`]s met on the way.
+pub fn parse_ics_report(content: &str, options: &ParseOptions) -> ParseReport {
+    let mut report = ParseReport::default();
+    let content = content.strip_prefix('\u{feff}').unwrap_or(content);
     if content.trim().is_empty() {
-        return Vec::new();
+        return report;
     }
 
-    let cursor = Cursor::new(content.as_bytes());
-    let mut parser = IcalParser::new(cursor);
-    let mut entries = Vec::new();
+    let fields = options.fields;
+    let unfolded = unfold_lines(content);
+    let cursor = Cursor::new(unfolded.as_bytes());
+    let parser = IcalParser::new(cursor);
+    let ParseReport {
+        entries,
+        warnings,
+        skipped_events,
+        calendar_name,
+        product_id,
+    } = &mut report;
 
-    while let Some(result) = parser.next() {
+    for result in parser {
         let calendar = match result {
             Ok(calendar) => calendar,
             Err(_) => continue,
         };
+        let calendar_text = |name| {
+            property_value(&calendar.properties, name)
+                .map(|value| unescape_text(value.trim()))
+                .filter(|value| !value.is_empty())
+        };
+        if calendar_name.is_none() {
+            *calendar_name = calendar_text("X-WR-CALNAME");
+        }
+        if product_id.is_none() {
+            *product_id = calendar_text("PRODID");
+        }
+        // A `METHOD:CANCEL` calendar announces that its events are called off.
+        let cancels = property_value(&calendar.properties, "METHOD")
+            .is_some_and(|method| method.trim().eq_ignore_ascii_case("CANCEL"));
 
         for event in calendar.events {
-            let summary = property_value(&event.properties, "SUMMARY");
-            let description = property_value(&event.properties, "DESCRIPTION");
-            let location = property_value(&event.properties, "LOCATION");
-            let dt_start_raw = property_value(&event.properties, "DTSTART");
-            let dt_end_raw = property_value(&event.properties, "DTEND");
-            let rrule_raw = property_value(&event.properties, "RRULE");
-
-            let dt_start = dt_start_raw
-                .as_deref()
-                .and_then(|value| parse_ics_date(value));
-            let dt_end = dt_end_raw
-                .as_deref()
-                .and_then(|value| parse_ics_date(value));
-
-            let date_text = dt_start
-                .as_ref()
-                .map(|dt| dt.format("%Y-%m-%d").to_string())
-                .unwrap_or_default();
-            let time_text = match (dt_start.as_ref(), dt_end.as_ref()) {
-                (Some(start), Some(end)) => {
-                    format!("{} - {}", start.format("%H:%M"), end.format("%H:%M"))
+            match entry_from_event(&event.properties, fields, cancels, warnings) {
+                Some(entry) => entries.push(entry),
+                None => *skipped_events += 1,
+            }
+        }
+    }
+
+    report
+}
+
+/// Parses `content` lazily: each call to [`Iterator::next`] reads up to the end of the next
+/// usable event, so large calendars are never held as entries all at once.
+///
+/// Yields the entries of [`parse_ics_with`], in the same order. Malformed lines are left out
+/// on their own rather than costing the whole calendar.
+pub fn parse_ics_events(content: &str, options: &ParseOptions) -> IcsEvents {
+    let content = content.strip_prefix('\u{feff}').unwrap_or(content);
+    IcsEvents {
+        properties: PropertyParser::from_reader(Cursor::new(unfold_lines(content).into_bytes())),
+        fields: options.fields,
+        cancels: false,
+        warnings: Vec::new(),
+    }
+}
+
+/// The iterator returned by [`parse_ics_events`].
+pub struct IcsEvents {
+    properties: PropertyParser<Cursor<Vec<u8>>>,
+    fields: FieldMask,
+    /// Whether the current calendar is a `METHOD:CANCEL` one.
+    cancels: bool,
+    warnings: Vec<ParseWarning>,
+}
+
+impl IcsEvents {
+    /// The warnings met so far.
+    pub fn warnings(&self) -> &[ParseWarning] {
+        &self.warnings
+    }
+}
+
+impl Iterator for IcsEvents {
+    type Item = TimetableEntry;
+
+    fn next(&mut self) -> Option<TimetableEntry> {
+        let mut event: Option<Vec<Property>> = None;
+        // Components nested in the event, such as `VALARM`, whose properties are not its own.
+        let mut nested = 0usize;
+        for property in self.properties.by_ref().flatten() {
+            let value = property.value.as_deref().unwrap_or_default().trim();
+            let is = |name: &str| property.name.eq_ignore_ascii_case(name);
+            match &mut event {
+                None if is("BEGIN") && value.eq_ignore_ascii_case("VCALENDAR") => {
+                    self.cancels = false;
                 }
-                (Some(start), None) => start.format("%H:%M").to_string(),
-                _ => String::new(),
-            };
-
-            let title = summary
-                .or(description)
-                .map(|s| s.trim().to_string())
-                .unwrap_or_default();
-            let loc = location.map(|s| s.trim().to_string()).unwrap_or_default();
-            let recurrence = rrule_raw
-                .as_deref()
-                .and_then(|rule| recurrence_from_rule(rule));
+                None if is("BEGIN") && value.eq_ignore_ascii_case("VEVENT") => {
+                    event = Some(Vec::new());
+                }
+                None if is("METHOD") => self.cancels = value.eq_ignore_ascii_case("CANCEL"),
+                None => {}
+                Some(_) if is("BEGIN") => nested += 1,
+                Some(_) if is("END") && nested > 0 => nested -= 1,
+                Some(properties) if is("END") => {
+                    let properties = std::mem::take(properties);
+                    event = None;
+                    if let Some(entry) =
+                        entry_from_event(&properties, self.fields, self.cancels, &mut self.warnings)
+                    {
+                        return Some(entry);
+                    }
+                }
+                Some(_) if nested > 0 => {}
+                Some(properties) => properties.push(property),
+            }
+        }
+        None
+    }
+}
+
+/// Turns the properties of one `VEVENT` into an entry; `None` for an event with neither a
+/// start nor a summary or description.
+fn entry_from_event(
+    properties: &[Property],
+    fields: FieldMask,
+    cancels: bool,
+    warnings: &mut Vec<ParseWarning>,
+) -> Option<TimetableEntry> {
+    let wanted = |flag: FieldMask, name: &str| {
+        if fields.contains(flag) {
+            property_value(properties, name)
+        } else {
+            None
+        }
+    };
+
+    let summary = wanted(FieldMask::TITLE, "SUMMARY");
+    let description = wanted(FieldMask::DESCRIPTION, "DESCRIPTION");
+    let location = wanted(FieldMask::LOCATION, "LOCATION");
+    let (summary, description, location) = (
+        summary.as_deref().map(unescape_text),
+        description.as_deref().map(unescape_text),
+        location.as_deref().map(unescape_text),
+    );
+    let dt_start_raw = wanted(FieldMask::TIMES, "DTSTART");
+    let dt_end_raw = wanted(FieldMask::TIMES, "DTEND");
+    let duration = wanted(FieldMask::TIMES, "DURATION")
+        .as_deref()
+        .and_then(parse_ics_duration);
+    let rrule_raw = wanted(FieldMask::RECURRENCE, "RRULE");
+    let uid = property_value(properties, "UID")
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty());
+
+    let mut zoned = |name: &str, raw: Option<String>| {
+        let tzid = property_param(properties, name, "TZID");
+        parse_ics_date_in(&raw?, tzid.as_deref(), warnings)
+    };
+    let time_zone = dt_start_raw.as_deref().and_then(|raw| {
+        source_time_zone(
+            raw,
+            property_param(properties, "DTSTART", "TZID").as_deref(),
+        )
+    });
+    // A date without a time (`DTSTART;VALUE=DATE:20250101`) starts an all-day event, whose
+    // midnight start is no time of day worth showing.
+    let all_day = dt_start_raw.as_deref().is_some_and(is_date_value)
+        || property_param(properties, "DTSTART", "VALUE")
+            .is_some_and(|value| value.eq_ignore_ascii_case("DATE"));
+    let dt_start = zoned("DTSTART", dt_start_raw);
+    // Events may give their length as `DURATION` instead of an end.
+    let dt_end = zoned("DTEND", dt_end_raw).or_else(|| Some(*dt_start.as_ref()? + duration?));
+
+    let date_text = dt_start
+        .as_ref()
+        .map(|dt| dt.format("%Y-%m-%d").to_string())
+        .unwrap_or_default();
+    let time_range = dt_start
+        .as_ref()
+        .filter(|_| !all_day)
+        .map(|start| TimeRange::new(start.time(), dt_end.as_ref().map(|end| end.time())));
 
-            if date_text.is_empty() && title.is_empty() {
-                continue;
+    let title = summary.map(|s| s.trim().to_string()).unwrap_or_default();
+    let description = description
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty());
+    let loc = location.map(|s| s.trim().to_string()).unwrap_or_default();
+    let recurrence = rrule_raw.as_deref().and_then(recurrence_from_rule);
+
+    // Decide on the source rather than the extracted values so that masking a field
+    // never drops an event.
+    let has_date = if fields.contains(FieldMask::TIMES) {
+        !date_text.is_empty()
+    } else {
+        has_value(properties, "DTSTART")
+    };
+    let has_title = if fields.contains(FieldMask::TITLE | FieldMask::DESCRIPTION) {
+        !title.is_empty() || description.is_some()
+    } else {
+        has_value(properties, "SUMMARY") || has_value(properties, "DESCRIPTION")
+    };
+    if !has_date && !has_title {
+        return None;
+    }
+
+    let mut entry = TimetableEntry::new(date_text, time_range, title, loc, recurrence);
+    entry.recurrence_rule = rrule_raw.as_deref().and_then(|rule| rule.parse().ok());
+    entry.description = description;
+    entry.status = if cancels {
+        EventStatus::Cancelled
+    } else {
+        property_value(properties, "STATUS")
+            .as_deref()
+            .map(EventStatus::from_ics)
+            .unwrap_or_default()
+    };
+    entry.start = dt_start;
+    entry.end = dt_end;
+    entry.all_day = all_day;
+    entry.time_zone = time_zone;
+    entry.uid = uid;
+    if fields.contains(FieldMask::CATEGORIES) {
+        let categories = categories(properties);
+        entry.kind = classify_kind(&entry.title, &categories);
+    }
+    if fields.contains(FieldMask::RAW) {
+        entry.extra = properties.iter().map(raw_property).collect();
+    }
+    Some(entry)
+}
+
+/// Parses `content` and returns each event's [`TimetableEntry::stable_id`].
+///
+/// Fails if `options` skip any of [`FieldMask::STABLE_ID`], since the ids would silently
+/// collide otherwise.
+pub fn parse_ics_stable_ids(
+    content: &str,
+    options: &ParseOptions,
+) -> Result<Vec<String>, MissingFields> {
+    options.fields.require(FieldMask::STABLE_ID)?;
+    Ok(parse_ics_with(content, options)
+        .iter()
+        .map(TimetableEntry::stable_id)
+        .collect())
+}
+
+/// Parses `content` and returns each event's [`TimetableEntry::content_hash`].
+///
+/// Fails if `options` skip any of [`FieldMask::CONTENT_HASH`], since the hashes would
+/// silently collide otherwise.
+pub fn parse_ics_content_hashes(
+    content: &str,
+    options: &ParseOptions,
+) -> Result<Vec<u64>, MissingFields> {
+    options.fields.require(FieldMask::CONTENT_HASH)?;
+    Ok(parse_ics_with(content, options)
+        .iter()
+        .map(TimetableEntry::content_hash)
+        .collect())
+}
+
+/// Joins folded content lines (RFC 5545 section 3.1): a line starting with a space or tab
+/// continues the previous one, without that first whitespace character.
+///
+/// Lines come out `\n`-terminated and without continuations, so `ical`'s own unfolding has
+/// nothing left to join.
+fn unfold_lines(content: &str) -> String {
+    let mut unfolded = String::with_capacity(content.len());
+    for line in content.split('\n') {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        match line.strip_prefix([' ', '\t']) {
+            Some(continuation) if !unfolded.is_empty() => {
+                unfolded.pop();
+                unfolded.push_str(continuation);
             }
+            _ => unfolded.push_str(line),
+        }
+        unfolded.push('\n');
+    }
+    unfolded
+}
 
-            entries.push(TimetableEntry::new(
-                date_text, time_text, title, loc, recurrence,
-            ));
+/// Decodes the escapes of a TEXT value (RFC 5545 section 3.3.11): `\\`, `\,`, `\;` and
+/// `\n`/`\N` for a newline.
+///
+/// Unknown escapes and a trailing backslash are kept as they are.
+pub(crate) fn unescape_text(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n' | 'N') => unescaped.push('\n'),
+            Some(escaped @ ('\\' | ',' | ';')) => unescaped.push(escaped),
+            Some(other) => {
+                unescaped.push('\\');
+                unescaped.push(other);
+            }
+            None => unescaped.push('\\'),
         }
     }
+    unescaped
+}
+
+/// Every comma-separated value of every `CATEGORIES` property.
+fn categories(properties: &[Property]) -> Vec<String> {
+    properties
+        .iter()
+        .filter(|p| p.name.eq_ignore_ascii_case("CATEGORIES"))
+        .filter_map(|p| p.value.as_deref())
+        .flat_map(|value| value.split(','))
+        .map(|category| category.trim().to_string())
+        .filter(|category| !category.is_empty())
+        .collect()
+}
+
+fn has_value(properties: &[Property], name: &str) -> bool {
+    property_value(properties, name).is_some_and(|value| !value.trim().is_empty())
+}
+
+/// Renders a property as its `NAME;PARAM=VALUE` head and raw value.
+fn raw_property(property: &Property) -> (String, String) {
+    let mut head = property.name.clone();
+    for (param, values) in property.params.iter().flatten() {
+        head.push(';');
+        head.push_str(param);
+        head.push('=');
+        head.push_str(&values.join(","));
+    }
+    (head, property.value.clone().unwrap_or_default())
+}
 
-    entries
+/// The first value of parameter `param` on property `name`.
+fn property_param(properties: &[Property], name: &str, param: &str) -> Option<String> {
+    properties
+        .iter()
+        .find(|p| p.name.eq_ignore_ascii_case(name))?
+        .params
+        .iter()
+        .flatten()
+        .find(|(key, _)| key.eq_ignore_ascii_case(param))
+        .and_then(|(_, values)| values.first().cloned())
 }
 
 fn property_value(properties: &[Property], name: &str) -> Option<String> {
@@ -79,7 +538,41 @@ fn property_value(properties: &[Property], name: &str) -> Option<String> {
     None
 }
 
-fn recurrence_from_rule(rule: &str) -> Option<Recurrence> {
+/// Whether a `DTSTART`/`DTEND` value is a bare `DATE` such as `20250101`.
+fn is_date_value(raw: &str) -> bool {
+    let value = raw.trim();
+    value.len() == 8 && value.bytes().all(|b| b.is_ascii_digit())
+}
+
+/// Parses an RFC 5545 `DURATION` value such as `PT1H30M`, `P1D` or `P2W`.
+pub(crate) fn parse_ics_duration(value: &str) -> Option<Duration> {
+    static DURATION_REGEX: Lazy<Regex> = Lazy::new(|| {
+        Regex::new(r"^([+-])?P(?:(\d+)W)?(?:(\d+)D)?(?:T(?:(\d+)H)?(?:(\d+)M)?(?:(\d+)S)?)?$")
+            .unwrap()
+    });
+    let value = value.trim().to_ascii_uppercase();
+    let captures = DURATION_REGEX.captures(&value)?;
+    let parts = [(2, 7 * 86_400), (3, 86_400), (4, 3_600), (5, 60), (6, 1)];
+    if parts
+        .iter()
+        .all(|(group, _)| captures.get(*group).is_none())
+    {
+        return None;
+    }
+    let mut seconds = 0i64;
+    for (group, unit) in parts {
+        if let Some(amount) = captures.get(group) {
+            seconds =
+                seconds.checked_add(amount.as_str().parse::<i64>().ok()?.checked_mul(unit)?)?;
+        }
+    }
+    if captures.get(1).is_some_and(|sign| sign.as_str() == "-") {
+        seconds = -seconds;
+    }
+    Duration::try_seconds(seconds)
+}
+
+pub(crate) fn recurrence_from_rule(rule: &str) -> Option<Recurrence> {
     for part in rule.split(';') {
         let mut iter = part.splitn(2, '=');
         let key = iter.next()?.trim().to_ascii_uppercase();
@@ -91,7 +584,25 @@ fn recurrence_from_rule(rule: &str) -> Option<Recurrence> {
     None
 }
 
-fn parse_ics_date(raw: &str) -> Option<DateTime<Local>> {
+/// Parses the value of a raw `(NAME;PARAMS, value)` property as kept in `extra`, honouring
+/// its `TZID`.
+pub(crate) fn parse_raw_property_date(head: &str, value: &str) -> Option<DateTime<Local>> {
+    let tzid = head.split(';').skip(1).find_map(|param| {
+        let (key, value) = param.split_once('=')?;
+        key.eq_ignore_ascii_case("TZID").then_some(value)
+    });
+    parse_ics_date_in(value, tzid, &mut Vec::new())
+}
+
+/// Parses a date or date-time, reading floating date-times in `tzid` when given.
+///
+/// An unknown `tzid` falls back to local time and records a warning, so one bad zone name
+/// does not lose the event.
+fn parse_ics_date_in(
+    raw: &str,
+    tzid: Option<&str>,
+    warnings: &mut Vec<ParseWarning>,
+) -> Option<DateTime<Local>> {
     let trimmed = raw.trim();
     let mut value = trimmed;
     if let Some(idx) = trimmed.find(':') {
@@ -100,9 +611,11 @@ fn parse_ics_date(raw: &str) -> Option<DateTime<Local>> {
     if value.is_empty() {
         return None;
     }
+    // Some exports write the `T` separator and `Z` suffix in lowercase.
+    let value = value.to_ascii_uppercase();
+    let value = value.as_str();
 
-    if value.ends_with('Z') {
-        let value_no_z = &value[..value.len() - 1];
+    if let Some(value_no_z) = value.strip_suffix('Z') {
         for fmt in ["%Y%m%dT%H%M%S", "%Y%m%dT%H%M"] {
             if let Ok(naive) = NaiveDateTime::parse_from_str(value_no_z, fmt) {
                 let utc = Utc.from_utc_datetime(&naive);
@@ -117,13 +630,16 @@ fn parse_ics_date(raw: &str) -> Option<DateTime<Local>> {
     } else {
         for fmt in ["%Y%m%dT%H%M%S", "%Y%m%dT%H%M"] {
             if let Ok(naive) = NaiveDateTime::parse_from_str(value, fmt) {
-                return Some(to_local_datetime(naive));
+                return Some(match tzid {
+                    Some(tzid) => in_time_zone(naive, tzid, warnings),
+                    None => to_local_datetime(naive),
+                });
             }
         }
-        if let Ok(date) = NaiveDate::parse_from_str(value, "%Y%m%d") {
-            if let Some(naive) = date.and_hms_opt(0, 0, 0) {
-                return Some(to_local_datetime(naive));
-            }
+        if let Ok(date) = NaiveDate::parse_from_str(value, "%Y%m%d")
+            && let Some(naive) = date.and_hms_opt(0, 0, 0)
+        {
+            return Some(to_local_datetime(naive));
         }
         if let Ok(dt) = DateTime::parse_from_rfc3339(value) {
             return Some(dt.with_timezone(&Local));
@@ -133,23 +649,232 @@ fn parse_ics_date(raw: &str) -> Option<DateTime<Local>> {
     None
 }
 
-fn to_local_datetime(naive: NaiveDateTime) -> DateTime<Local> {
-    match Local.from_local_datetime(&naive) {
-        LocalResult::Single(dt) => dt,
-        LocalResult::Ambiguous(first, second) => {
-            if first.timestamp() <= second.timestamp() {
-                first
-            } else {
-                second
-            }
-        }
-        LocalResult::None => Utc.from_utc_datetime(&naive).with_timezone(&Local),
+/// The zone a `DTSTART` value is given in: its known `tzid`, or UTC for a `Z` time.
+fn source_time_zone(raw: &str, tzid: Option<&str>) -> Option<Tz> {
+    if let Some(tz) = tzid.and_then(|tzid| tzid.trim().trim_matches('"').parse::<Tz>().ok()) {
+        return Some(tz);
     }
+    raw.trim()
+        .to_ascii_uppercase()
+        .ends_with('Z')
+        .then_some(Tz::UTC)
+}
+
+fn in_time_zone(
+    naive: NaiveDateTime,
+    tzid: &str,
+    warnings: &mut Vec<ParseWarning>,
+) -> DateTime<Local> {
+    let tzid = tzid.trim().trim_matches('"');
+    let Ok(tz) = tzid.parse::<Tz>() else {
+        tracing::warn!(tzid, "unknown TZID, interpreting as local time");
+        let warning = ParseWarning::UnknownTimeZone {
+            tzid: tzid.to_string(),
+        };
+        if !warnings.contains(&warning) {
+            warnings.push(warning);
+        }
+        return to_local_datetime(naive);
+    };
+    let zoned = match tz.from_local_datetime(&naive) {
+        LocalResult::Single(dt) => dt,
+        LocalResult::Ambiguous(earlier, _) => earlier,
+        // A wall-clock time skipped by a DST change; move it past the usual one-hour gap.
+        LocalResult::None => tz
+            .from_local_datetime(&(naive + chrono::Duration::hours(1)))
+            .earliest()
+            .unwrap_or_else(|| tz.from_utc_datetime(&naive)),
+    };
+    zoned.with_timezone(&Local)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::model::EventKind;
+
+    #[test]
+    fn classifies_kind_from_categories() {
+        let ics = "BEGIN:VCALENDAR\nBEGIN:VEVENT\nSUMMARY:Analysis I\nCATEGORIES:Lehrveranstaltung,Übung\nDTSTART:20250101T100000\nEND:VEVENT\nBEGIN:VEVENT\nSUMMARY:Tutorium Analysis I\nDTSTART:20250102T100000\nEND:VEVENT\nEND:VCALENDAR";
+        let kinds: Vec<_> = parse_ics(ics).iter().map(|e| e.kind).collect();
+        assert_eq!(
+            kinds,
+            [Some(EventKind::Exercise), Some(EventKind::Tutorial)]
+        );
+    }
+
+    #[test]
+    fn flags_cancelled_and_tentative_events() {
+        let ics = "BEGIN:VCALENDAR\nBEGIN:VEVENT\nSUMMARY:Analysis I\nSTATUS:CANCELLED\nDTSTART:20250101T100000\nEND:VEVENT\nBEGIN:VEVENT\nSUMMARY:Analysis I\nSTATUS:tentative\nDTSTART:20250108T100000\nEND:VEVENT\nBEGIN:VEVENT\nSUMMARY:Analysis I\nSTATUS:CONFIRMED\nDTSTART:20250115T100000\nEND:VEVENT\nEND:VCALENDAR";
+        let entries = parse_ics(ics);
+        let statuses: Vec<EventStatus> = entries.iter().map(|entry| entry.status).collect();
+        assert_eq!(
+            statuses,
+            [
+                EventStatus::Cancelled,
+                EventStatus::Tentative,
+                EventStatus::Confirmed
+            ]
+        );
+        assert!(entries[0].is_cancelled());
+        assert!(
+            entries[0]
+                .to_string()
+                .starts_with("[CANCELLED] 2025-01-01 10:00")
+        );
+        assert!(entries[1].to_string().starts_with("[TENTATIVE] "));
+        assert!(!entries[0].semantically_equal(&entries[2]));
+
+        let cancel = "BEGIN:VCALENDAR\nMETHOD:CANCEL\nBEGIN:VEVENT\nSUMMARY:Übung\nDTSTART:20250101T100000\nEND:VEVENT\nEND:VCALENDAR";
+        assert!(parse_ics(cancel)[0].is_cancelled());
+    }
+
+    #[test]
+    fn keeps_description_next_to_the_summary() {
+        let ics = "BEGIN:VCALENDAR\nBEGIN:VEVENT\nSUMMARY:Analysis I\nDESCRIPTION:Dozent: Prof. Dr. Beispiel\\nBitte Skript mitbringen\nDTSTART:20250101T100000\nEND:VEVENT\nBEGIN:VEVENT\nDESCRIPTION:Sprechstunde nach Vereinbarung\nDTSTART:20250102T100000\nEND:VEVENT\nEND:VCALENDAR";
+        let entries = parse_ics(ics);
+        assert_eq!(entries[0].title, "Analysis I");
+        assert_eq!(
+            entries[0].description.as_deref(),
+            Some("Dozent: Prof. Dr. Beispiel\nBitte Skript mitbringen")
+        );
+        assert_eq!(entries[1].title, "");
+        assert_eq!(
+            entries[1].description.as_deref(),
+            Some("Sprechstunde nach Vereinbarung")
+        );
+    }
+
+    #[test]
+    fn keeps_located_event_without_summary_untitled() {
+        let ics = "BEGIN:VCALENDAR\nBEGIN:VEVENT\nSUMMARY:\nDTSTART:20250101T100000\nDTEND:20250101T120000\nLOCATION:H 1\nEND:VEVENT\nEND:VCALENDAR";
+        let entries = parse_ics(ics);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].title, "");
+        assert_eq!(
+            entries[0].to_string(),
+            "2025-01-01 10:00 - 12:00 (untitled) @ H 1"
+        );
+    }
+
+    #[test]
+    fn reports_skipped_events_and_calendar_metadata() {
+        let ics = "BEGIN:VCALENDAR\nPRODID:-//HIS eG//HISinOne//DE\nX-WR-CALNAME:Stundenplan\\, SoSe 2025\nBEGIN:VEVENT\nSUMMARY:Analysis\nDTSTART:20250101T100000\nEND:VEVENT\nBEGIN:VEVENT\nLOCATION:H 1\nEND:VEVENT\nEND:VCALENDAR";
+        let report = parse_ics_report(ics, &ParseOptions::default());
+        assert_eq!(report.entries.len(), 1);
+        assert_eq!(report.skipped_events, 1);
+        assert_eq!(
+            report.calendar_name.as_deref(),
+            Some("Stundenplan, SoSe 2025")
+        );
+        assert_eq!(
+            report.product_id.as_deref(),
+            Some("-//HIS eG//HISinOne//DE")
+        );
+    }
+
+    #[test]
+    fn parses_events_lazily_like_the_whole_document() {
+        let fixture = include_str!("../../tests/fixtures/timetable.ics");
+        let ics = "BEGIN:VCALENDAR\nBEGIN:VEVENT\nSUMMARY:Analysis\nDTSTART:20250101T100000\n\
+            BEGIN:VALARM\nDESCRIPTION:Erinnerung\nTRIGGER:-PT15M\nEND:VALARM\nEND:VEVENT\n\
+            BEGIN:VEVENT\nLOCATION:H 1\nEND:VEVENT\nEND:VCALENDAR\nBEGIN:VCALENDAR\n\
+            METHOD:CANCEL\nBEGIN:VEVENT\nSUMMARY:Übung\nDTSTART:20250102T100000\nEND:VEVENT\n\
+            END:VCALENDAR";
+        for content in [fixture, ics] {
+            for options in [
+                ParseOptions::default(),
+                ParseOptions::default().fields(FieldMask::all()),
+            ] {
+                assert_eq!(
+                    parse_ics_events(content, &options).collect::<Vec<_>>(),
+                    parse_ics_with(content, &options)
+                );
+            }
+        }
+
+        let mut events = parse_ics_events(ics, &ParseOptions::default());
+        let first = events.next().unwrap();
+        assert_eq!(first.title, "Analysis");
+        assert_eq!(first.description, None);
+        assert_eq!(events.next().unwrap().status, EventStatus::Cancelled);
+        assert_eq!(events.next(), None);
+    }
+
+    #[test]
+    fn computes_the_end_from_a_duration() {
+        let ics = "BEGIN:VCALENDAR\nBEGIN:VEVENT\nSUMMARY:Analysis\nDTSTART:20250101T100000\n\
+            DURATION:PT90M\nEND:VEVENT\nBEGIN:VEVENT\nSUMMARY:Exkursion\nDTSTART:20250102T080000\n\
+            DTEND:20250102T120000\nDURATION:PT1H\nEND:VEVENT\nEND:VCALENDAR";
+        let entries = parse_ics(ics);
+        let start = entries[0].start.unwrap();
+        assert_eq!(entries[0].end, Some(start + Duration::minutes(90)));
+        assert_eq!(
+            entries[0].time_range,
+            Some(TimeRange::new(
+                start.time(),
+                Some((start + Duration::minutes(90)).time())
+            ))
+        );
+        // An explicit end wins over the duration.
+        assert_eq!(
+            entries[1].end.unwrap() - entries[1].start.unwrap(),
+            Duration::hours(4)
+        );
+
+        assert_eq!(parse_ics_duration("PT1H30M"), Some(Duration::minutes(90)));
+        assert_eq!(parse_ics_duration("P1D"), Some(Duration::days(1)));
+        assert_eq!(parse_ics_duration("P2W"), Some(Duration::weeks(2)));
+        assert_eq!(parse_ics_duration("-P1DT2H"), Some(-Duration::hours(26)));
+        assert_eq!(parse_ics_duration("pt45m"), Some(Duration::minutes(45)));
+        for invalid in ["P", "PT", "90M", "P1H", ""] {
+            assert_eq!(parse_ics_duration(invalid), None, "{invalid}");
+        }
+    }
+
+    #[test]
+    fn parses_lowercase_separator_with_minute_precision() {
+        let local = parse_ics_date_in("20241001t0800", None, &mut Vec::new()).unwrap();
+        assert_eq!(
+            local.naive_local(),
+            NaiveDate::from_ymd_opt(2024, 10, 1)
+                .unwrap()
+                .and_hms_opt(8, 0, 0)
+                .unwrap()
+        );
+
+        let utc = parse_ics_date_in("DTSTART:20241001t0800z", None, &mut Vec::new()).unwrap();
+        assert_eq!(
+            utc.with_timezone(&Utc).naive_utc(),
+            NaiveDate::from_ymd_opt(2024, 10, 1)
+                .unwrap()
+                .and_hms_opt(8, 0, 0)
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn honours_tzid_and_tolerates_unknown_zones() {
+        let ics = "BEGIN:VCALENDAR\nBEGIN:VEVENT\nSUMMARY:Berlin\nDTSTART;TZID=Europe/Berlin:20250115T080000\nEND:VEVENT\nBEGIN:VEVENT\nSUMMARY:Atlantis\nDTSTART;TZID=Atlantis/Lost_City:20250115T080000\nEND:VEVENT\nBEGIN:VEVENT\nSUMMARY:Atlantis again\nDTSTART;TZID=Atlantis/Lost_City:20250116T080000\nEND:VEVENT\nEND:VCALENDAR";
+        let report = parse_ics_report(ics, &ParseOptions::default());
+        assert_eq!(report.entries.len(), 3);
+
+        let berlin = report.entries[0].start.unwrap().with_timezone(&Utc);
+        assert_eq!(berlin.naive_utc().to_string(), "2025-01-15 07:00:00");
+        assert_eq!(report.entries[0].time_zone, Some(Tz::Europe__Berlin));
+        let source = report.entries[0].start_in_source_zone().unwrap();
+        assert_eq!(source.naive_local().to_string(), "2025-01-15 08:00:00");
+
+        let atlantis = report.entries[1].start.unwrap();
+        assert_eq!(atlantis.naive_local().to_string(), "2025-01-15 08:00:00");
+        assert_eq!(report.entries[1].time_zone, None);
+        assert_eq!(
+            report.warnings,
+            [ParseWarning::UnknownTimeZone {
+                tzid: "Atlantis/Lost_City".to_string()
+            }]
+        );
+    }
 
     #[test]
     fn unfolds_and_parses_basic_event() {
@@ -159,16 +884,236 @@ mod tests {
         let entry = &entries[0];
         assert_eq!(entry.title, "Test Event");
         assert_eq!(entry.location, "Room 101");
-        assert_eq!(entry.time.len(), 13);
+        assert_eq!(entry.time().len(), 13);
         assert!(entry.recurrence.is_none());
+        assert!(entry.uid.is_none());
+
+        let (start, end) = (entry.start.unwrap(), entry.end.unwrap());
+        assert_eq!(start.format("%Y-%m-%d").to_string(), entry.date);
+        assert_eq!(
+            entry.time(),
+            format!("{} - {}", start.format("%H:%M"), end.format("%H:%M"))
+        );
+        assert_eq!(end - start, chrono::Duration::minutes(90));
+    }
+
+    #[test]
+    fn joins_summary_folded_across_three_lines() {
+        let input = "BEGIN:VCALENDAR\r\nBEGIN:VEVENT\r\nSUMMARY:Einführung in die \r\n theoretische\r\n\t Informatik\r\nLOCATION:H\r\n 1\r\nDTSTART:20241001T080000Z\r\nEND:VEVENT\r\nEND:VCALENDAR\r\n";
+        let entries = parse_ics(input);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(
+            entries[0].title,
+            "Einführung in die theoretische Informatik"
+        );
+        assert_eq!(entries[0].location, "H1");
+        assert_eq!(unfold_lines("A:b\n c\n\td\nE:f"), "A:bcd\nE:f\n");
+    }
+
+    #[test]
+    fn decodes_text_escapes() {
+        let input = "BEGIN:VCALENDAR\nBEGIN:VEVENT\nSUMMARY:Mathematik I\\, Übung\\nGruppe B\nLOCATION:H 1\\; Raum C:\\\\tmp\nDTSTART:20241001T080000Z\nEND:VEVENT\nEND:VCALENDAR";
+        let entries = parse_ics(input);
+        assert_eq!(entries[0].title, "Mathematik I, Übung\nGruppe B");
+        assert_eq!(entries[0].location, "H 1; Raum C:\\tmp");
+
+        assert_eq!(
+            unescape_text("Mathematik I\\, Übung"),
+            "Mathematik I, Übung"
+        );
+        assert_eq!(unescape_text("a\\Nb\\x"), "a\nb\\x");
+        assert_eq!(unescape_text("trailing\\"), "trailing\\");
     }
 
     #[test]
     fn captures_recurrence_frequency() {
-        let input = "BEGIN:VCALENDAR\nBEGIN:VEVENT\nSUMMARY:Weekly Seminar\nDTSTART:20241001T080000Z\nDTEND:20241001T090000Z\nRRULE:FREQ=WEEKLY;BYDAY=TU\nEND:VEVENT\nEND:VCALENDAR";
+        let input = "BEGIN:VCALENDAR\nBEGIN:VEVENT\nUID:seminar-1@ur.de\nSUMMARY:Weekly Seminar\nDTSTART:20241001T080000Z\nDTEND:20241001T090000Z\nRRULE:FREQ=WEEKLY;BYDAY=TU\nEND:VEVENT\nEND:VCALENDAR";
         let entries = parse_ics(input);
         assert_eq!(entries.len(), 1);
         let entry = &entries[0];
         assert!(matches!(entry.recurrence, Some(Recurrence::Weekly)));
+        assert_eq!(entry.uid.as_deref(), Some("seminar-1@ur.de"));
+    }
+
+    #[test]
+    fn captures_bi_weekly_rule_with_several_days() {
+        let input = "BEGIN:VCALENDAR\nBEGIN:VEVENT\nSUMMARY:Tutorium\nDTSTART:20250107T080000Z\nRRULE:FREQ=WEEKLY;INTERVAL=2;UNTIL=20250131T000000Z;BYDAY=TU,TH\nEND:VEVENT\nEND:VCALENDAR";
+        let entries = parse_ics(input);
+        let rule = entries[0].recurrence_rule.as_ref().unwrap();
+        assert_eq!(rule.freq, crate::model::Freq::Weekly);
+        assert_eq!(rule.interval, 2);
+        assert_eq!(rule.by_day, [chrono::Weekday::Tue, chrono::Weekday::Thu]);
+        assert!(rule.until.is_some() && rule.count.is_none());
+        assert!(matches!(entries[0].recurrence, Some(Recurrence::Weekly)));
+    }
+
+    #[test]
+    fn tolerates_leading_byte_order_mark() {
+        let input = "\u{feff}BEGIN:VCALENDAR\nBEGIN:VEVENT\nSUMMARY:Lecture\nDTSTART:20241001T080000Z\nEND:VEVENT\nEND:VCALENDAR";
+        let entries = parse_ics(input);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].title, "Lecture");
+    }
+
+    #[test]
+    fn single_all_day_event_occupies_one_day() {
+        let input = "BEGIN:VCALENDAR\nBEGIN:VEVENT\nSUMMARY:Dies Academicus\nDTSTART;VALUE=DATE:20241001\nDTEND;VALUE=DATE:20241002\nEND:VEVENT\nEND:VCALENDAR";
+        let entries = parse_ics(input);
+        assert_eq!(entries.len(), 1);
+        let entry = &entries[0];
+        assert_eq!(entry.date, "2024-10-01");
+        assert_eq!(entry.day_count(), Some(1));
+        assert_eq!(entry.last_day(), NaiveDate::from_ymd_opt(2024, 10, 1));
+        assert_eq!(entry.duration(), Some(chrono::Duration::days(1)));
+        assert!(entry.all_day);
+        assert_eq!(entry.time_range, None);
+    }
+
+    #[test]
+    fn flags_date_only_starts_as_all_day() {
+        let input = "BEGIN:VCALENDAR\nBEGIN:VEVENT\nSUMMARY:Vorlesungsfreie Zeit\nDTSTART:20250101\n\
+            END:VEVENT\nBEGIN:VEVENT\nSUMMARY:Mitternachtsvorlesung\nDTSTART:20250101T000000\n\
+            DTEND:20250101T013000\nEND:VEVENT\nEND:VCALENDAR";
+        let entries = parse_ics(input);
+        assert!(entries[0].all_day);
+        assert_eq!(entries[0].time_range, None);
+        assert_eq!(entries[0].date, "2025-01-01");
+        assert_eq!(entries[0].to_string(), "2025-01-01 Vorlesungsfreie Zeit");
+
+        assert!(!entries[1].all_day);
+        assert_eq!(entries[1].time(), "00:00 - 01:30");
+    }
+
+    fn sample_calendar(events: usize, description_len: usize) -> String {
+        let description = "Dozent: Prof. Dr. Beispiel; Hinweise zur Veranstaltung. "
+            .repeat(description_len / 56 + 1);
+        let mut ics = String::from("BEGIN:VCALENDAR\nVERSION:2.0\n");
+        for i in 0..events {
+            ics.push_str(&format!(
+                "BEGIN:VEVENT\nUID:event-{i}@ur.de\nSUMMARY:Vorlesung {i}\nDESCRIPTION:{description}\nLOCATION:H {room}\nDTSTART:20241001T{hour:02}0000Z\nDTEND:20241001T{end:02}0000Z\nRRULE:FREQ=WEEKLY\nX-FOO:bar\nEND:VEVENT\n",
+                room = i % 20,
+                hour = 8 + i % 8,
+                end = 9 + i % 8,
+            ));
+        }
+        ics.push_str("END:VCALENDAR\n");
+        ics
+    }
+
+    #[test]
+    fn masked_fields_are_left_empty() {
+        let ics = sample_calendar(3, 100);
+
+        let times_only = ParseOptions::default().fields(FieldMask::TIMES);
+        let entries = parse_ics_with(&ics, &times_only);
+        assert_eq!(entries.len(), 3);
+        assert!(entries.iter().all(|e| e.title.is_empty()
+            && e.location.is_empty()
+            && e.recurrence.is_none()
+            && e.extra.is_empty()
+            && e.time_range.is_some()));
+
+        let title_only = ParseOptions::default().fields(FieldMask::TITLE);
+        let entries = parse_ics_with(&ics, &title_only);
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].title, "Vorlesung 0");
+        assert!(entries[0].date.is_empty() && entries[0].time_range.is_none());
+
+        let description_only = ParseOptions::default().fields(FieldMask::DESCRIPTION);
+        let entries = parse_ics_with(&ics, &description_only);
+        assert!(entries[0].title.is_empty());
+        assert!(
+            entries[0]
+                .description
+                .as_deref()
+                .is_some_and(|d| d.starts_with("Dozent:"))
+        );
+
+        let location = ParseOptions::default().fields(FieldMask::LOCATION | FieldMask::RECURRENCE);
+        let entries = parse_ics_with(&ics, &location);
+        assert_eq!(entries[1].location, "H 1");
+        assert_eq!(entries[1].recurrence, Some(Recurrence::Weekly));
+        assert!(entries[1].title.is_empty());
+
+        let raw = ParseOptions::default().fields(FieldMask::RAW);
+        let entries = parse_ics_with(&ics, &raw);
+        assert!(entries[0].title.is_empty());
+        assert!(
+            entries[0]
+                .extra
+                .contains(&("X-FOO".to_string(), "bar".to_string()))
+        );
+        assert_eq!(entries[0].extra[0].0, "UID");
+    }
+
+    #[test]
+    fn default_mask_matches_parse_ics() {
+        let ics = sample_calendar(5, 100);
+        assert_eq!(
+            FieldMask::default(),
+            FieldMask::all().difference(FieldMask::RAW)
+        );
+        assert_eq!(
+            parse_ics_with(&ics, &ParseOptions::default()),
+            parse_ics(&ics)
+        );
+    }
+
+    #[test]
+    fn content_hashes_require_their_fields() {
+        let ics = sample_calendar(2, 10);
+        let err = parse_ics_content_hashes(&ics, &ParseOptions::default().fields(FieldMask::TIMES))
+            .unwrap_err();
+        assert_eq!(
+            err.missing,
+            FieldMask::TITLE | FieldMask::LOCATION | FieldMask::RECURRENCE
+        );
+        assert_eq!(
+            format!("{:?}", err.missing),
+            "TITLE | LOCATION | RECURRENCE"
+        );
+
+        let hashes = parse_ics_content_hashes(
+            &ics,
+            &ParseOptions::default().fields(FieldMask::CONTENT_HASH),
+        )
+        .unwrap();
+        let full: Vec<u64> = parse_ics(&ics)
+            .iter()
+            .map(TimetableEntry::content_hash)
+            .collect();
+        assert_eq!(hashes, full);
+    }
+
+    #[test]
+    fn stable_ids_require_their_fields() {
+        let ics = sample_calendar(2, 10);
+        let err = parse_ics_stable_ids(&ics, &ParseOptions::default().fields(FieldMask::TITLE))
+            .unwrap_err();
+        assert_eq!(err.missing, FieldMask::TIMES);
+
+        let ids = parse_ics_stable_ids(&ics, &ParseOptions::default().fields(FieldMask::STABLE_ID))
+            .unwrap();
+        let full: Vec<String> = parse_ics(&ics)
+            .iter()
+            .map(TimetableEntry::stable_id)
+            .collect();
+        assert_eq!(ids, full);
+    }
+
+    #[test]
+    fn minimal_mask_keeps_nothing_of_large_calendars_but_its_fields() {
+        let ics = sample_calendar(1_000, 2_000);
+        let minimal = ParseOptions::default().fields(FieldMask::STABLE_ID);
+        let entries = parse_ics_with(&ics, &minimal);
+        assert_eq!(entries.len(), 1_000);
+        assert!(entries.iter().all(|e| !e.title.is_empty()
+            && e.time_range.is_some()
+            && e.description.is_none()
+            && e.location.is_empty()
+            && e.recurrence.is_none()
+            && e.recurrence_rule.is_none()
+            && e.extra.is_empty()));
+        assert!(parse_ics_content_hashes(&ics, &minimal).is_err());
     }
 }
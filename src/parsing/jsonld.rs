@@ -0,0 +1,184 @@
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime};
+use serde_json::Value;
+
+use crate::{
+    model::{TimeRange, TimetableEntry, to_local_datetime},
+    parsing::dom::{parse_document, select_elements},
+};
+
+/// Reads schema.org `Event` objects from the page's `application/ld+json` scripts.
+///
+/// Scripts that are not valid JSON are skipped, as are objects without a start date or with
+/// neither a name nor a description. A date-only `startDate` makes an all-day entry.
+pub fn parse_jsonld_events(html: &str) -> Vec<TimetableEntry> {
+    let document = parse_document(html);
+    let mut entries = Vec::new();
+
+    for script in select_elements(&document, "script[type='application/ld+json']") {
+        let Ok(value) = serde_json::from_str::<Value>(&script.text_contents()) else {
+            continue;
+        };
+        let mut events = Vec::new();
+        collect_events(&value, &mut events);
+        entries.extend(events.into_iter().filter_map(entry_from_event));
+    }
+
+    entries
+}
+
+fn collect_events<'a>(value: &'a Value, events: &mut Vec<&'a Value>) {
+    match value {
+        Value::Array(items) => items.iter().for_each(|item| collect_events(item, events)),
+        Value::Object(object) => {
+            if is_event(value) {
+                events.push(value);
+            }
+            if let Some(graph) = object.get("@graph") {
+                collect_events(graph, events);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// `Event` and its subtypes, such as `EducationEvent`.
+fn is_event(value: &Value) -> bool {
+    let is_event_type = |t: &Value| t.as_str().is_some_and(|t| t.ends_with("Event"));
+    match value.get("@type") {
+        Some(Value::Array(types)) => types.iter().any(is_event_type),
+        Some(t) => is_event_type(t),
+        None => false,
+    }
+}
+
+fn entry_from_event(event: &Value) -> Option<TimetableEntry> {
+    let text = |key: &str| {
+        event
+            .get(key)
+            .and_then(Value::as_str)
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+    };
+
+    let (start, all_day) = text("startDate").and_then(parse_jsonld_date)?;
+    let end = text("endDate")
+        .and_then(parse_jsonld_date)
+        .map(|(end, _)| end);
+    let title = text("name");
+    let description = text("description");
+    if title.is_none() && description.is_none() {
+        return None;
+    }
+
+    let date = start.format("%Y-%m-%d").to_string();
+    let time_range = (!all_day).then(|| TimeRange::new(start.time(), end.map(|e| e.time())));
+    let location = event.get("location").map(location_text).unwrap_or_default();
+
+    let mut entry = TimetableEntry::new(
+        date,
+        time_range,
+        title.unwrap_or_default().to_string(),
+        location,
+        None,
+    );
+    entry.start = Some(start);
+    entry.end = end;
+    entry.all_day = all_day;
+    entry.description = description.map(str::to_string);
+    entry.uid = text("identifier")
+        .or_else(|| text("@id"))
+        .map(str::to_string);
+    Some(entry)
+}
+
+/// A location may be a plain string, a `Place` with a name and/or address, or a list of those.
+fn location_text(location: &Value) -> String {
+    match location {
+        Value::String(name) => name.trim().to_string(),
+        Value::Array(items) => items.first().map(location_text).unwrap_or_default(),
+        Value::Object(place) => place
+            .get("name")
+            .or_else(|| place.get("address"))
+            .map(|value| match value {
+                Value::Object(address) => address
+                    .get("streetAddress")
+                    .and_then(Value::as_str)
+                    .unwrap_or_default()
+                    .trim()
+                    .to_string(),
+                other => location_text(other),
+            })
+            .unwrap_or_default(),
+        _ => String::new(),
+    }
+}
+
+/// Accepts ISO 8601 timestamps with or without offset, with or without seconds, and plain
+/// dates (taken as local midnight); the flag tells whether `value` was a plain date.
+fn parse_jsonld_date(value: &str) -> Option<(DateTime<Local>, bool)> {
+    if let Ok(dt) = DateTime::parse_from_rfc3339(value) {
+        return Some((dt.with_timezone(&Local), false));
+    }
+    for fmt in ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M"] {
+        if let Ok(naive) = NaiveDateTime::parse_from_str(value, fmt) {
+            return Some((to_local_datetime(naive), false));
+        }
+    }
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .ok()
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .map(|midnight| (to_local_datetime(midnight), true))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_event_objects_from_ld_json_scripts() {
+        let html = r#"<html><head>
+            <script type="application/ld+json">{"@context":"https://schema.org","@type":"WebPage","name":"Stundenplan"}</script>
+            <script type="application/ld+json">[
+              {"@context":"https://schema.org","@type":"EducationEvent","name":"Analysis I",
+               "startDate":"2025-04-22T10:15:00","endDate":"2025-04-22T11:45:00",
+               "location":{"@type":"Place","name":"H 21"},"identifier":"ana-1@ur"},
+              {"@type":["Event"],"name":"Lineare Algebra I","startDate":"2025-04-23T08:30",
+               "endDate":"2025-04-23T10:00","location":"PHY 5.0.20"}
+            ]</script>
+            <script type="application/ld+json">{"@graph":[{"@type":"Event","name":"Tag der offenen Tür","startDate":"2025-05-01"}]}</script>
+            <script type="application/ld+json">not json</script>
+            </head><body></body></html>"#;
+
+        let entries = parse_jsonld_events(html);
+        assert_eq!(entries.len(), 3);
+
+        assert_eq!(entries[0].title, "Analysis I");
+        assert_eq!(entries[0].date, "2025-04-22");
+        assert_eq!(entries[0].time(), "10:15 - 11:45");
+        assert_eq!(entries[0].location, "H 21");
+        assert_eq!(entries[0].uid.as_deref(), Some("ana-1@ur"));
+
+        assert_eq!(entries[1].location, "PHY 5.0.20");
+        assert_eq!(entries[1].time(), "08:30 - 10:00");
+
+        assert_eq!(entries[2].date, "2025-05-01");
+        assert_eq!(entries[2].end, None);
+        assert!(entries[2].all_day && entries[2].time_range.is_none());
+        assert!(!entries[0].all_day);
+    }
+
+    #[test]
+    fn skips_events_without_a_start_date() {
+        let html = r#"<script type="application/ld+json">[
+              {"@type":"Event","name":"Ohne Termin","description":"Wird noch bekannt gegeben"},
+              {"@type":"Event","startDate":"2025-04-22T10:15"},
+              {"@type":"Event","name":"Analysis I","startDate":"not a date"},
+              {"@type":"Event","description":"Sprechstunde","startDate":"2025-04-24T14:00"}
+            ]</script>"#;
+
+        let entries = parse_jsonld_events(html);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].description.as_deref(), Some("Sprechstunde"));
+        assert!(entries[0].start.is_some());
+    }
+}
@@ -1,2 +1,3 @@
 pub mod dom;
 pub mod ics;
+pub mod jsonld;
@@ -0,0 +1,567 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    time::Duration,
+};
+
+use chrono::{DateTime, Datelike, Local, Months, NaiveDate, NaiveDateTime};
+
+use crate::model::{Freq, RecurrenceRule, TimetableEntry, fnv1a, to_local_datetime};
+
+/// How an entry from an older snapshot relates to the current timetable.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RefreshOutcome {
+    Unchanged,
+    Updated(Box<TimetableEntry>),
+    Gone,
+}
+
+/// Locates the current version of `entry` in `current`.
+///
+/// Entries are matched by `UID` when both sides carry one, otherwise by
+/// [`TimetableEntry::stable_id`].
+pub fn find_current<'a>(
+    entry: &TimetableEntry,
+    current: &'a [TimetableEntry],
+) -> Option<&'a TimetableEntry> {
+    if let Some(uid) = entry.uid.as_deref()
+        && let Some(found) = current.iter().find(|c| c.uid.as_deref() == Some(uid))
+    {
+        return Some(found);
+    }
+
+    let stable_id = entry.stable_id();
+    current
+        .iter()
+        .filter(|c| entry.uid.is_none() || c.uid.is_none())
+        .find(|c| c.stable_id() == stable_id)
+}
+
+/// Classifies `entry` against the current timetable, treating semantically equal versions as
+/// unchanged.
+pub fn refresh_outcome(entry: &TimetableEntry, current: &[TimetableEntry]) -> RefreshOutcome {
+    match find_current(entry, current) {
+        Some(found) if found.semantically_equal(entry) => RefreshOutcome::Unchanged,
+        Some(found) => RefreshOutcome::Updated(Box::new(found.clone())),
+        None => RefreshOutcome::Gone,
+    }
+}
+
+/// A hash of the whole timetable that ignores entry order and provenance (`uid`, `extra`).
+///
+/// Two timetables have the same fingerprint exactly when they hold the same multiset of
+/// [`content_hash`](TimetableEntry::content_hash)es, so it is a cheap "did anything change".
+pub fn timetable_fingerprint(entries: &[TimetableEntry]) -> u64 {
+    let mut hashes: Vec<String> = entries
+        .iter()
+        .map(|entry| format!("{:016x}", entry.content_hash()))
+        .collect();
+    hashes.sort_unstable();
+    let parts: Vec<&str> = hashes.iter().map(String::as_str).collect();
+    fnv1a(&parts)
+}
+
+/// Drops entries whose `UID` already appeared earlier in `entries`, keeping the first one.
+///
+/// Entries without a `UID` are all kept, since nothing identifies them as the same event.
+pub fn dedup_by_uid(entries: Vec<TimetableEntry>) -> Vec<TimetableEntry> {
+    let mut seen = HashSet::new();
+    entries
+        .into_iter()
+        .filter(|entry| {
+            entry
+                .uid
+                .as_ref()
+                .is_none_or(|uid| seen.insert(uid.clone()))
+        })
+        .collect()
+}
+
+/// Buckets `entries` by the local date they start on, each day ordered by start time.
+///
+/// Entries without a `start` are skipped; entries starting at the same time keep their order.
+pub fn group_by_day(entries: &[TimetableEntry]) -> BTreeMap<NaiveDate, Vec<TimetableEntry>> {
+    let mut days: BTreeMap<NaiveDate, Vec<TimetableEntry>> = BTreeMap::new();
+    for entry in entries {
+        if let Some(start) = entry.start {
+            days.entry(start.date_naive())
+                .or_default()
+                .push(entry.clone());
+        }
+    }
+    for day in days.values_mut() {
+        day.sort_by_key(|entry| entry.start);
+    }
+    days
+}
+
+/// Orders `entries` by start time, moving entries without a `start` to the end.
+///
+/// The sort is stable: entries starting at the same time, and undated entries among
+/// themselves, keep their order.
+pub fn sort_chronologically(entries: &mut [TimetableEntry]) {
+    entries.sort_by_key(|entry| (entry.start.is_none(), entry.start));
+}
+
+/// Merges a fresh export into a locally annotated copy of the timetable.
+///
+/// Entries follow `incoming`, except that entries of `base` whose `UID` is in
+/// `user_edited_uids` are kept as they are, even if the portal changed or dropped them.
+pub fn merge_preserving(
+    base: &[TimetableEntry],
+    incoming: &[TimetableEntry],
+    user_edited_uids: &HashSet<String>,
+) -> Vec<TimetableEntry> {
+    let is_edited = |entry: &TimetableEntry| {
+        entry
+            .uid
+            .as_ref()
+            .is_some_and(|uid| user_edited_uids.contains(uid))
+    };
+    let edited: Vec<&TimetableEntry> = base.iter().filter(|e| is_edited(e)).collect();
+
+    let mut merged: Vec<TimetableEntry> = incoming
+        .iter()
+        .map(|entry| {
+            let local = edited
+                .iter()
+                .find(|e| e.uid.is_some() && e.uid == entry.uid);
+            local.map_or_else(|| entry.clone(), |local| (*local).clone())
+        })
+        .collect();
+    merged.extend(
+        edited
+            .into_iter()
+            .filter(|local| !incoming.iter().any(|e| e.uid == local.uid))
+            .cloned(),
+    );
+    merged
+}
+
+/// The next `n` occurrences starting at or after `now`, earliest first.
+///
+/// Recurring entries are expanded into one-off instances (with the recurrence cleared) by
+/// their full [`RecurrenceRule`] when known, else by their frequency; custom frequencies are
+/// treated as one-off. Entries without a start are skipped.
+pub fn upcoming(entries: &[TimetableEntry], now: DateTime<Local>, n: usize) -> Vec<TimetableEntry> {
+    let mut instances: Vec<TimetableEntry> = entries
+        .iter()
+        .flat_map(|entry| occurrences_from(entry, now).take(n))
+        .collect();
+    instances.sort_by_key(|entry| entry.start);
+    instances.truncate(n);
+    instances
+}
+
+/// Occurrences of `entry` starting at or after `from`, as one-off entries.
+fn occurrences_from(
+    entry: &TimetableEntry,
+    from: DateTime<Local>,
+) -> Box<dyn Iterator<Item = TimetableEntry> + '_> {
+    let Some(start) = entry.start else {
+        return Box::new(std::iter::empty());
+    };
+    let rule = entry.recurrence_rule.clone().or_else(|| {
+        let freq = entry.recurrence.as_ref()?.freq()?;
+        Some(RecurrenceRule::new(freq))
+    });
+    let Some(rule) = rule else {
+        return Box::new((start >= from).then(|| entry.clone()).into_iter());
+    };
+
+    let length = entry.end.map(|end| end - start);
+    let until = rule.until;
+    let count = rule.count.map_or(usize::MAX, |count| count as usize);
+    Box::new(
+        rule_starts(rule, start.naive_local())
+            .map(to_local_datetime)
+            .take_while(move |begin| until.is_none_or(|until| *begin <= until))
+            .take(count)
+            .skip_while(move |begin| *begin < from)
+            .map(move |begin| {
+                let mut instance = entry.clone();
+                instance.date = begin.format("%Y-%m-%d").to_string();
+                instance.start = Some(begin);
+                instance.end = length.map(|length| begin + length);
+                instance.recurrence = None;
+                instance.recurrence_rule = None;
+                instance
+            }),
+    )
+}
+
+/// The wall-clock starts described by `rule` for a series beginning at `first`, ignoring
+/// `UNTIL` and `COUNT`.
+///
+/// Stepping on the wall clock keeps a 10:00 class at 10:00 across DST changes. `BYDAY`
+/// picks the days within each week for weekly rules and filters daily ones; monthly and
+/// yearly rules ignore it.
+fn rule_starts(rule: RecurrenceRule, first: NaiveDateTime) -> impl Iterator<Item = NaiveDateTime> {
+    let mut by_day = rule.by_day;
+    by_day.sort_by_key(|day| day.num_days_from_monday());
+    by_day.dedup();
+    let interval = rule.interval.max(1);
+    let period = move |k: u32| -> Option<NaiveDateTime> {
+        let steps = k.checked_mul(interval)?;
+        match rule.freq {
+            Freq::Daily => first.checked_add_signed(chrono::Duration::days(steps.into())),
+            Freq::Weekly => first.checked_add_signed(chrono::Duration::weeks(steps.into())),
+            Freq::Monthly => first.checked_add_months(Months::new(steps)),
+            Freq::Yearly => first.checked_add_months(Months::new(steps.checked_mul(12)?)),
+        }
+    };
+    (0..=u32::MAX)
+        .map_while(period)
+        .flat_map(move |base| -> Vec<NaiveDateTime> {
+            match rule.freq {
+                _ if by_day.is_empty() => vec![base],
+                Freq::Weekly => {
+                    let monday =
+                        base - chrono::Duration::days(base.weekday().num_days_from_monday().into());
+                    by_day
+                        .iter()
+                        .map(|day| {
+                            monday + chrono::Duration::days(day.num_days_from_monday().into())
+                        })
+                        .collect()
+                }
+                Freq::Daily if !by_day.contains(&base.weekday()) => Vec::new(),
+                _ => vec![base],
+            }
+        })
+        .filter(move |begin| *begin >= first)
+}
+
+/// Finds consecutive same-day entries in different locations where the second starts no more
+/// than `max_transfer` after the first ends.
+///
+/// Entries without a start and end, or without a location, are ignored. Overlapping entries
+/// are not reported, since there is no transfer between them to make.
+pub fn tight_transitions(
+    entries: &[TimetableEntry],
+    max_transfer: Duration,
+) -> Vec<(TimetableEntry, TimetableEntry)> {
+    let mut timed: Vec<&TimetableEntry> = entries
+        .iter()
+        .filter(|e| e.start.is_some() && e.end.is_some() && !e.location.trim().is_empty())
+        .collect();
+    timed.sort_by_key(|e| e.start);
+
+    let max_transfer = chrono::Duration::from_std(max_transfer).unwrap_or(chrono::Duration::MAX);
+    timed
+        .windows(2)
+        .filter_map(|pair| {
+            let (first, second) = (pair[0], pair[1]);
+            let (first_end, second_start) = (first.end?, second.start?);
+            let same_day = first.start?.date_naive() == second_start.date_naive();
+            let gap = second_start - first_end;
+            let moves = !first
+                .location
+                .trim()
+                .eq_ignore_ascii_case(second.location.trim());
+            (same_day && moves && gap >= chrono::Duration::zero() && gap <= max_transfer)
+                .then(|| (first.clone(), second.clone()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::Recurrence;
+
+    fn entry(title: &str, time: &str, location: &str, uid: Option<&str>) -> TimetableEntry {
+        let mut entry = TimetableEntry::new(
+            "2025-01-01".to_string(),
+            time.parse().ok(),
+            title.to_string(),
+            location.to_string(),
+            None,
+        );
+        entry.uid = uid.map(str::to_string);
+        entry
+    }
+
+    #[test]
+    fn matches_by_uid_even_when_everything_else_moved() {
+        let old = entry("Analysis", "10:00 - 12:00", "H 1", Some("a@ur"));
+        let current = vec![
+            entry("Algebra", "10:00 - 12:00", "H 1", Some("b@ur")),
+            entry("Analysis I", "14:00 - 16:00", "H 3", Some("a@ur")),
+        ];
+        assert_eq!(
+            refresh_outcome(&old, &current),
+            RefreshOutcome::Updated(Box::new(current[1].clone()))
+        );
+    }
+
+    #[test]
+    fn falls_back_to_stable_id_without_uid() {
+        let old = entry("Analysis", "10:00 - 12:00", "H 1", None);
+        let moved = entry("Analysis", "10:00 - 12:00", "H 2", None);
+        assert_eq!(
+            refresh_outcome(&old, std::slice::from_ref(&moved)),
+            RefreshOutcome::Updated(Box::new(moved))
+        );
+        assert_eq!(
+            refresh_outcome(&old, std::slice::from_ref(&old)),
+            RefreshOutcome::Unchanged
+        );
+    }
+
+    #[test]
+    fn provenance_changes_do_not_count_as_updates() {
+        let old = entry("Analysis", "10:00 - 12:00", "H 1", None);
+        let reexported = entry("Analysis", "10:00 - 12:00", "H 1", Some("new@ur"));
+        assert_eq!(
+            refresh_outcome(&old, &[reexported]),
+            RefreshOutcome::Unchanged
+        );
+    }
+
+    #[test]
+    fn fingerprint_ignores_order_and_provenance() {
+        let analysis = entry("Analysis", "10:00 - 12:00", "H 1", Some("a@ur"));
+        let algebra = entry("Algebra", "12:15 - 13:45", "H 2", None);
+        let fingerprint = timetable_fingerprint(&[analysis.clone(), algebra.clone()]);
+
+        let mut reexported = analysis.clone();
+        reexported.uid = Some("other@ur".to_string());
+        assert_eq!(
+            timetable_fingerprint(&[algebra.clone(), reexported]),
+            fingerprint
+        );
+
+        let mut moved = algebra.clone();
+        moved.location = "H 3".to_string();
+        assert_ne!(
+            timetable_fingerprint(&[analysis.clone(), moved]),
+            fingerprint
+        );
+        assert_ne!(timetable_fingerprint(&[analysis]), fingerprint);
+        assert_ne!(timetable_fingerprint(&[]), fingerprint);
+    }
+
+    #[test]
+    fn dedup_keeps_the_first_entry_per_uid() {
+        let first = entry("Analysis", "10:00 - 12:00", "H 1", Some("ana@ur"));
+        let moved = entry("Analysis", "14:00 - 16:00", "H 2", Some("ana@ur"));
+        let other = entry("Algebra", "10:00 - 12:00", "H 3", Some("alg@ur"));
+        let anonymous = entry("Tutorium", "08:00", "H 4", None);
+
+        let deduped = dedup_by_uid(vec![
+            first.clone(),
+            anonymous.clone(),
+            moved,
+            other.clone(),
+            anonymous.clone(),
+        ]);
+        assert_eq!(deduped, [first, anonymous.clone(), other, anonymous]);
+    }
+
+    #[test]
+    fn groups_entries_by_day_in_start_order() {
+        let algebra = entry("Algebra", "14:00 - 16:00", "H 2", None);
+        let analysis = entry("Analysis", "10:00 - 12:00", "H 1", None);
+        let seminar = TimetableEntry::new(
+            "2025-01-02".into(),
+            "08:00 - 10:00".parse().ok(),
+            "Seminar".into(),
+            "H 3".into(),
+            None,
+        );
+        let undated =
+            TimetableEntry::new(String::new(), None, "Lerngruppe".into(), "".into(), None);
+
+        let days = group_by_day(&[algebra.clone(), seminar.clone(), undated, analysis.clone()]);
+        let first = NaiveDate::from_ymd_opt(2025, 1, 1).unwrap();
+        let second = NaiveDate::from_ymd_opt(2025, 1, 2).unwrap();
+        assert_eq!(days.keys().copied().collect::<Vec<_>>(), [first, second]);
+        assert_eq!(days[&first], [analysis, algebra]);
+        assert_eq!(days[&second], [seminar]);
+    }
+
+    #[test]
+    fn sorts_by_start_keeping_ties_and_undated_entries_in_order() {
+        let algebra = entry("Algebra", "14:00 - 16:00", "H 2", None);
+        let analysis = entry("Analysis", "10:00 - 12:00", "H 1", None);
+        let tutorial = entry("Tutorium", "10:00 - 11:00", "H 4", None);
+        let seminar = TimetableEntry::new(
+            "2024-12-31".into(),
+            "16:00 - 18:00".parse().ok(),
+            "Seminar".into(),
+            "H 3".into(),
+            None,
+        );
+        let undated =
+            TimetableEntry::new(String::new(), None, "Lerngruppe".into(), "".into(), None);
+        let also_undated =
+            TimetableEntry::new(String::new(), None, "Sprechstunde".into(), "".into(), None);
+
+        let mut entries = vec![
+            undated.clone(),
+            algebra.clone(),
+            tutorial.clone(),
+            also_undated.clone(),
+            seminar.clone(),
+            analysis.clone(),
+        ];
+        sort_chronologically(&mut entries);
+        assert_eq!(
+            entries,
+            [seminar, tutorial, analysis, algebra, undated, also_undated]
+        );
+    }
+
+    #[test]
+    fn merge_keeps_locally_edited_uids() {
+        let mut annotated = entry(
+            "Analysis (bring laptop)",
+            "10:00 - 12:00",
+            "H 1",
+            Some("a@ur"),
+        );
+        annotated.location = "H 1, front row".to_string();
+        let base = vec![
+            annotated.clone(),
+            entry("Algebra", "12:15 - 13:45", "H 2", Some("b@ur")),
+            entry("Seminar", "16:00 - 18:00", "H 4", Some("c@ur")),
+        ];
+        let incoming = vec![
+            entry("Analysis", "10:15 - 12:00", "H 3", Some("a@ur")),
+            entry("Algebra", "12:15 - 13:45", "H 5", Some("b@ur")),
+            entry("Tutorium", "14:00 - 15:00", "H 2", Some("d@ur")),
+        ];
+        let edited = HashSet::from(["a@ur".to_string(), "c@ur".to_string()]);
+
+        let merged = merge_preserving(&base, &incoming, &edited);
+        assert_eq!(
+            merged,
+            vec![
+                annotated,
+                incoming[1].clone(),
+                incoming[2].clone(),
+                base[2].clone()
+            ]
+        );
+    }
+
+    #[test]
+    fn upcoming_expands_recurrences_in_start_order() {
+        let mut weekly = entry("Analysis", "10:00 - 12:00", "H 1", None);
+        weekly.recurrence = Some(Recurrence::Weekly);
+        let mut monthly = TimetableEntry::new(
+            "2025-01-31".to_string(),
+            "14:00 - 15:00".parse().ok(),
+            "Kolloquium".to_string(),
+            "H 4".to_string(),
+            Some(Recurrence::Monthly),
+        );
+        monthly.uid = Some("kolloquium@ur".to_string());
+        let once = TimetableEntry::new(
+            "2025-01-09".to_string(),
+            "08:00".parse().ok(),
+            "Klausur".to_string(),
+            "H 2".to_string(),
+            None,
+        );
+        let past = entry("Einführung", "09:00", "H 3", None);
+        let now = to_local_datetime(
+            NaiveDateTime::parse_from_str("2025-01-08 11:00", "%Y-%m-%d %H:%M").unwrap(),
+        );
+
+        let next = upcoming(&[weekly, monthly, once, past], now, 5);
+        let summary: Vec<(String, String)> = next
+            .iter()
+            .map(|e| (e.date.clone(), e.title.clone()))
+            .collect();
+        let expected = [
+            ("2025-01-09", "Klausur"),
+            ("2025-01-15", "Analysis"),
+            ("2025-01-22", "Analysis"),
+            ("2025-01-29", "Analysis"),
+            ("2025-01-31", "Kolloquium"),
+        ];
+        assert_eq!(
+            summary,
+            expected.map(|(date, title)| (date.to_string(), title.to_string()))
+        );
+        assert!(next.iter().all(|e| e.recurrence.is_none()));
+        assert_eq!(next[1].duration(), Some(chrono::Duration::hours(2)));
+        assert_eq!(next[1].time(), "10:00 - 12:00");
+
+        assert!(upcoming(&[], now, 3).is_empty());
+    }
+
+    #[test]
+    fn upcoming_follows_interval_by_day_and_count() {
+        let mut tutorial = TimetableEntry::new(
+            "2025-01-07".to_string(),
+            "08:00 - 10:00".parse().ok(),
+            "Tutorium".to_string(),
+            "H 5".to_string(),
+            Some(Recurrence::Weekly),
+        );
+        tutorial.recurrence_rule = Some(
+            "FREQ=WEEKLY;INTERVAL=2;COUNT=5;BYDAY=TU,TH"
+                .parse()
+                .unwrap(),
+        );
+        let now = to_local_datetime(
+            NaiveDateTime::parse_from_str("2025-01-08 00:00", "%Y-%m-%d %H:%M").unwrap(),
+        );
+
+        let dates: Vec<String> = upcoming(&[tutorial.clone()], now, 10)
+            .into_iter()
+            .map(|e| e.date)
+            .collect();
+        // Tue 7th (already past) counts towards COUNT=5.
+        assert_eq!(
+            dates,
+            ["2025-01-09", "2025-01-21", "2025-01-23", "2025-02-04"]
+        );
+
+        let rule = tutorial.recurrence_rule.as_mut().unwrap();
+        rule.count = None;
+        rule.until = Some(to_local_datetime(
+            NaiveDateTime::parse_from_str("2025-01-21 08:00", "%Y-%m-%d %H:%M").unwrap(),
+        ));
+        let dates: Vec<String> = upcoming(&[tutorial], now, 10)
+            .into_iter()
+            .map(|e| e.date)
+            .collect();
+        assert_eq!(dates, ["2025-01-09", "2025-01-21"]);
+    }
+
+    #[test]
+    fn flags_back_to_back_classes_in_different_rooms() {
+        let entries = vec![
+            entry("Algebra", "12:15 - 13:45", "PHY 5.0.20", None),
+            entry("Analysis", "10:00 - 12:00", "H 1", None),
+            entry("Tutorium", "14:00 - 15:00", "H 2", None),
+            entry("Seminar", "15:00 - 16:00", "h 2", None),
+        ];
+        let tight = tight_transitions(&entries, Duration::from_secs(15 * 60));
+        assert_eq!(tight.len(), 2);
+        assert_eq!(
+            (tight[0].0.title.as_str(), tight[0].1.title.as_str()),
+            ("Analysis", "Algebra")
+        );
+        assert_eq!(
+            (tight[1].0.title.as_str(), tight[1].1.title.as_str()),
+            ("Algebra", "Tutorium")
+        );
+
+        assert_eq!(
+            tight_transitions(&entries, Duration::from_secs(10 * 60)).len(),
+            0
+        );
+    }
+
+    #[test]
+    fn reports_gone_when_no_match_exists() {
+        let old = entry("Analysis", "10:00 - 12:00", "H 1", Some("a@ur"));
+        let current = vec![entry("Analysis", "10:00 - 12:00", "H 1", Some("other@ur"))];
+        assert_eq!(refresh_outcome(&old, &current), RefreshOutcome::Gone);
+        assert_eq!(refresh_outcome(&old, &[]), RefreshOutcome::Gone);
+    }
+}
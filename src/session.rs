@@ -0,0 +1,174 @@
+//! Saving a logged-in portal session so later runs can skip the login, and the credentials
+//! for logging in again when it expires.
+
+use std::{env, fmt};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+use crate::error::UrError;
+
+/// The environment variable the username is conventionally read from.
+pub const USER_ENV_VAR: &str = "UR_USER";
+/// The environment variable the password is conventionally read from.
+pub const PASSWORD_ENV_VAR: &str = "UR_PASSWORD";
+
+/// The cookies of a portal session, as returned by
+/// [`UrConnect::export_session`](crate::UrConnect::export_session).
+///
+/// Restore it with [`UrConnect::from_session`](crate::UrConnect::from_session). The cookies
+/// grant access to the account, so store the state like a password. `Debug` prints the cookie
+/// names only.
+#[derive(Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SessionState {
+    /// The portal origin the cookies belong to.
+    pub base_url: String,
+    /// `name=value` pairs, e.g. `JSESSIONID=...`.
+    pub cookies: Vec<String>,
+}
+
+impl SessionState {
+    /// The names of the saved cookies.
+    pub fn cookie_names(&self) -> impl Iterator<Item = &str> {
+        self.cookies.iter().map(|cookie| {
+            cookie
+                .split_once('=')
+                .map_or(cookie.as_str(), |(name, _)| name)
+        })
+    }
+}
+
+impl fmt::Debug for SessionState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SessionState")
+            .field("base_url", &self.base_url)
+            .field("cookies", &self.cookie_names().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+/// A username and password kept for logging in again; see
+/// [`UrConnect::with_credentials`](crate::UrConnect::with_credentials).
+///
+/// The password is wiped from memory when the credentials are dropped, and `Debug` hides it.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Credentials {
+    username: String,
+    password: Zeroizing<String>,
+}
+
+impl Credentials {
+    pub fn new(username: &str, password: &str) -> Self {
+        Self {
+            username: username.to_string(),
+            password: Zeroizing::new(password.to_string()),
+        }
+    }
+
+    /// Reads the username and password from the environment variables `user_var` and
+    /// `password_var`, e.g. [`USER_ENV_VAR`] and [`PASSWORD_ENV_VAR`].
+    ///
+    /// A variable that is unset or empty fails with [`UrError::MissingEnvVar`] naming it,
+    /// before anything is sent to the portal.
+    pub fn from_env(user_var: &str, password_var: &str) -> Result<Self, UrError> {
+        Self::from_env_with(user_var, password_var, |name| env::var(name).ok())
+    }
+
+    /// Like [`from_env`](Self::from_env), looking the variables up with `lookup`.
+    pub(crate) fn from_env_with(
+        user_var: &str,
+        password_var: &str,
+        lookup: impl Fn(&str) -> Option<String>,
+    ) -> Result<Self, UrError> {
+        let read = |name: &str| {
+            lookup(name)
+                .filter(|value| !value.trim().is_empty())
+                .ok_or_else(|| UrError::MissingEnvVar {
+                    name: name.to_string(),
+                })
+        };
+        Ok(Self {
+            username: read(user_var)?.trim().to_string(),
+            password: Zeroizing::new(read(password_var)?),
+        })
+    }
+
+    pub fn username(&self) -> &str {
+        &self.username
+    }
+
+    pub(crate) fn password(&self) -> &str {
+        &self.password
+    }
+}
+
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Credentials")
+            .field("username", &self.username)
+            .field("password", &"<redacted>")
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::UrConnect;
+
+    #[test]
+    fn restored_session_exports_the_same_cookies() {
+        let state = SessionState {
+            base_url: "https://portal.example.org".to_string(),
+            cookies: vec![
+                "JSESSIONID=0123456789ABCDEF".to_string(),
+                "lastRefresh=1735725600000".to_string(),
+            ],
+        };
+
+        let client = UrConnect::from_session(state.clone()).unwrap();
+        let mut exported = client.export_session();
+        exported.cookies.sort();
+        assert_eq!(exported, state);
+
+        let printed = format!("{state:?}");
+        assert!(printed.contains("JSESSIONID") && !printed.contains("0123456789ABCDEF"));
+    }
+
+    #[test]
+    fn reads_credentials_from_the_environment() {
+        let lookup = |name: &str| {
+            match name {
+                "USER" => Some(" student\n"),
+                "PASSWORD" => Some("hunter2"),
+                "EMPTY" => Some(" "),
+                _ => None,
+            }
+            .map(str::to_string)
+        };
+
+        let credentials = Credentials::from_env_with("USER", "PASSWORD", lookup).unwrap();
+        assert_eq!(credentials.username(), "student");
+        assert_eq!(credentials.password(), "hunter2");
+
+        for (user, password, missing) in
+            [("UNSET", "PASSWORD", "UNSET"), ("USER", "EMPTY", "EMPTY")]
+        {
+            let err = Credentials::from_env_with(user, password, lookup).unwrap_err();
+            assert!(matches!(&err, UrError::MissingEnvVar { name } if name == missing));
+            assert!(err.to_string().contains(missing));
+        }
+    }
+
+    #[test]
+    fn credentials_debug_hides_the_password() {
+        let credentials = Credentials::new("student", "hunter2");
+        for printed in [format!("{credentials:?}"), format!("{credentials:#?}")] {
+            assert!(printed.contains("student") && !printed.contains("hunter2"));
+            assert!(printed.contains("<redacted>"));
+        }
+        assert_eq!(credentials.password(), "hunter2");
+    }
+}
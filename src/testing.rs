@@ -0,0 +1,190 @@
+//! An in-process stand-in for the HISinOne portal, for offline tests and examples.
+//!
+//! [`FixturePortal`] implements [`HttpTransport`] and answers the requests of the login and
+//! timetable flow with the captured pages in `tests/fixtures/`, so the real navigation code
+//! runs without network access or credentials.
+
+use std::sync::{
+    Mutex,
+    atomic::{AtomicBool, Ordering},
+};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use reqwest::{StatusCode, Url, header::HeaderMap};
+
+use crate::{
+    config::PortalConfig,
+    transport::{FetchResult, HttpTransport},
+};
+
+pub const FIXTURE_USERNAME: &str = "student";
+pub const FIXTURE_PASSWORD: &str = "correct horse battery staple";
+
+const LOGIN_PAGE: &str = include_str!("../tests/fixtures/login.html");
+const LOGIN_FAILED_PAGE: &str = include_str!("../tests/fixtures/login_failed.html");
+const LANDING_PAGE: &str = include_str!("../tests/fixtures/landing.html");
+const TIMETABLE_ENTRY_PAGE: &str = include_str!("../tests/fixtures/timetable_entry.html");
+const TIMETABLE_FULL_PAGE: &str = include_str!("../tests/fixtures/timetable_full.html");
+const TIMETABLE_ICS: &str = include_str!("../tests/fixtures/timetable.ics");
+
+/// A fake portal that keeps its own login state and records every request it serves.
+pub struct FixturePortal {
+    base: Url,
+    username: String,
+    password: String,
+    ics: String,
+    logged_in: AtomicBool,
+    requests: Mutex<Vec<String>>,
+}
+
+impl FixturePortal {
+    /// Creates a portal answering for `base_url`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `base_url` is not a valid URL.
+    pub fn new(base_url: &str) -> Self {
+        Self {
+            base: Url::parse(base_url).expect("fixture portal base URL must be valid"),
+            username: FIXTURE_USERNAME.to_string(),
+            password: FIXTURE_PASSWORD.to_string(),
+            ics: TIMETABLE_ICS.to_string(),
+            logged_in: AtomicBool::new(false),
+            requests: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Replaces the accepted credentials.
+    pub fn with_credentials(mut self, username: &str, password: &str) -> Self {
+        self.username = username.to_string();
+        self.password = password.to_string();
+        self
+    }
+
+    /// Replaces the calendar served by the ICS export.
+    pub fn with_ics(mut self, ics: impl Into<String>) -> Self {
+        self.ics = ics.into();
+        self
+    }
+
+    /// A [`PortalConfig`] pointing at this portal with the default paths.
+    pub fn config(&self) -> PortalConfig {
+        PortalConfig {
+            base_url: self.base.as_str().trim_end_matches('/').to_string(),
+            ..PortalConfig::default()
+        }
+    }
+
+    /// Ends the session on the portal side, as a timeout would.
+    pub fn expire_session(&self) {
+        self.logged_in.store(false, Ordering::SeqCst);
+    }
+
+    pub fn is_logged_in(&self) -> bool {
+        self.logged_in.load(Ordering::SeqCst)
+    }
+
+    /// Every served request as `"METHOD url"`, oldest first.
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    fn respond(&self, method: &str, url: &Url, form: &[(String, String)]) -> FetchResult {
+        self.requests
+            .lock()
+            .unwrap()
+            .push(format!("{method} {url}"));
+
+        let (status, body) = if url.host_str() != self.base.host_str() {
+            (StatusCode::NOT_FOUND, String::new())
+        } else {
+            self.route(method, url, form)
+        };
+        FetchResult {
+            body,
+            final_url: url.clone(),
+            status,
+            headers: HeaderMap::new(),
+            refreshed_from: Vec::new(),
+        }
+    }
+
+    fn route(&self, method: &str, url: &Url, form: &[(String, String)]) -> (StatusCode, String) {
+        let path = url.path();
+        let query = |name: &str| {
+            url.query_pairs()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.into_owned())
+        };
+        let field = |name: &str| {
+            form.iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.as_str())
+        };
+
+        if path.ends_with("/individualTimetableCalendarExport.faces") {
+            return (StatusCode::OK, self.ics.clone());
+        }
+
+        if path.ends_with("/rds") && query("category").as_deref() == Some("auth.login") {
+            let accepted = method == "POST"
+                && field("asdf") == Some(self.username.as_str())
+                && field("fdsa") == Some(self.password.as_str());
+            self.logged_in.store(accepted, Ordering::SeqCst);
+            if !accepted {
+                return (StatusCode::OK, LOGIN_FAILED_PAGE.to_string());
+            }
+            return (StatusCode::OK, self.home_page());
+        }
+
+        if path.ends_with("/rds") && query("category").as_deref() == Some("auth.logout") {
+            self.logged_in.store(false, Ordering::SeqCst);
+            return (StatusCode::OK, LOGIN_PAGE.to_string());
+        }
+
+        if path.ends_with("/hisinoneStartPage.faces") {
+            return (StatusCode::OK, self.home_page());
+        }
+
+        if path.ends_with("/individualTimetable.xhtml") {
+            if !self.is_logged_in() {
+                return (StatusCode::OK, LOGIN_PAGE.to_string());
+            }
+            let page = if query("_flowExecutionKey").is_some() {
+                TIMETABLE_FULL_PAGE
+            } else {
+                TIMETABLE_ENTRY_PAGE
+            };
+            return (StatusCode::OK, page.to_string());
+        }
+
+        (StatusCode::NOT_FOUND, String::new())
+    }
+
+    fn home_page(&self) -> String {
+        if self.is_logged_in() {
+            LANDING_PAGE.to_string()
+        } else {
+            LOGIN_PAGE.to_string()
+        }
+    }
+}
+
+#[async_trait]
+impl HttpTransport for FixturePortal {
+    async fn get(&self, url: &Url, _referer: Option<&Url>) -> Result<FetchResult> {
+        Ok(self.respond("GET", url, &[]))
+    }
+
+    async fn post_form(
+        &self,
+        url: &Url,
+        _referer: Option<&Url>,
+        form: &[(String, String)],
+    ) -> Result<FetchResult> {
+        Ok(self.respond("POST", url, form))
+    }
+
+    fn add_cookie(&self, _cookie: &str, _url: &Url) {}
+}
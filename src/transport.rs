@@ -0,0 +1,449 @@
+use std::sync::{Arc, RwLock};
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use reqwest::{
+    Client, StatusCode, Url,
+    cookie::CookieStore,
+    header::{self, CACHE_CONTROL, HeaderMap, HeaderValue, ORIGIN, PRAGMA, REFERER},
+};
+
+/// A fetched page together with where the request finally ended up.
+pub struct FetchResult {
+    pub body: String,
+    pub final_url: Url,
+    pub status: StatusCode,
+    /// Response headers; transports that cannot report them leave this empty.
+    pub headers: HeaderMap,
+    /// The pages left through a `<meta http-equiv="refresh">` on the way to this one, oldest
+    /// first. Transports leave this empty; the client fills it in when it follows them.
+    pub refreshed_from: Vec<Url>,
+}
+
+/// The HTTP operations the portal workflow needs.
+#[async_trait]
+pub trait HttpTransport: Send + Sync {
+    async fn get(&self, url: &Url, referer: Option<&Url>) -> Result<FetchResult>;
+
+    /// A GET with additional request headers, such as `If-None-Match` for revalidation.
+    ///
+    /// The default ignores `headers`, which is always safe: the server then answers with the
+    /// full response.
+    async fn get_with(
+        &self,
+        url: &Url,
+        referer: Option<&Url>,
+        headers: &HeaderMap,
+    ) -> Result<FetchResult> {
+        let _ = headers;
+        self.get(url, referer).await
+    }
+
+    async fn post_form(
+        &self,
+        url: &Url,
+        referer: Option<&Url>,
+        form: &[(String, String)],
+    ) -> Result<FetchResult>;
+
+    /// Stores a cookie given in `Set-Cookie` syntax as if `url` had sent it.
+    fn add_cookie(&self, cookie: &str, url: &Url);
+
+    /// The `name=value` pairs a request to `url` would carry.
+    ///
+    /// Transports without a cookie store of their own report none, which makes
+    /// [`UrConnect::export_session`](crate::UrConnect::export_session) come back empty.
+    fn cookies(&self, url: &Url) -> Vec<String> {
+        let _ = url;
+        Vec::new()
+    }
+
+    /// Forgets every stored cookie. The default does nothing.
+    fn clear_cookies(&self) {}
+}
+
+/// The cookie store of a [`ReqwestTransport`]; unlike `reqwest`'s own jar it can be emptied.
+#[derive(Default)]
+pub struct CookieJar(RwLock<cookie_store::CookieStore>);
+
+impl CookieJar {
+    /// Stores a cookie given in `Set-Cookie` syntax as if `url` had sent it.
+    pub fn add_cookie_str(&self, cookie: &str, url: &Url) {
+        let _ = self.0.write().unwrap().parse(cookie, url);
+    }
+
+    pub fn clear(&self) {
+        self.0.write().unwrap().clear();
+    }
+}
+
+impl CookieStore for CookieJar {
+    fn set_cookies(&self, cookie_headers: &mut dyn Iterator<Item = &HeaderValue>, url: &Url) {
+        let mut store = self.0.write().unwrap();
+        for header in cookie_headers {
+            if let Ok(cookie) = header.to_str() {
+                let _ = store.parse(cookie, url);
+            }
+        }
+    }
+
+    fn cookies(&self, url: &Url) -> Option<HeaderValue> {
+        let header = self
+            .0
+            .read()
+            .unwrap()
+            .get_request_values(url)
+            .map(|(name, value)| format!("{name}={value}"))
+            .collect::<Vec<_>>()
+            .join("; ");
+        if header.is_empty() {
+            return None;
+        }
+        HeaderValue::from_str(&header).ok()
+    }
+}
+
+/// The default transport backed by a `reqwest` client and its cookie jar.
+pub struct ReqwestTransport {
+    client: Client,
+    jar: Arc<CookieJar>,
+}
+
+impl ReqwestTransport {
+    /// `jar` must be the cookie provider `client` was built with.
+    pub fn new(client: Client, jar: Arc<CookieJar>) -> Self {
+        Self { client, jar }
+    }
+}
+
+#[async_trait]
+impl HttpTransport for ReqwestTransport {
+    async fn get(&self, url: &Url, referer: Option<&Url>) -> Result<FetchResult> {
+        self.get_with(url, referer, &HeaderMap::new()).await
+    }
+
+    async fn get_with(
+        &self,
+        url: &Url,
+        referer: Option<&Url>,
+        headers: &HeaderMap,
+    ) -> Result<FetchResult> {
+        let mut request = self.client.get(url.clone()).headers(headers.clone());
+        if let Some(r) = referer {
+            request = request.header(REFERER, r.as_str());
+        }
+        request = request
+            .header("Upgrade-Insecure-Requests", "1")
+            .header("Sec-Fetch-Dest", "document")
+            .header("Sec-Fetch-Mode", "navigate")
+            .header("Sec-Fetch-Site", "same-origin")
+            .header(header::CONNECTION, "keep-alive");
+
+        let response = request.send().await.context("HTTP GET request failed")?;
+        let status = response.status();
+        let final_url = response.url().clone();
+        let headers = response.headers().clone();
+        let body = response
+            .text()
+            .await
+            .context("failed to read GET response body")?;
+
+        Ok(FetchResult {
+            body,
+            final_url,
+            status,
+            headers,
+            refreshed_from: Vec::new(),
+        })
+    }
+
+    async fn post_form(
+        &self,
+        url: &Url,
+        referer: Option<&Url>,
+        form: &[(String, String)],
+    ) -> Result<FetchResult> {
+        let mut pairs: Vec<(&str, &str)> = Vec::with_capacity(form.len());
+        for (k, v) in form {
+            pairs.push((k.as_str(), v.as_str()));
+        }
+
+        let mut request = self.client.post(url.clone()).form(&pairs);
+        if let Some(r) = referer {
+            request = request.header(REFERER, r.as_str());
+        }
+
+        if let Some(authority) = url.domain() {
+            request = request.header(ORIGIN, format!("https://{authority}"));
+        }
+
+        request = request
+            .header("Upgrade-Insecure-Requests", "1")
+            .header("Sec-Fetch-Dest", "document")
+            .header("Sec-Fetch-Mode", "navigate")
+            .header("Sec-Fetch-Site", "same-origin")
+            .header("Sec-Fetch-User", "?1")
+            .header(PRAGMA, "no-cache")
+            .header(CACHE_CONTROL, "no-cache");
+
+        let response = request.send().await.context("HTTP POST request failed")?;
+        let status = response.status();
+        let final_url = response.url().clone();
+        let headers = response.headers().clone();
+        let body = response
+            .text()
+            .await
+            .context("failed to read POST response body")?;
+
+        Ok(FetchResult {
+            body,
+            final_url,
+            status,
+            headers,
+            refreshed_from: Vec::new(),
+        })
+    }
+
+    fn add_cookie(&self, cookie: &str, url: &Url) {
+        self.jar.add_cookie_str(cookie, url);
+    }
+
+    fn cookies(&self, url: &Url) -> Vec<String> {
+        let Some(header) = self.jar.cookies(url) else {
+            return Vec::new();
+        };
+        header
+            .to_str()
+            .unwrap_or_default()
+            .split(';')
+            .map(str::trim)
+            .filter(|pair| !pair.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+
+    fn clear_cookies(&self) {
+        self.jar.clear();
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod mock {
+    use std::{sync::Mutex, time::Duration};
+
+    use super::*;
+
+    /// A posted form with its `"POST url"` request line.
+    pub(crate) type PostedForm = (String, Vec<(String, String)>);
+
+    /// Serves canned bodies for URLs containing a pattern and records every request.
+    #[derive(Default)]
+    pub(crate) struct MockTransport {
+        routes: Vec<(String, StatusCode, String)>,
+        failures: Mutex<Vec<Failure>>,
+        redirects: Vec<(String, Url)>,
+        requests: Mutex<Vec<String>>,
+        forms: Mutex<Vec<PostedForm>>,
+        request_headers: Mutex<Vec<HeaderMap>>,
+        delay: Duration,
+    }
+
+    /// A canned response for the first `times` requests matching `pattern`.
+    struct Failure {
+        pattern: String,
+        status: StatusCode,
+        headers: HeaderMap,
+        body: String,
+        times: u32,
+    }
+
+    impl MockTransport {
+        /// Adds a route matched against `"METHOD url"`; earlier routes win when several
+        /// patterns match.
+        pub(crate) fn route(self, pattern: &str, body: &str) -> Self {
+            self.route_status(pattern, StatusCode::OK, body)
+        }
+
+        pub(crate) fn route_status(
+            mut self,
+            pattern: &str,
+            status: StatusCode,
+            body: &str,
+        ) -> Self {
+            self.routes
+                .push((pattern.to_string(), status, body.to_string()));
+            self
+        }
+
+        /// Answers the first `times` requests matching `pattern` with an empty `status`
+        /// response before its routes apply.
+        pub(crate) fn failing(self, pattern: &str, status: StatusCode, times: u32) -> Self {
+            self.failing_with_headers(pattern, status, HeaderMap::new(), times)
+        }
+
+        /// Like [`failing`](Self::failing), with `headers` on the error responses.
+        pub(crate) fn failing_with_headers(
+            self,
+            pattern: &str,
+            status: StatusCode,
+            headers: HeaderMap,
+            times: u32,
+        ) -> Self {
+            self.failures.lock().unwrap().push(Failure {
+                pattern: pattern.to_string(),
+                status,
+                headers,
+                body: String::new(),
+                times,
+            });
+            self
+        }
+
+        /// Answers the first `times` requests matching `pattern` with `body` before its routes
+        /// apply, e.g. to hand out a key the portal rejects later.
+        pub(crate) fn answering_first(self, pattern: &str, body: &str, times: u32) -> Self {
+            self.failures.lock().unwrap().push(Failure {
+                pattern: pattern.to_string(),
+                status: StatusCode::OK,
+                headers: HeaderMap::new(),
+                body: body.to_string(),
+                times,
+            });
+            self
+        }
+
+        /// Answers requests matching `pattern` as if the server redirected them to `target`:
+        /// the body comes from the route for `GET target` and `final_url` is `target`.
+        pub(crate) fn redirect(mut self, pattern: &str, target: &str) -> Self {
+            let target = Url::parse(target).expect("mock redirect target must be a URL");
+            self.redirects.push((pattern.to_string(), target));
+            self
+        }
+
+        /// Waits for `delay` before answering each request, to simulate a slow portal.
+        pub(crate) fn with_delay(mut self, delay: Duration) -> Self {
+            self.delay = delay;
+            self
+        }
+
+        pub(crate) fn requests(&self) -> Vec<String> {
+            self.requests.lock().unwrap().clone()
+        }
+
+        /// Every form posted, oldest first.
+        pub(crate) fn posted_forms(&self) -> Vec<PostedForm> {
+            self.forms.lock().unwrap().clone()
+        }
+
+        /// The extra headers of every request, in the order of [`requests`](Self::requests).
+        pub(crate) fn request_headers(&self) -> Vec<HeaderMap> {
+            self.request_headers.lock().unwrap().clone()
+        }
+
+        async fn wait(&self) {
+            if !self.delay.is_zero() {
+                tokio::time::sleep(self.delay).await;
+            }
+        }
+
+        fn respond(&self, method: &str, url: &Url, headers: &HeaderMap) -> FetchResult {
+            let request = format!("{method} {url}");
+            let redirect = self
+                .redirects
+                .iter()
+                .find(|(pattern, _)| request.contains(pattern.as_str()))
+                .map(|(_, target)| target.clone());
+            let lookup = match &redirect {
+                Some(target) => format!("GET {target}"),
+                None => request.clone(),
+            };
+            let route = self
+                .routes
+                .iter()
+                .find(|(pattern, _, _)| lookup.contains(pattern.as_str()));
+            self.requests.lock().unwrap().push(request);
+            self.request_headers.lock().unwrap().push(headers.clone());
+            let mut failures = self.failures.lock().unwrap();
+            if let Some(failure) = failures
+                .iter_mut()
+                .find(|failure| failure.times > 0 && lookup.contains(failure.pattern.as_str()))
+            {
+                failure.times -= 1;
+                return FetchResult {
+                    body: failure.body.clone(),
+                    final_url: url.clone(),
+                    status: failure.status,
+                    headers: failure.headers.clone(),
+                    refreshed_from: Vec::new(),
+                };
+            }
+            FetchResult {
+                body: route.map(|(_, _, body)| body.clone()).unwrap_or_default(),
+                final_url: redirect.unwrap_or_else(|| url.clone()),
+                status: route.map_or(StatusCode::NOT_FOUND, |(_, status, _)| *status),
+                headers: HeaderMap::new(),
+                refreshed_from: Vec::new(),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl HttpTransport for MockTransport {
+        async fn get(&self, url: &Url, _referer: Option<&Url>) -> Result<FetchResult> {
+            Ok(self.respond("GET", url, &HeaderMap::new()))
+        }
+
+        async fn get_with(
+            &self,
+            url: &Url,
+            _referer: Option<&Url>,
+            headers: &HeaderMap,
+        ) -> Result<FetchResult> {
+            self.wait().await;
+            Ok(self.respond("GET", url, headers))
+        }
+
+        async fn post_form(
+            &self,
+            url: &Url,
+            _referer: Option<&Url>,
+            form: &[(String, String)],
+        ) -> Result<FetchResult> {
+            self.forms
+                .lock()
+                .unwrap()
+                .push((format!("POST {url}"), form.to_vec()));
+            self.wait().await;
+            Ok(self.respond("POST", url, &HeaderMap::new()))
+        }
+
+        fn add_cookie(&self, _cookie: &str, _url: &Url) {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cookie_jar_sends_path_scoped_cookies_and_clears() {
+        let jar = CookieJar::default();
+        let login = Url::parse("https://portal.test/qisserver/rds?state=user").unwrap();
+        jar.add_cookie_str("JSESSIONID=abc; Path=/qisserver", &login);
+        jar.add_cookie_str("lastRefresh=1; Path=/", &login);
+
+        let page = Url::parse("https://portal.test/qisserver/pages/start.faces").unwrap();
+        let sent = CookieStore::cookies(&jar, &page).unwrap();
+        let mut sent: Vec<&str> = sent.to_str().unwrap().split("; ").collect();
+        sent.sort();
+        assert_eq!(sent, ["JSESSIONID=abc", "lastRefresh=1"]);
+        let root = Url::parse("https://portal.test/").unwrap();
+        assert_eq!(
+            CookieStore::cookies(&jar, &root).unwrap().to_str().unwrap(),
+            "lastRefresh=1"
+        );
+
+        jar.clear();
+        assert!(CookieStore::cookies(&jar, &page).is_none());
+    }
+}
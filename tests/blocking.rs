@@ -0,0 +1,35 @@
+#![cfg(feature = "blocking")]
+
+use std::sync::Arc;
+
+use ur_connect::{
+    UrError,
+    blocking::UrConnect,
+    testing::{FIXTURE_PASSWORD, FIXTURE_USERNAME, FixturePortal},
+};
+
+#[test]
+fn blocking_client_logs_in_and_downloads_timetable() {
+    let portal = Arc::new(FixturePortal::new("https://portal.invalid"));
+    let client = UrConnect::from_async(
+        ur_connect::UrConnect::with_transport(portal.config(), portal.clone()).unwrap(),
+    )
+    .unwrap();
+
+    let err = client.get_timetable().unwrap_err();
+    assert!(matches!(err, UrError::SessionExpired), "{err}");
+
+    assert!(!client.is_logged_in().unwrap());
+    client.login(FIXTURE_USERNAME, FIXTURE_PASSWORD).unwrap();
+    assert!(client.is_logged_in().unwrap());
+    let entries = client.get_timetable().unwrap();
+    let async_entries = tokio::runtime::Runtime::new()
+        .unwrap()
+        .block_on(client.as_async().get_timetable())
+        .unwrap();
+    assert_eq!(entries.len(), 3);
+    assert_eq!(entries, async_entries);
+
+    client.logout().unwrap();
+    assert!(!portal.is_logged_in());
+}
@@ -0,0 +1,34 @@
+//! Runs in its own process so that setting environment variables cannot race with other
+//! tests reading them.
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use ur_connect::{
+    UrConnect, UrError,
+    testing::{FIXTURE_PASSWORD, FIXTURE_USERNAME, FixturePortal},
+};
+
+#[tokio::test]
+async fn logs_in_with_credentials_from_the_environment() -> Result<()> {
+    // SAFETY: this is the only test in the binary, so no other thread reads the environment
+    // while it is changed.
+    unsafe {
+        std::env::set_var("UR_CONNECT_OFFLINE_USER", FIXTURE_USERNAME);
+        std::env::set_var("UR_CONNECT_OFFLINE_PASSWORD", FIXTURE_PASSWORD);
+    }
+    let portal = Arc::new(FixturePortal::new("https://portal.invalid"));
+
+    let missing = UrConnect::with_transport(portal.config(), portal.clone())?
+        .login_from_env("UR_CONNECT_OFFLINE_USER", "UR_CONNECT_OFFLINE_UNSET")
+        .await;
+    assert!(matches!(missing, Err(UrError::MissingEnvVar { .. })));
+    assert!(portal.requests().is_empty());
+
+    let client = UrConnect::with_transport(portal.config(), portal.clone())?
+        .login_from_env("UR_CONNECT_OFFLINE_USER", "UR_CONNECT_OFFLINE_PASSWORD")
+        .await?;
+    assert!(portal.is_logged_in());
+    assert_eq!(client.get_timetable().await?.len(), 3);
+    Ok(())
+}
@@ -0,0 +1,302 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::Result;
+use futures_util::StreamExt;
+use ur_connect::{
+    Credentials, UrConnect, UrError,
+    testing::{FIXTURE_PASSWORD, FIXTURE_USERNAME, FixturePortal},
+};
+
+#[tokio::test]
+async fn logs_in_and_downloads_timetable_from_fixture_portal() -> Result<()> {
+    let portal = Arc::new(FixturePortal::new("https://portal.invalid"));
+    let client = UrConnect::with_transport(portal.config(), portal.clone())?;
+
+    client.login(FIXTURE_USERNAME, FIXTURE_PASSWORD).await?;
+    assert!(portal.is_logged_in());
+
+    let entries = client.get_timetable().await?;
+    assert_eq!(entries.len(), 3);
+    assert!(
+        entries
+            .iter()
+            .any(|entry| entry.title == "Lineare Algebra I")
+    );
+
+    let requests = portal.requests();
+    assert!(
+        requests
+            .iter()
+            .any(|r| r.contains("_flowExecutionKey=e1s1"))
+    );
+    assert!(
+        requests
+            .last()
+            .is_some_and(|r| r.contains("individualTimetableCalendarExport"))
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn downloads_the_calendar_export_verbatim() -> Result<()> {
+    let portal = Arc::new(FixturePortal::new("https://portal.invalid"));
+    let client = UrConnect::with_transport(portal.config(), portal.clone())?;
+    client.login(FIXTURE_USERNAME, FIXTURE_PASSWORD).await?;
+
+    let ics = client.get_raw_ics().await?;
+    assert_eq!(ics, include_str!("fixtures/timetable.ics"));
+    assert_eq!(client.download_ics().await?, ics);
+    Ok(())
+}
+
+#[tokio::test]
+async fn streams_the_entries_get_timetable_returns() -> Result<()> {
+    let portal = Arc::new(FixturePortal::new("https://portal.invalid"));
+    let client = UrConnect::with_transport(portal.config(), portal.clone())?;
+
+    let before_login: Vec<_> = client.stream_timetable().collect().await;
+    assert!(
+        matches!(before_login.as_slice(), [Err(UrError::SessionExpired)]),
+        "{before_login:?}"
+    );
+
+    client.login(FIXTURE_USERNAME, FIXTURE_PASSWORD).await?;
+    let streamed = client
+        .stream_timetable()
+        .collect::<Vec<_>>()
+        .await
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(streamed.len(), 3);
+    assert_eq!(streamed, client.get_timetable().await?);
+    Ok(())
+}
+
+#[tokio::test]
+async fn reports_where_the_entries_came_from() -> Result<()> {
+    let portal = Arc::new(FixturePortal::new("https://portal.invalid"));
+    let client = UrConnect::with_transport(portal.config(), portal.clone())?;
+    client.login(FIXTURE_USERNAME, FIXTURE_PASSWORD).await?;
+
+    let result = client.get_timetable_detailed().await?;
+    assert_eq!(result.entries, client.get_timetable().await?);
+    assert!(
+        result
+            .ics_url
+            .is_some_and(|url| url.path().contains("individualTimetableCalendarExport"))
+    );
+    assert_eq!(result.calendar_name.as_deref(), Some("Stundenplan"));
+    assert_eq!(
+        result.product_id.as_deref(),
+        Some("-//HISinOne//Stundenplan//DE")
+    );
+    assert_eq!(result.skipped_events, 0);
+    Ok(())
+}
+
+#[tokio::test]
+async fn refreshes_a_live_session_and_reports_an_ended_one() -> Result<()> {
+    let portal = Arc::new(FixturePortal::new("https://portal.invalid"));
+    let client = UrConnect::with_transport(portal.config(), portal.clone())?;
+    client.login(FIXTURE_USERNAME, FIXTURE_PASSWORD).await?;
+
+    let before = portal.requests().len();
+    client.refresh_session().await?;
+    assert_eq!(portal.requests().len(), before + 1);
+
+    portal.expire_session();
+    let err = client.refresh_session().await.unwrap_err();
+    assert!(matches!(err, UrError::SessionExpired), "{err}");
+    Ok(())
+}
+
+#[tokio::test]
+async fn keeps_the_session_alive_until_the_handle_is_dropped() -> Result<()> {
+    let portal = Arc::new(FixturePortal::new("https://portal.invalid"));
+    let client = Arc::new(UrConnect::with_transport(portal.config(), portal.clone())?);
+    client.login(FIXTURE_USERNAME, FIXTURE_PASSWORD).await?;
+
+    let before = portal.requests().len();
+    let keepalive = client.spawn_keepalive(Duration::from_millis(10));
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let refreshes = portal.requests().len() - before;
+    assert!(refreshes >= 2, "{refreshes} refreshes");
+    assert!(!keepalive.is_finished());
+
+    drop(keepalive);
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    let stopped_at = portal.requests().len();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(portal.requests().len(), stopped_at);
+
+    let keepalive = client.spawn_keepalive(Duration::from_millis(10));
+    portal.expire_session();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(keepalive.is_finished());
+    Ok(())
+}
+
+#[tokio::test]
+async fn finds_the_export_link_without_downloading_it() -> Result<()> {
+    let portal = Arc::new(FixturePortal::new("https://portal.invalid"));
+    let client = UrConnect::with_transport(portal.config(), portal.clone())?;
+    client.login(FIXTURE_USERNAME, FIXTURE_PASSWORD).await?;
+
+    let url = client.get_ics_url().await?;
+    assert!(
+        url.path().contains("individualTimetableCalendarExport"),
+        "{url}"
+    );
+    assert!(
+        !portal
+            .requests()
+            .iter()
+            .any(|r| r.contains("individualTimetableCalendarExport"))
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn rejects_wrong_password() -> Result<()> {
+    let portal = Arc::new(FixturePortal::new("https://portal.invalid"));
+    let client = UrConnect::with_transport(portal.config(), portal.clone())?;
+
+    let credentials = Credentials::new(FIXTURE_USERNAME, "not-the-password");
+    let err = client
+        .login_with_credentials(&credentials)
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(&err, UrError::InvalidCredentials { message: Some(message) }
+            if message.contains("Anmeldung ist fehlgeschlagen")),
+        "{err}"
+    );
+    assert!(!format!("{err} {err:?} {credentials:?}").contains("not-the-password"));
+    assert!(!portal.is_logged_in());
+    Ok(())
+}
+
+#[tokio::test]
+async fn reports_expired_session_instead_of_parse_error() -> Result<()> {
+    let portal = Arc::new(FixturePortal::new("https://portal.invalid"));
+    let client = UrConnect::with_transport(portal.config(), portal.clone())?;
+
+    let err = client.get_timetable().await.unwrap_err();
+    assert!(matches!(err, UrError::SessionExpired), "{err}");
+    Ok(())
+}
+
+#[tokio::test]
+async fn reports_whether_the_session_is_logged_in() -> Result<()> {
+    let portal = Arc::new(FixturePortal::new("https://portal.invalid"));
+    let client = UrConnect::with_transport(portal.config(), portal.clone())?;
+
+    assert!(!client.is_logged_in().await?);
+    client.login(FIXTURE_USERNAME, FIXTURE_PASSWORD).await?;
+    assert!(client.is_logged_in().await?);
+    client.logout().await?;
+    assert!(!client.is_logged_in().await?);
+    Ok(())
+}
+
+#[tokio::test]
+async fn logout_forgets_remembered_credentials() -> Result<()> {
+    let portal = Arc::new(FixturePortal::new("https://portal.invalid"));
+    let client = UrConnect::with_transport(portal.config(), portal.clone())?
+        .with_credentials(FIXTURE_USERNAME, FIXTURE_PASSWORD);
+    assert_eq!(client.get_timetable().await?.len(), 3);
+
+    client.logout().await?;
+    let after_logout = portal.requests().len();
+    let err = client.get_timetable().await.unwrap_err();
+    assert!(matches!(err, UrError::SessionExpired), "{err}");
+    assert!(
+        portal.requests()[after_logout..]
+            .iter()
+            .all(|r| !(r.starts_with("POST") && r.contains("category=auth.login")))
+    );
+    assert!(!portal.is_logged_in());
+    Ok(())
+}
+
+#[tokio::test]
+async fn logout_ends_the_session() -> Result<()> {
+    let portal = Arc::new(FixturePortal::new("https://portal.invalid"));
+    let client = UrConnect::with_transport(portal.config(), portal.clone())?;
+    client.login(FIXTURE_USERNAME, FIXTURE_PASSWORD).await?;
+
+    assert!(client.is_session_valid().await?);
+    client.logout().await?;
+    assert!(!portal.is_logged_in());
+    assert!(
+        portal
+            .requests()
+            .last()
+            .is_some_and(|r| r.contains("category=auth.logout"))
+    );
+    assert!(!client.is_session_valid().await?);
+
+    let err = client.get_timetable().await.unwrap_err();
+    assert!(matches!(err, UrError::SessionExpired), "{err}");
+    Ok(())
+}
+
+#[tokio::test]
+async fn logout_without_a_session_is_harmless() -> Result<()> {
+    let portal = Arc::new(FixturePortal::new("https://portal.invalid"));
+    let client = UrConnect::with_transport(portal.config(), portal.clone())?;
+
+    client.logout().await?;
+    client.logout().await?;
+    assert!(!portal.is_logged_in());
+
+    client.login(FIXTURE_USERNAME, FIXTURE_PASSWORD).await?;
+    assert_eq!(client.get_timetable().await?.len(), 3);
+    Ok(())
+}
+
+#[tokio::test]
+async fn logs_in_again_once_when_the_session_expires() -> Result<()> {
+    let portal = Arc::new(FixturePortal::new("https://portal.invalid"));
+    let client = UrConnect::with_transport(portal.config(), portal.clone())?
+        .login_and_remember(FIXTURE_USERNAME, FIXTURE_PASSWORD)
+        .await?;
+
+    portal.expire_session();
+    assert_eq!(client.get_timetable().await?.len(), 3);
+    assert!(portal.is_logged_in());
+
+    let stale = UrConnect::with_transport(portal.config(), portal.clone())?
+        .with_credentials(FIXTURE_USERNAME, "old password");
+    portal.expire_session();
+    let before = portal.requests().len();
+    let err = stale.get_timetable().await.unwrap_err();
+    assert!(matches!(err, UrError::InvalidCredentials { .. }), "{err}");
+    let logins = portal.requests()[before..]
+        .iter()
+        .filter(|r| r.contains("category=auth.login"))
+        .count();
+    assert_eq!(logins, 1);
+    Ok(())
+}
+
+#[tokio::test]
+async fn resumes_with_known_flow_key() -> Result<()> {
+    let portal = Arc::new(FixturePortal::new("https://portal.invalid"));
+    let client = UrConnect::with_transport(portal.config(), portal.clone())?;
+    client.login(FIXTURE_USERNAME, FIXTURE_PASSWORD).await?;
+    let after_login = portal.requests().len();
+
+    let entries = client.get_timetable_with_flow_key("e1s1").await?;
+    assert_eq!(entries.len(), 3);
+
+    let requests = &portal.requests()[after_login..];
+    assert!(requests.iter().all(|r| !r.contains("hisinoneStartPage")));
+    assert!(
+        requests
+            .iter()
+            .filter(|r| r.contains("individualTimetable.xhtml"))
+            .all(|r| r.contains("_flowExecutionKey=e1s1"))
+    );
+    Ok(())
+}
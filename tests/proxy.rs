@@ -0,0 +1,80 @@
+use std::sync::{Arc, Mutex};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+};
+use ur_connect::{RetryPolicy, UrConnect, UrError};
+
+/// Starts a proxy on localhost that serves an empty page to requests carrying
+/// `Proxy-Authorization: Basic user:pass` and answers everything else with 407. Returns its
+/// URL and the request heads it received.
+async fn start_proxy() -> (String, Arc<Mutex<Vec<String>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let log = seen.clone();
+    tokio::spawn(async move {
+        loop {
+            let Ok((mut stream, _)) = listener.accept().await else {
+                return;
+            };
+            let mut head = Vec::new();
+            let mut buf = [0; 1024];
+            while !head.ends_with(b"\r\n\r\n") {
+                match stream.read(&mut buf).await {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => head.extend_from_slice(&buf[..n]),
+                }
+            }
+            let head = String::from_utf8_lossy(&head).to_ascii_lowercase();
+            let response = if head.contains("proxy-authorization: basic dxnlcjpwyxnz") {
+                "HTTP/1.1 200 OK\r\nContent-Length: 13\r\nConnection: close\r\n\r\n<html></html>"
+            } else {
+                "HTTP/1.1 407 Proxy Authentication Required\r\nProxy-Authenticate: Basic\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+            };
+            log.lock().unwrap().push(head);
+            let _ = stream.write_all(response.as_bytes()).await;
+        }
+    });
+    (url, seen)
+}
+
+fn client(base_url: &str, proxy: &str, auth: Option<(&str, &str)>) -> UrConnect {
+    let mut builder = UrConnect::builder()
+        .base_url(base_url)
+        .proxy(proxy)
+        .retry(RetryPolicy::never());
+    if let Some((username, password)) = auth {
+        builder = builder.proxy_auth(username, password);
+    }
+    builder.build().unwrap()
+}
+
+#[tokio::test]
+async fn routes_requests_through_the_proxy_with_basic_auth() {
+    let (proxy, seen) = start_proxy().await;
+    let client = client("http://portal.test", &proxy, Some(("user", "pass")));
+
+    assert!(!client.is_session_valid().await.unwrap());
+    let seen = seen.lock().unwrap();
+    assert!(
+        seen[0].starts_with("get http://portal.test/qisserver/"),
+        "{seen:?}"
+    );
+}
+
+#[tokio::test]
+async fn reports_rejected_proxy_credentials_distinctly() {
+    let (proxy, _) = start_proxy().await;
+
+    let plain = client("http://portal.test", &proxy, Some(("user", "wrong")));
+    let err = plain.is_session_valid().await.unwrap_err();
+    assert!(matches!(err, UrError::ProxyAuthRequired), "{err:?}");
+
+    // HTTPS goes through a CONNECT tunnel, which the proxy refuses the same way.
+    let tunnelled = client("https://portal.test", &proxy, None);
+    let err = tunnelled.is_session_valid().await.unwrap_err();
+    assert!(matches!(err, UrError::ProxyAuthRequired), "{err:?}");
+    assert!(!err.is_transient());
+}
@@ -1,18 +1,18 @@
 use anyhow::Result;
-use ur_connect::UrConnect;
+use ur_connect::{PASSWORD_ENV_VAR, USER_ENV_VAR, UrConnect, UrError};
 
 #[tokio::test(flavor = "multi_thread")]
 async fn downloads_and_prints_timetable() -> Result<()> {
-    let username = std::env::var("UR_USER").unwrap_or_default();
-    let password = std::env::var("UR_PASSWORD").unwrap_or_default();
-
-    if username.is_empty() || password.is_empty() {
-        println!("Skipping test: set UR_USER and UR_PASSWORD to run.");
-        return Ok(());
-    }
-
-    let client = UrConnect::new()?;
-    client.login(username.as_str(), password.as_str()).await?;
+    let client = match UrConnect::new()?
+        .login_from_env(USER_ENV_VAR, PASSWORD_ENV_VAR)
+        .await
+    {
+        Err(UrError::MissingEnvVar { .. }) => {
+            println!("Skipping test: set UR_USER and UR_PASSWORD to run.");
+            return Ok(());
+        }
+        result => result?,
+    };
     let entries = client.get_timetable().await?;
 
     let formatted = UrConnect::format_entries(&entries);
@@ -0,0 +1,98 @@
+use std::sync::Arc;
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+};
+use tokio_rustls::{
+    TlsAcceptor,
+    rustls::{
+        ServerConfig,
+        pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject},
+    },
+};
+use ur_connect::{RetryPolicy, UrConnect, UrConnectBuilder, UrError};
+
+const CERT: &[u8] = include_bytes!("fixtures/tls/localhost.crt");
+const KEY: &[u8] = include_bytes!("fixtures/tls/localhost.key");
+
+/// Starts an HTTPS server for `localhost` with a self-signed certificate that answers every
+/// request with an empty page, and returns its origin.
+async fn start_server() -> String {
+    let config = ServerConfig::builder_with_provider(Arc::new(
+        tokio_rustls::rustls::crypto::ring::default_provider(),
+    ))
+    .with_safe_default_protocol_versions()
+    .unwrap()
+    .with_no_client_auth()
+    .with_single_cert(
+        vec![CertificateDer::from_pem_slice(CERT).unwrap()],
+        PrivateKeyDer::from_pem_slice(KEY).unwrap(),
+    )
+    .unwrap();
+    let acceptor = TlsAcceptor::from(Arc::new(config));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let origin = format!(
+        "https://localhost:{}",
+        listener.local_addr().unwrap().port()
+    );
+    tokio::spawn(async move {
+        loop {
+            let Ok((stream, _)) = listener.accept().await else {
+                return;
+            };
+            let acceptor = acceptor.clone();
+            tokio::spawn(async move {
+                let Ok(mut stream) = acceptor.accept(stream).await else {
+                    return;
+                };
+                let mut head = Vec::new();
+                let mut buf = [0; 1024];
+                while !head.ends_with(b"\r\n\r\n") {
+                    match stream.read(&mut buf).await {
+                        Ok(0) | Err(_) => return,
+                        Ok(n) => head.extend_from_slice(&buf[..n]),
+                    }
+                }
+                let response = "HTTP/1.1 200 OK\r\nContent-Length: 13\r\nConnection: close\r\n\r\n<html></html>";
+                let _ = stream.write_all(response.as_bytes()).await;
+                let _ = stream.shutdown().await;
+            });
+        }
+    });
+    origin
+}
+
+fn builder(origin: &str) -> UrConnectBuilder {
+    UrConnect::builder()
+        .base_url(origin)
+        .env_proxy(false)
+        .retry(RetryPolicy::never())
+}
+
+#[tokio::test]
+async fn trusts_an_added_root_certificate() {
+    let origin = start_server().await;
+
+    let untrusted = builder(&origin).build().unwrap();
+    let err = untrusted.is_session_valid().await.unwrap_err();
+    assert!(matches!(err, UrError::Network { .. }), "{err:?}");
+
+    let trusting = builder(&origin).add_root_certificate(CERT).build().unwrap();
+    assert!(!trusting.is_session_valid().await.unwrap());
+}
+
+#[tokio::test]
+async fn accepts_any_certificate_only_when_asked() {
+    let origin = start_server().await;
+    let lab = builder(&origin)
+        .danger_accept_invalid_certs()
+        .build()
+        .unwrap();
+    assert!(!lab.is_session_valid().await.unwrap());
+
+    let garbage = builder(&origin)
+        .add_root_certificate(b"not a certificate")
+        .build();
+    assert!(matches!(garbage, Err(UrError::Config(_))));
+}
@@ -0,0 +1,92 @@
+use std::{
+    fmt,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use tracing::{
+    Event, Metadata, Subscriber,
+    field::{Field, Visit},
+    span::{Attributes, Id, Record},
+};
+use ur_connect::{
+    UrConnect,
+    testing::{FIXTURE_PASSWORD, FIXTURE_USERNAME, FixturePortal},
+};
+
+/// Records every span and event, with its fields, as one line of text.
+#[derive(Clone, Default)]
+struct Recorder {
+    lines: Arc<Mutex<Vec<String>>>,
+    next_id: Arc<AtomicU64>,
+}
+
+struct Line<'a>(&'a mut String);
+
+impl Visit for Line<'_> {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0.push_str(&format!(" {}={:?}", field.name(), value));
+    }
+}
+
+impl Subscriber for Recorder {
+    fn enabled(&self, _: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, span: &Attributes<'_>) -> Id {
+        let mut line = format!("span {}", span.metadata().name());
+        span.record(&mut Line(&mut line));
+        self.lines.lock().unwrap().push(line);
+        Id::from_u64(self.next_id.fetch_add(1, Ordering::SeqCst) + 1)
+    }
+
+    fn record(&self, _: &Id, values: &Record<'_>) {
+        let mut line = "record".to_string();
+        values.record(&mut Line(&mut line));
+        self.lines.lock().unwrap().push(line);
+    }
+
+    fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut line = "event".to_string();
+        event.record(&mut Line(&mut line));
+        self.lines.lock().unwrap().push(line);
+    }
+
+    fn enter(&self, _: &Id) {}
+
+    fn exit(&self, _: &Id) {}
+}
+
+#[tokio::test]
+async fn traces_the_navigation_without_credentials() {
+    let recorder = Recorder::default();
+    let _guard = tracing::subscriber::set_default(recorder.clone());
+
+    let portal = Arc::new(FixturePortal::new("https://portal.invalid"));
+    let client = UrConnect::with_transport(portal.config(), portal.clone()).unwrap();
+    client
+        .login(FIXTURE_USERNAME, FIXTURE_PASSWORD)
+        .await
+        .unwrap();
+    client.get_timetable().await.unwrap();
+
+    let lines = recorder.lines.lock().unwrap().join("\n");
+    for expected in [
+        "span login",
+        "span get_timetable",
+        "span post url=https://portal.invalid/qisserver/rds",
+        "link_source=\"menu link\"",
+        "key_source=",
+        "found_on=\"full timetable page\"",
+        "body_len=",
+    ] {
+        assert!(lines.contains(expected), "{expected} missing from\n{lines}");
+    }
+    assert!(!lines.contains(FIXTURE_PASSWORD));
+    assert!(!lines.contains(FIXTURE_USERNAME));
+}
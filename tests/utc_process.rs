@@ -0,0 +1,19 @@
+//! Runs in its own process so that setting `TZ` cannot affect other tests.
+
+use chrono::{Local, Timelike};
+use ur_connect::parsing::ics::parse_ics;
+
+#[test]
+fn berlin_lecture_keeps_its_time_on_a_utc_machine() {
+    // SAFETY: this is the only test in the binary and runs before anything reads `TZ`.
+    unsafe { std::env::set_var("TZ", "UTC") };
+
+    let ics = "BEGIN:VCALENDAR\nBEGIN:VEVENT\nSUMMARY:Analysis\nDTSTART;TZID=Europe/Berlin:20250115T080000\nDTEND;TZID=Europe/Berlin:20250115T100000\nEND:VEVENT\nEND:VCALENDAR";
+    let entry = &parse_ics(ics)[0];
+
+    let start = entry.start.unwrap();
+    assert_eq!(start.offset().local_minus_utc(), 0, "{start}");
+    assert_eq!(start.with_timezone(&Local).hour(), 7);
+    assert_eq!(entry.time(), "07:00 - 09:00");
+    assert_eq!(entry.start_in_source_zone().unwrap().hour(), 8);
+}